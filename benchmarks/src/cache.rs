@@ -0,0 +1,58 @@
+//! False sharing benchmarks. cache-thrash exposes allocators that hand out
+//! objects on the same cache line to different threads (active false
+//! sharing); cache-scratch exposes allocators that reuse a freed object on the
+//! thread that freed it, even though it shares a line with objects owned by
+//! other threads (passive false sharing).
+
+use std::hint::black_box;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::{run_threads, BenchConfig, Reporter};
+
+const OBJECT_SIZE: usize = 8;
+const WRITES_PER_OBJECT: usize = 1000;
+
+fn scribble(object: &mut Box<[u8; OBJECT_SIZE]>) {
+    for i in 0..WRITES_PER_OBJECT {
+        object[i % OBJECT_SIZE] = object[i % OBJECT_SIZE].wrapping_add(1);
+        black_box(&mut *object);
+    }
+}
+
+fn alloc_write_free(thread_id: usize, per_thread: usize, metric: &str, reporter: &dyn Reporter) {
+    let iterations = (per_thread / WRITES_PER_OBJECT).max(1);
+    let t = Instant::now();
+    for _ in 0..iterations {
+        let mut object = Box::new([0u8; OBJECT_SIZE]);
+        scribble(&mut object);
+        drop(black_box(object));
+    }
+    reporter.report(
+        thread_id,
+        metric,
+        iterations * WRITES_PER_OBJECT,
+        t.elapsed(),
+    );
+}
+
+pub fn cache_thrash(config: &BenchConfig, reporter: &dyn Reporter) {
+    let per_thread = config.per_thread();
+    run_threads(config, |thread_id| {
+        alloc_write_free(thread_id, per_thread, "cache_thrash", reporter)
+    });
+}
+
+pub fn cache_scratch(config: &BenchConfig, reporter: &dyn Reporter) {
+    let per_thread = config.per_thread();
+    // Allocated back to back by one thread, so they likely share cache lines.
+    let initial = (0..config.nthreads)
+        .map(|_| Mutex::new(Some(Box::new([0u8; OBJECT_SIZE]))))
+        .collect::<Vec<_>>();
+    run_threads(config, |thread_id| {
+        let mut object = initial[thread_id].lock().unwrap().take().unwrap();
+        scribble(&mut object);
+        drop(object);
+        alloc_write_free(thread_id, per_thread, "cache_scratch", reporter)
+    });
+}
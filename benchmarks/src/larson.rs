@@ -0,0 +1,49 @@
+//! larson: a server simulation where each thread replaces random blocks in a
+//! working set. Between rounds the working sets rotate to the next thread, so
+//! most frees release memory allocated by another thread.
+
+use std::hint::black_box;
+use std::sync::{Barrier, Mutex};
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{run_threads, BenchConfig, Reporter};
+
+const ROUNDS: usize = 10;
+const SLOTS_PER_THREAD: usize = 1000;
+const MIN_SIZE: usize = 8;
+const MAX_SIZE: usize = 256;
+
+pub fn run(config: &BenchConfig, reporter: &dyn Reporter) {
+    let per_round = (config.per_thread() / ROUNDS).max(1);
+    let mut rng = StdRng::seed_from_u64(12345);
+    let working_sets = (0..config.nthreads)
+        .map(|_| {
+            Mutex::new(
+                (0..SLOTS_PER_THREAD)
+                    .map(|_| vec![0u8; rng.gen_range(MIN_SIZE..=MAX_SIZE)])
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let barrier = Barrier::new(config.nthreads);
+
+    run_threads(config, |thread_id| {
+        let mut rng = StdRng::seed_from_u64((12345 + thread_id) as u64);
+        let t = Instant::now();
+        for round in 0..ROUNDS {
+            let mut slots = working_sets[(thread_id + round) % config.nthreads]
+                .lock()
+                .unwrap();
+            for _ in 0..per_round {
+                let slot = rng.gen_range(0..SLOTS_PER_THREAD);
+                slots[slot] = black_box(vec![0u8; rng.gen_range(MIN_SIZE..=MAX_SIZE)]);
+            }
+            drop(slots);
+            barrier.wait();
+        }
+        reporter.report(thread_id, "larson", ROUNDS * per_round, t.elapsed());
+    });
+}
@@ -1,14 +1,59 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+//! Allocator stress benchmarks, run against whatever `#[global_allocator]`
+//! the binary linking this crate selects.
+
+use std::thread;
+use std::time::Duration;
+
+use core_affinity::get_core_ids;
+
+pub mod cache;
+pub mod larson;
+pub mod shbench;
+pub mod threadtest;
+
+/// Receives per-thread results, in the same shape as benchrunner reports.
+pub trait Reporter: Sync {
+    fn report(&self, thread_id: usize, metric: &str, num: usize, cost: Duration);
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub struct BenchConfig {
+    pub nthreads: usize,
+    /// Total number of operations, split evenly across threads.
+    pub size: usize,
+    pub pin: bool,
+}
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+impl BenchConfig {
+    pub fn per_thread(&self) -> usize {
+        self.size / self.nthreads
     }
 }
+
+pub type Benchmark = fn(&BenchConfig, &dyn Reporter);
+
+pub fn find(name: &str) -> Benchmark {
+    match name {
+        "threadtest" => threadtest::run,
+        "larson" => larson::run,
+        "cache_thrash" => cache::cache_thrash,
+        "cache_scratch" => cache::cache_scratch,
+        "shbench" => shbench::run,
+        _ => panic!("unknown benchmark: {}", name),
+    }
+}
+
+/// Runs `f(thread_id)` on `nthreads` threads, pinned to cores if requested.
+pub(crate) fn run_threads<F: Fn(usize) + Sync>(config: &BenchConfig, f: F) {
+    let core_ids = get_core_ids().unwrap()[..config.nthreads].to_vec();
+    thread::scope(|s| {
+        for (thread_id, core_id) in core_ids.into_iter().enumerate() {
+            let f = &f;
+            s.spawn(move || {
+                if config.pin && !core_affinity::set_for_current(core_id) {
+                    panic!("Failed to set core affinity");
+                }
+                f(thread_id);
+            });
+        }
+    });
+}
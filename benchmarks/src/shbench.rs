@@ -0,0 +1,41 @@
+//! shbench: mixed object sizes skewed towards small ones, freed in an order
+//! different from the allocation order.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{run_threads, BenchConfig, Reporter};
+
+const BATCH: usize = 1000;
+const MAX_SIZE: usize = 1000;
+
+/// Roughly 1/size distributed: most requests are small, a few are large.
+fn object_size(rng: &mut StdRng) -> usize {
+    let r = rng.gen::<f64>();
+    ((MAX_SIZE as f64).powf(r) as usize).max(1)
+}
+
+pub fn run(config: &BenchConfig, reporter: &dyn Reporter) {
+    let batches = (config.per_thread() / BATCH).max(1);
+    run_threads(config, |thread_id| {
+        let mut rng = StdRng::seed_from_u64((12345 + thread_id) as u64);
+        let mut objects: Vec<Option<Vec<u8>>> = Vec::with_capacity(BATCH);
+        let t = Instant::now();
+        for _ in 0..batches {
+            for _ in 0..BATCH {
+                objects.push(Some(black_box(vec![0u8; object_size(&mut rng)])));
+            }
+            // Free every other object first, then the rest in reverse.
+            for object in objects.iter_mut().step_by(2) {
+                drop(object.take());
+            }
+            while let Some(object) = objects.pop() {
+                drop(object);
+            }
+        }
+        reporter.report(thread_id, "shbench", batches * BATCH, t.elapsed());
+    });
+}
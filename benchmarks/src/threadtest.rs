@@ -0,0 +1,25 @@
+//! threadtest: every thread repeatedly allocates a batch of small objects and
+//! frees all of them, with no sharing between threads.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use crate::{run_threads, BenchConfig, Reporter};
+
+const ROUNDS: usize = 50;
+const OBJECT_SIZE: usize = 8;
+
+pub fn run(config: &BenchConfig, reporter: &dyn Reporter) {
+    let per_round = (config.per_thread() / ROUNDS).max(1);
+    run_threads(config, |thread_id| {
+        let mut objects = Vec::with_capacity(per_round);
+        let t = Instant::now();
+        for _ in 0..ROUNDS {
+            for _ in 0..per_round {
+                objects.push(black_box(Box::new([0u8; OBJECT_SIZE])));
+            }
+            objects.clear();
+        }
+        reporter.report(thread_id, "threadtest", ROUNDS * per_round, t.elapsed());
+    });
+}
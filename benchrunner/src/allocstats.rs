@@ -0,0 +1,182 @@
+use itertools::Itertools;
+
+/// Allocator-specific counters, sampled by the harness after each phase.
+pub trait AllocatorStats {
+    /// Current counters as `(field, bytes)` pairs, in a stable order. Empty
+    /// if the allocator could not report them.
+    fn sample() -> Vec<(&'static str, usize)>;
+}
+
+#[cfg(feature = "jemalloc")]
+pub struct JemallocStats;
+
+#[cfg(feature = "jemalloc")]
+impl AllocatorStats for JemallocStats {
+    fn sample() -> Vec<(&'static str, usize)> {
+        use tikv_jemalloc_ctl::{epoch, stats};
+
+        // jemalloc caches its statistics, advancing the epoch refreshes them.
+        // Stale counters would be misleading, so skip the sample instead.
+        if epoch::advance().is_err() {
+            return Vec::new();
+        }
+        vec![
+            ("allocated", stats::allocated::read().unwrap_or(0)),
+            ("active", stats::active::read().unwrap_or(0)),
+            ("resident", stats::resident::read().unwrap_or(0)),
+            ("mapped", stats::mapped::read().unwrap_or(0)),
+            ("metadata", stats::metadata::read().unwrap_or(0)),
+        ]
+    }
+}
+
+#[cfg(feature = "tcmalloc")]
+pub struct TcmallocStats;
+
+#[cfg(feature = "tcmalloc")]
+impl AllocatorStats for TcmallocStats {
+    fn sample() -> Vec<(&'static str, usize)> {
+        use std::ffi::{c_char, c_int, CStr};
+
+        extern "C" {
+            fn MallocExtension_GetNumericProperty(
+                property: *const c_char,
+                value: *mut usize,
+            ) -> c_int;
+        }
+
+        fn property(name: &CStr) -> usize {
+            let mut value = 0;
+            unsafe {
+                if MallocExtension_GetNumericProperty(name.as_ptr(), &mut value) == 0 {
+                    return 0;
+                }
+            }
+            value
+        }
+
+        vec![
+            ("allocated", property(c"generic.current_allocated_bytes")),
+            ("heap_size", property(c"generic.heap_size")),
+            ("pageheap_free", property(c"tcmalloc.pageheap_free_bytes")),
+            (
+                "pageheap_unmapped",
+                property(c"tcmalloc.pageheap_unmapped_bytes"),
+            ),
+            (
+                "central_cache_free",
+                property(c"tcmalloc.central_cache_free_bytes"),
+            ),
+            (
+                "thread_cache_free",
+                property(c"tcmalloc.current_total_thread_cache_bytes"),
+            ),
+        ]
+    }
+}
+
+#[cfg(feature = "hoard")]
+pub struct HoardStats;
+
+#[cfg(feature = "hoard")]
+impl AllocatorStats for HoardStats {
+    fn sample() -> Vec<(&'static str, usize)> {
+        let stats = hoard_allocator::stats();
+        vec![
+            ("allocated", stats.live_bytes()),
+            ("live_allocations", stats.live_allocations()),
+            ("total_allocated", stats.allocated_bytes),
+            ("total_freed", stats.freed_bytes),
+        ]
+    }
+}
+
+#[cfg(not(any(feature = "jemalloc", feature = "tcmalloc", feature = "hoard")))]
+pub struct GlibcStats;
+
+#[cfg(not(any(feature = "jemalloc", feature = "tcmalloc", feature = "hoard")))]
+impl AllocatorStats for GlibcStats {
+    fn sample() -> Vec<(&'static str, usize)> {
+        #[allow(dead_code)]
+        #[repr(C)]
+        struct Mallinfo2 {
+            arena: usize,
+            ordblks: usize,
+            smblks: usize,
+            hblks: usize,
+            hblkhd: usize,
+            usmblks: usize,
+            fsmblks: usize,
+            uordblks: usize,
+            fordblks: usize,
+            keepcost: usize,
+        }
+
+        extern "C" {
+            fn mallinfo2() -> Mallinfo2;
+        }
+
+        let info = unsafe { mallinfo2() };
+        vec![
+            ("allocated", info.uordblks + info.hblkhd),
+            ("arena", info.arena),
+            ("mmapped", info.hblkhd),
+            ("free", info.fordblks),
+        ]
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+pub type CurrentAllocator = JemallocStats;
+#[cfg(feature = "tcmalloc")]
+pub type CurrentAllocator = TcmallocStats;
+#[cfg(feature = "hoard")]
+pub type CurrentAllocator = HoardStats;
+#[cfg(not(any(feature = "jemalloc", feature = "tcmalloc", feature = "hoard")))]
+pub type CurrentAllocator = GlibcStats;
+
+/// Resident set size of the process, from `/proc/self/statm`.
+pub fn resident_set_size() -> usize {
+    std::fs::read_to_string("/proc/self/statm")
+        .ok()
+        .and_then(|statm| statm.split_whitespace().nth(1)?.parse::<usize>().ok())
+        .map_or(0, |pages| pages * page_size())
+}
+
+/// The kernel page size `statm` counts in, which is not 4 KiB everywhere.
+fn page_size() -> usize {
+    use std::ffi::{c_int, c_long};
+
+    extern "C" {
+        fn sysconf(name: c_int) -> c_long;
+    }
+    // `_SC_PAGESIZE` on Linux.
+    const SC_PAGESIZE: c_int = 30;
+
+    match unsafe { sysconf(SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+/// Bytes held by live allocations, as reported by the selected allocator.
+pub fn live_bytes() -> usize {
+    CurrentAllocator::sample()
+        .into_iter()
+        .find(|(field, _)| *field == "allocated")
+        .map_or(0, |(_, value)| value)
+}
+
+/// Samples the selected allocator and the process RSS.
+pub fn sample() -> Vec<(&'static str, usize)> {
+    let mut fields = CurrentAllocator::sample();
+    fields.push(("rss", resident_set_size()));
+    fields
+}
+
+pub fn format_sample(fields: &[(&'static str, usize)]) -> String {
+    fields
+        .iter()
+        .map(|(field, value)| format!("{}: {}", field, value))
+        .join(", ")
+}
@@ -1,9 +1,17 @@
 #![feature(trait_alias, generic_const_exprs)]
 
-use crate::{testcases::Testcases, testrunner::multithread_run};
+use crate::{
+    testcases::Testcases,
+    testrunner::{alloc_bench_run, multithread_run},
+};
 use clap::Parser;
-use data_structures::{binary_search_tree, interfaces::Tree};
+use data_structures::{
+    binary_search_tree,
+    interfaces::{Capability, KeyType, Tree, ValueType},
+};
+use std::{sync::Arc, time::Instant};
 
+mod allocstats;
 mod testcases;
 mod testclient;
 mod testrunner;
@@ -17,7 +25,16 @@ static GLOBAL: Jemalloc = Jemalloc;
 
 use data_structures::art::DefaultArt;
 use data_structures::avl::ConcurrentAVLTree;
+use data_structures::blink_tree::BLinkTree;
 use data_structures::bptree::BpTree;
+use data_structures::bw_tree::BwTree;
+use data_structures::ca_tree::CaTree;
+use data_structures::external_bst::ExternalBST;
+use data_structures::hash_map::LockFreeHashMap;
+use data_structures::locked_btree::{MutexBTreeMap, RwLockBTreeMap, ShardedBTreeMap};
+use data_structures::masstree::Masstree;
+use data_structures::skiplist::{FixedKeySkipList, SkipList};
+use data_structures::treap::Treap;
 #[cfg(feature = "tcmalloc")]
 use tcmalloc::TCMalloc;
 
@@ -60,6 +77,14 @@ struct Args {
     #[arg(short = 's', long, default_value = "5000000")]
     size: usize,
 
+    /// Keys per `multi_put`/`multi_get` call in the test cases that batch.
+    #[arg(short = 'b', long, default_value = "1")]
+    batch: usize,
+
+    /// Keys bulk-loaded into the tree before the test case starts.
+    #[arg(long, default_value = "0")]
+    preload: usize,
+
     #[arg(short = 'n', default_value = "my_test")]
     run_name: String,
 
@@ -67,21 +92,63 @@ struct Args {
     run_profiler: bool,
 }
 
-fn run<T: Tree<String, String> + 'static>(args: Args) {
+/// Exits with an error if `T` lacks an operation the test case needs, instead
+/// of letting it fail partway through the run.
+fn check_capabilities<K: KeyType, V: ValueType, T: Tree<K, V>>(
+    args: &Args,
+    required: &[Capability],
+) {
+    let tree = T::new();
+    let missing: Vec<String> = required
+        .iter()
+        .filter(|capability| !tree.supports(**capability))
+        .map(|capability| capability.to_string())
+        .collect();
+    if !missing.is_empty() {
+        eprintln!(
+            "error: tree `{}` does not support {}, required by test case `{}`",
+            args.tree,
+            missing.join(", "),
+            args.testcase
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Builds the tree from `n` sorted keys with `Tree::bulk_load`.
+fn preload<K: KeyType, V: ValueType, T: Tree<K, V>>(n: usize, item: impl Fn(usize) -> (K, V)) -> T {
+    let start = Instant::now();
+    let tree = T::bulk_load((0..n).map(item)).expect("bulk load failed");
+    println!(
+        "preload: keys: {}, seconds: {:.3}",
+        n,
+        start.elapsed().as_secs_f64()
+    );
+    tree
+}
+
+fn run<T: Tree<String, String> + 'static>(args: Args) -> Arc<T> {
+    check_capabilities::<_, _, T>(&args, Testcases::<T>::requires(&args.testcase));
+    let key = |i: usize| format!("{:016}", i);
     multithread_run(
+        preload::<_, _, T>(args.preload, |i| (key(i), key(i))),
         args.num_threads,
         args.size,
+        args.batch,
         args.pin,
         args.run_name,
         args.run_profiler,
         Testcases::<T>::find(&args.testcase),
-    );
+    )
 }
 
 fn run_usize<T: Tree<usize, usize> + 'static>(args: Args) {
+    check_capabilities::<_, _, T>(&args, TestcasesUsize::<T>::requires(&args.testcase));
     multithread_run(
+        preload::<_, _, T>(args.preload, |i| (i, i)),
         args.num_threads,
         args.size,
+        args.batch,
         args.pin,
         args.run_name,
         args.run_profiler,
@@ -89,28 +156,101 @@ fn run_usize<T: Tree<usize, usize> + 'static>(args: Args) {
     );
 }
 
+fn run_alloc(args: Args) {
+    alloc_bench_run(
+        args.num_threads,
+        args.size,
+        args.pin,
+        args.run_name,
+        args.run_profiler,
+        benchmarks::find(&args.testcase),
+    );
+}
+
 fn main() {
     let args = Args::parse();
     println!(
-        "Benchmark: test run {} threads, size: {}, pin_to_core?: {}, memory allocator: {}",
-        args.num_threads, args.size, args.pin, MALLOC_NOTE
+        "Benchmark: test run {} threads, size: {}, batch: {}, pin_to_core?: {}, memory allocator: {}",
+        args.num_threads, args.size, args.batch, args.pin, MALLOC_NOTE
     );
     match args.tree.as_str() {
         "bst" => {
             run::<binary_search_tree::LockFreeBST>(args);
         }
+        "bst_ordered" => {
+            run::<binary_search_tree::OrderedBST>(args);
+        }
+        "ebst" => {
+            run::<ExternalBST>(args);
+        }
+        "treap" => {
+            run::<Treap<String, String>>(args);
+        }
+        "catree" => {
+            let stats = run::<CaTree<String, String>>(args).stats();
+            println!(
+                "catree_stats: splits: {}, joins: {}",
+                stats.splits, stats.joins
+            );
+        }
         "skiplist" => {
             run::<data_structures::skiplist::SkipMapWrapper<String, String>>(args);
         }
+        "lf_skiplist" => {
+            run::<SkipList<String, String>>(args);
+        }
+        "lf_skiplist_fixed" => {
+            run::<FixedKeySkipList<String>>(args);
+        }
         "bptree" => {
             run::<BpTree<String, String>>(args);
         }
+        "bwtree" => {
+            run::<BwTree<String, String>>(args);
+        }
+        "blink" => {
+            run::<BLinkTree<String, String>>(args);
+        }
+        "blink_16" => {
+            run::<BLinkTree<String, String, 16>>(args);
+        }
+        "blink_256" => {
+            run::<BLinkTree<String, String, 256>>(args);
+        }
+        "masstree" => {
+            run::<Masstree<String>>(args);
+        }
+        "hashmap" => {
+            run::<LockFreeHashMap<String, String>>(args);
+        }
+        "mutex_btree" => {
+            run::<MutexBTreeMap<String, String>>(args);
+        }
+        "rwlock_btree" => {
+            run::<RwLockBTreeMap<String, String>>(args);
+        }
+        "sharded_btree" => {
+            run::<ShardedBTreeMap<String, String>>(args);
+        }
+        "sharded_btree_4" => {
+            run::<ShardedBTreeMap<String, String, 4>>(args);
+        }
+        "sharded_btree_64" => {
+            run::<ShardedBTreeMap<String, String, 64>>(args);
+        }
         "avltree" => {
             run_usize::<ConcurrentAVLTree<usize, usize>>(args);
         }
         "art" => {
             run_usize::<DefaultArt>(args);
         }
+        "bst_usize" => {
+            run_usize::<binary_search_tree::UsizeBST>(args);
+        }
+        // Allocator micro-benchmarks, no tree involved.
+        "alloc" => {
+            run_alloc(args);
+        }
         _ => panic!("unknown tree: {}", args.tree),
     }
 }
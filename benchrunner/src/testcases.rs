@@ -1,12 +1,23 @@
 use std::collections::HashSet;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 
 use rand::distributions::Alphanumeric;
 use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
 
+use data_structures::interfaces::Capability;
+
+use crate::allocstats;
 use crate::testclient::{TestClient, TestTree};
+use crate::MALLOC_NOTE;
+
+const MAX_THREADS: usize = 256;
+
+/// Number of keys each producer has inserted so far, indexed by thread id.
+static PRODUCED: [AtomicUsize; MAX_THREADS] = [const { AtomicUsize::new(0) }; MAX_THREADS];
 
 pub struct Testcases<T: TestTree<String>> {
     _phantom: PhantomData<T>,
@@ -18,10 +29,27 @@ impl<T: TestTree<String>> Testcases<T> {
             "put_s" => Self::put_s,
             "put_m" => Self::put_m,
             "put_l" => Self::put_l,
+            "remote_overwrite" => Self::remote_overwrite,
+            "remote_remove" => Self::remote_remove,
+            "prod_cons" => Self::prod_cons,
+            "prod_cons_remove" => Self::prod_cons_remove,
+            "churn" => Self::churn,
+            "ascending" => Self::ascending,
+            "counter" => Self::counter,
+            "put_get" => Self::put_get,
             _ => panic!("unknown test case: {}", name),
         }
     }
 
+    /// Operations the test case needs beyond puts and gets.
+    pub fn requires(name: &str) -> &'static [Capability] {
+        match name {
+            "remote_remove" | "prod_cons_remove" | "churn" => &[Capability::Remove],
+            "counter" => &[Capability::ReadModifyWrite],
+            _ => &[],
+        }
+    }
+
     fn put_with_value_size<C: TestClient<String, T>>(client: &mut C, n: usize, value_size: usize) {
         let mut rng = StdRng::seed_from_u64((12345 + client.id()) as u64);
         let t = Instant::now();
@@ -52,6 +80,263 @@ impl<T: TestTree<String>> Testcases<T> {
     pub fn put_l<C: TestClient<String, T>>(client: &mut C, n: usize) {
         Self::put_with_value_size(client, n, 128)
     }
+
+    /// The key sequence inserted by `thread_id`, so that other threads can
+    /// replay it and free memory that thread allocated.
+    fn keys_of(thread_id: usize, n: usize) -> Vec<String> {
+        let mut rng = StdRng::seed_from_u64((12345 + thread_id) as u64);
+        (0..n).map(|_| rng.gen::<u32>().to_string()).collect()
+    }
+
+    fn random_value(rng: &mut StdRng, value_size: usize) -> String {
+        rng.sample_iter(&Alphanumeric)
+            .take(value_size)
+            .map(char::from)
+            .collect()
+    }
+
+    /// Every thread inserts its own keys, then overwrites or removes the keys
+    /// inserted by its neighbour, so every free hits memory allocated by
+    /// another thread.
+    fn remote_free<C: TestClient<String, T>>(client: &mut C, n: usize, remove: bool) {
+        let mut rng = StdRng::seed_from_u64((54321 + client.id()) as u64);
+        let keys = Self::keys_of(client.id(), n);
+
+        let mut t = Instant::now();
+        for key in keys {
+            let value = Self::random_value(&mut rng, 32);
+            client.put(key, value);
+        }
+        client.report("put_32", n, t.elapsed());
+        client.wait();
+
+        let remote_keys = Self::keys_of((client.id() + 1) % client.nthreads(), n);
+        t = Instant::now();
+        if remove {
+            for key in remote_keys {
+                client.remove(key);
+            }
+            client.report("remote_remove", n, t.elapsed());
+        } else {
+            for key in remote_keys {
+                let value = Self::random_value(&mut rng, 32);
+                client.put(key, value);
+            }
+            client.report("remote_overwrite", n, t.elapsed());
+        }
+        client.wait();
+        client.end();
+    }
+
+    pub fn remote_overwrite<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        Self::remote_free(client, n, false)
+    }
+
+    pub fn remote_remove<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        Self::remote_free(client, n, true)
+    }
+
+    /// Threads are paired up: the even thread of each pair produces keys, and
+    /// the odd one concurrently overwrites or removes them as soon as they
+    /// are published. With an odd thread count the last thread only produces.
+    fn producer_consumer<C: TestClient<String, T>>(client: &mut C, n: usize, remove: bool) {
+        let id = client.id();
+        assert!(id < MAX_THREADS, "too many threads");
+        let mut rng = StdRng::seed_from_u64((54321 + id) as u64);
+        PRODUCED[id].store(0, Ordering::Relaxed);
+        client.wait();
+
+        let t = Instant::now();
+        if id % 2 == 0 {
+            for (i, key) in Self::keys_of(id, n).into_iter().enumerate() {
+                let value = Self::random_value(&mut rng, 32);
+                client.put(key, value);
+                PRODUCED[id].store(i + 1, Ordering::Release);
+            }
+            client.report("produce", n, t.elapsed());
+        } else {
+            let producer = id - 1;
+            let mut keys = Self::keys_of(producer, n);
+            let mut consumed = 0;
+            while consumed < n {
+                let produced = PRODUCED[producer].load(Ordering::Acquire);
+                if produced == consumed {
+                    std::hint::spin_loop();
+                    continue;
+                }
+                for key in keys[consumed..produced].iter_mut() {
+                    let key = std::mem::take(key);
+                    if remove {
+                        client.remove(key);
+                    } else {
+                        let value = Self::random_value(&mut rng, 8);
+                        client.put(key, value);
+                    }
+                }
+                consumed = produced;
+            }
+            client.report(
+                if remove { "consume_remove" } else { "consume" },
+                n,
+                t.elapsed(),
+            );
+        }
+        client.wait();
+        client.end();
+    }
+
+    pub fn prod_cons<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        Self::producer_consumer(client, n, false)
+    }
+
+    pub fn prod_cons_remove<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        Self::producer_consumer(client, n, true)
+    }
+
+    /// Repeatedly re-inserts every key and removes a random half of them,
+    /// alternating between small and large values, to expose allocator
+    /// fragmentation. After every round the first thread samples live bytes
+    /// against RSS.
+    pub fn churn<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        const ROUNDS: usize = 20;
+
+        let mut rng = StdRng::seed_from_u64((54321 + client.id()) as u64);
+        let keys = Self::keys_of(client.id(), n);
+        let tree_name = std::any::type_name::<T>()
+            .split('<')
+            .next()
+            .and_then(|path| path.rsplit("::").next())
+            .unwrap_or("unknown");
+        let mut ops = 0;
+        let mut max_ratio: f64 = 0.0;
+
+        let t = Instant::now();
+        for round in 0..ROUNDS {
+            let (min_size, max_size) = if round % 2 == 0 { (8, 64) } else { (256, 2048) };
+            for key in keys.iter() {
+                let value_size = rng.gen_range(min_size..max_size);
+                let value = Self::random_value(&mut rng, value_size);
+                client.put(key.clone(), value);
+            }
+            for key in keys.iter() {
+                if rng.gen::<bool>() {
+                    client.remove(key.clone());
+                    ops += 1;
+                }
+            }
+            ops += n;
+            client.wait();
+
+            if client.id() == 0 {
+                let live = allocstats::live_bytes();
+                let rss = allocstats::resident_set_size();
+                let ratio = rss as f64 / live.max(1) as f64;
+                max_ratio = max_ratio.max(ratio);
+                println!(
+                    "fragmentation: round: {}, malloc: {}, tree: {}, live: {}, rss: {}, ratio: {}",
+                    round, MALLOC_NOTE, tree_name, live, rss, ratio
+                );
+            }
+            client.wait();
+        }
+        let churn_time_cost = t.elapsed();
+
+        if client.id() == 0 {
+            println!(
+                "fragmentation: max_ratio, malloc: {}, tree: {}, ratio: {}",
+                MALLOC_NOTE, tree_name, max_ratio
+            );
+        }
+        client.report("churn", ops, churn_time_cost);
+        client.wait();
+        client.end();
+    }
+
+    /// Threads insert interleaved keys in ascending order and read them back.
+    /// Keys are zero-padded to 16 bytes so string order matches numeric order
+    /// and they still fit a `FixSizedKey`. Trees that compare keys as native
+    /// integer words, like `bst`, do not see them in order; run against
+    /// `bst_ordered`, or the `usize` variant against `bst_usize`, to degrade
+    /// an unbalanced tree.
+    pub fn ascending<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        let (id, nthreads) = (client.id(), client.nthreads());
+        let keys: Vec<String> = (0..n)
+            .map(|i| format!("{:016}", i * nthreads + id))
+            .collect();
+
+        let mut t = Instant::now();
+        for key in keys.iter() {
+            client.put(key.clone(), key.clone());
+        }
+        client.report("put_ascending", n, t.elapsed());
+        client.wait();
+
+        t = Instant::now();
+        for key in keys {
+            client.get_check(key.clone(), key);
+        }
+        client.report("get_ascending", n, t.elapsed());
+        client.wait();
+        client.end();
+    }
+
+    /// Threads increment a few counters shared by all of them, through
+    /// `update`, after racing to create them with `insert_if_absent`. The
+    /// first thread then checks that no increment was lost.
+    pub fn counter<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        const COUNTERS: usize = 64;
+
+        let mut rng = StdRng::seed_from_u64((54321 + client.id()) as u64);
+        let key_of = |i: usize| format!("{:016}", i);
+        for i in 0..COUNTERS {
+            client.insert_if_absent(key_of(i), "0".to_string());
+        }
+        client.wait();
+
+        let t = Instant::now();
+        for _ in 0..n {
+            let key = key_of(rng.gen_range(0..COUNTERS));
+            let mut increment = |count: &String| (count.parse::<usize>().unwrap() + 1).to_string();
+            client.update(key, &mut increment);
+        }
+        client.report("update", n, t.elapsed());
+        client.wait();
+
+        if client.id() == 0 {
+            let total: usize = (0..COUNTERS)
+                .map(|i| client.get(key_of(i)).unwrap().parse::<usize>().unwrap())
+                .sum();
+            assert_eq!(total, n * client.nthreads(), "lost updates");
+        }
+        client.wait();
+        client.end();
+    }
+
+    /// Threads put distinct keys in random order, then read them all back,
+    /// `--batch` keys per `multi_put`/`multi_get` call.
+    pub fn put_get<C: TestClient<String, T>>(client: &mut C, n: usize) {
+        let (id, nthreads) = (client.id(), client.nthreads());
+        let mut rng = StdRng::seed_from_u64((12345 + id) as u64);
+        let mut keys: Vec<String> = (0..n)
+            .map(|i| format!("{:016}", i * nthreads + id))
+            .collect();
+        keys.shuffle(&mut rng);
+        let items: Vec<(String, String)> = keys
+            .into_iter()
+            .map(|key| (key, Self::random_value(&mut rng, 32)))
+            .collect();
+
+        let mut t = Instant::now();
+        client.put_all(&items);
+        client.report("put_32", n, t.elapsed());
+        client.wait();
+
+        t = Instant::now();
+        client.get_check_all(&items);
+        client.report("get", n, t.elapsed());
+        client.wait();
+        client.end();
+    }
 }
 
 pub struct Testcasesi32<T: TestTree<i32>> {
@@ -130,10 +415,38 @@ impl<T: TestTree<usize>> TestcasesUsize<T> {
     pub fn find<C: TestClient<usize, T>>(name: &str) -> fn(&mut C, size: usize) {
         match name {
             "simple" => Self::test_simple,
+            "ascending" => Self::ascending,
             _ => panic!("unknown test case: {}", name),
         }
     }
 
+    /// Operations the test case needs beyond puts and gets, none so far.
+    pub fn requires(_name: &str) -> &'static [Capability] {
+        &[]
+    }
+
+    /// Threads insert interleaved keys in ascending order and read them back.
+    /// Without balancing a search tree degenerates into a linked list.
+    pub fn ascending<C: TestClient<usize, T>>(client: &mut C, n: usize) {
+        let (id, nthreads) = (client.id(), client.nthreads());
+        let keys: Vec<usize> = (0..n).map(|i| i * nthreads + id).collect();
+
+        let mut t = Instant::now();
+        for &key in keys.iter() {
+            client.put(key, key);
+        }
+        client.report("put_ascending", n, t.elapsed());
+        client.wait();
+
+        t = Instant::now();
+        for key in keys {
+            client.get_check(key, key);
+        }
+        client.report("get_ascending", n, t.elapsed());
+        client.wait();
+        client.end();
+    }
+
     pub fn test_simple<C: TestClient<usize, T>>(client: &mut C, n: usize) {
         let mut rng = StdRng::seed_from_u64((12345 + client.id()) as u64);
         let mut keys = HashSet::new();
@@ -142,18 +455,16 @@ impl<T: TestTree<usize>> TestcasesUsize<T> {
         }
         let keys: Vec<_> = keys.into_iter().collect();
 
+        let items: Vec<_> = keys.iter().map(|&key| (key, key + 1)).collect();
+
         let mut t = Instant::now();
-        for &key in keys.iter() {
-            client.put(key, key + 1);
-        }
+        client.put_all(&items);
         let put_time_cost = t.elapsed();
         client.report("put", n, put_time_cost);
         client.wait();
 
         t = Instant::now();
-        for &key in keys.iter() {
-            client.get_check(key, key + 1);
-        }
+        client.get_check_all(&items);
         let get_time_cost = t.elapsed();
         client.report("get", n, get_time_cost);
         client.wait();
@@ -7,8 +7,11 @@ use std::{
     time::Duration,
 };
 
-use crate::MALLOC_NOTE;
-use data_structures::interfaces::{GetType, KeyType, Tree};
+use crate::{allocstats, MALLOC_NOTE};
+use data_structures::{
+    error::{Result, TreeError},
+    interfaces::{Capability, KeyType, Tree},
+};
 
 pub trait TestTree<T: KeyType> = Tree<T, T> + Sized;
 
@@ -43,10 +46,54 @@ pub trait TestClient<D, T: TestTree<D>> {
     fn put(&self, key: D, value: D);
     fn get_check(&self, key: D, value: D);
     fn get_check_absent(&self, key: D);
+    fn get(&self, key: D) -> Option<D>;
+    fn remove(&self, key: D);
+    fn insert_if_absent(&self, key: D, value: D) -> bool;
+    fn update(&self, key: D, f: &mut dyn FnMut(&D) -> D) -> Option<D>;
+    fn multi_put(&self, items: &[(D, D)]);
+    /// `get_check` of every `(key, value)` pair in one batch.
+    fn multi_get_check(&self, items: &[(D, D)]);
 
     fn id(&self) -> usize {
         0
     }
+    fn nthreads(&self) -> usize {
+        1
+    }
+    /// Operations per `multi_put`/`multi_get_check` call.
+    fn batch(&self) -> usize {
+        1
+    }
+    /// Puts `items` one by one, or through `multi_put` when batching.
+    fn put_all(&self, items: &[(D, D)])
+    where
+        D: Clone,
+    {
+        if self.batch() == 1 {
+            for (key, value) in items {
+                self.put(key.clone(), value.clone());
+            }
+        } else {
+            for batch in items.chunks(self.batch()) {
+                self.multi_put(batch);
+            }
+        }
+    }
+    /// `get_check` of all of `items`, through `multi_get_check` when batching.
+    fn get_check_all(&self, items: &[(D, D)])
+    where
+        D: Clone,
+    {
+        if self.batch() == 1 {
+            for (key, value) in items {
+                self.get_check(key.clone(), value.clone());
+            }
+        } else {
+            for batch in items.chunks(self.batch()) {
+                self.multi_get_check(batch);
+            }
+        }
+    }
     fn notice(&self, msg: &str) {
         println!("{}", msg)
     }
@@ -59,38 +106,40 @@ pub trait TestClient<D, T: TestTree<D>> {
 
 pub static STAT_MAP: Lazy<Mutex<HashMap<String, Vec<(usize, ReportEntry)>>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
+pub static ALLOC_STAT_MAP: Lazy<Mutex<HashMap<String, Vec<(&'static str, usize)>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+/// Failed operations of the current run by error, so one bad key or missing
+/// capability does not abort the whole run.
+pub static FAILURES: Lazy<Mutex<HashMap<String, usize>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 pub static WAIT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
 
 pub trait MultiThreadShmClient<D, T: TestTree<D>>: TestClient<D, T> {
-    fn new_multithread(id: usize, nthreads: usize) -> Self;
+    fn new_multithread(id: usize, nthreads: usize, batch: usize) -> Self;
     fn set_tree(&mut self, tree: Arc<T>);
     fn get_tree(&self) -> &T;
-    fn nthreads(&self) -> usize;
 }
 
 pub struct MultiThreadClient<D, T: TestTree<D>> {
     _tree: Option<Arc<T>>,
     nthreads: usize,
     thread_id: usize,
+    batch: usize,
     _phantom_d: PhantomData<D>,
     _phantom_t: PhantomData<T>,
 }
 
 impl<D, T: TestTree<D>> MultiThreadShmClient<D, T> for MultiThreadClient<D, T> {
-    fn new_multithread(id: usize, nthreads: usize) -> Self {
+    fn new_multithread(id: usize, nthreads: usize, batch: usize) -> Self {
         MultiThreadClient {
             _tree: None,
             nthreads,
             thread_id: id,
+            batch,
             _phantom_d: PhantomData,
             _phantom_t: PhantomData,
         }
     }
 
-    fn nthreads(&self) -> usize {
-        self.nthreads
-    }
-
     fn set_tree(&mut self, tree: Arc<T>) {
         self._tree = Some(tree)
     }
@@ -105,26 +154,76 @@ impl<D, T: TestTree<D>> TestClient<D, T> for MultiThreadClient<D, T> {
         self.thread_id
     }
 
+    fn nthreads(&self) -> usize {
+        self.nthreads
+    }
+
+    fn batch(&self) -> usize {
+        self.batch
+    }
+
     #[inline]
     fn put(&self, key: D, value: D) {
-        self.get_tree().put(key, value);
+        record_failure(self.get_tree().put(key, value));
     }
 
     #[inline]
     fn get_check(&self, key: D, value: D) {
-        match T::GET_TYPE {
-            GetType::GetVal => {
-                assert_eq!(self.get_tree().get_val(key).expect("key not found"), value)
-            }
-            GetType::GetRef => assert_eq!(self.get_tree().get(key).expect("key not found"), &value),
-        };
+        let found = self
+            .get_tree()
+            .get_with(key, |found| assert_eq!(found, &value));
+        if let Some(found) = record_failure(found) {
+            found.expect("key not found");
+        }
     }
 
     fn get_check_absent(&self, key: D) {
-        match T::GET_TYPE {
-            GetType::GetVal => assert!(self.get_tree().get_val(key).is_none()),
-            GetType::GetRef => assert!(self.get_tree().get(key).is_none()),
-        };
+        if let Some(found) = record_failure(self.get_tree().get_with(key, |_| ())) {
+            assert!(found.is_none());
+        }
+    }
+
+    fn get(&self, key: D) -> Option<D> {
+        record_failure(self.get_tree().get(key)).flatten()
+    }
+
+    #[inline]
+    fn remove(&self, key: D) {
+        let tree = self.get_tree().as_remove();
+        record_failure(
+            tree.ok_or(TreeError::Unsupported(Capability::Remove))
+                .and_then(|tree| tree.remove(key)),
+        );
+    }
+
+    fn insert_if_absent(&self, key: D, value: D) -> bool {
+        let tree = self.get_tree().as_read_modify_write();
+        let inserted = tree
+            .ok_or(TreeError::Unsupported(Capability::ReadModifyWrite))
+            .and_then(|tree| tree.insert_if_absent(key, value));
+        record_failure(inserted).unwrap_or(false)
+    }
+
+    #[inline]
+    fn update(&self, key: D, f: &mut dyn FnMut(&D) -> D) -> Option<D> {
+        let tree = self.get_tree().as_read_modify_write();
+        let old = tree
+            .ok_or(TreeError::Unsupported(Capability::ReadModifyWrite))
+            .and_then(|tree| tree.update(key, f));
+        record_failure(old).flatten()
+    }
+
+    fn multi_put(&self, items: &[(D, D)]) {
+        record_failure(self.get_tree().multi_put(items));
+    }
+
+    fn multi_get_check(&self, items: &[(D, D)]) {
+        let keys: Vec<D> = items.iter().map(|(key, _)| key.clone()).collect();
+        if let Some(found) = record_failure(self.get_tree().multi_get(&keys)) {
+            for ((_, value), found) in items.iter().zip(found) {
+                assert_eq!(&found.expect("key not found"), value);
+            }
+        }
     }
 
     fn new() -> Self {
@@ -136,10 +235,7 @@ impl<D, T: TestTree<D>> TestClient<D, T> for MultiThreadClient<D, T> {
     }
 
     fn report(&self, base: &str, num: usize, cost: std::time::Duration) {
-        let mut stat_map = STAT_MAP.lock().unwrap();
-        let entry = ReportEntry::new(base, num, cost);
-        let v = stat_map.entry(base.to_string()).or_insert(vec![]);
-        v.push((self.id(), entry));
+        record_report(self.id(), base, num, cost);
     }
 
     fn wait(&self) {
@@ -152,31 +248,103 @@ impl<D, T: TestTree<D>> TestClient<D, T> for MultiThreadClient<D, T> {
     }
 
     fn end(&self) {
-        let mut stat_map = STAT_MAP.lock().unwrap();
-
-        for metric in stat_map.keys().sorted() {
-            let entries = &stat_map[metric];
-            let mut total_cost = std::time::Duration::new(0, 0);
-            let mut total_num = 0;
-            let num_thread = entries.len();
-            let mut max_time_cost = std::time::Duration::new(0, 0);
-
-            for (id, entry) in entries.iter() {
-                total_num += entry.size;
-                total_cost += entry.elapsed;
-                if max_time_cost < entry.elapsed {
-                    max_time_cost = entry.elapsed;
-                }
-                // println!("[{id}]: {}", entry.format());
-            }
+        print_stats();
+    }
+}
+
+/// Counts a failed operation, returning the result of a successful one.
+pub fn record_failure<R>(result: Result<R>) -> Option<R> {
+    match result {
+        Ok(result) => Some(result),
+        Err(error) => {
+            *FAILURES
+                .lock()
+                .unwrap()
+                .entry(error.to_string())
+                .or_insert(0) += 1;
+            None
+        }
+    }
+}
 
-            let total_throughput = total_num as f64 / max_time_cost.as_secs_f64();
-            let avg_throughput = total_num as f64 / total_cost.as_secs_f64();
-            println!(
-                "metric: {}, malloc: {}, #threads: {}, total_throughput: {}, avg_throughput: {}",
-                metric, MALLOC_NOTE, num_thread, total_throughput, avg_throughput,
-            );
+/// Records one thread's result for a phase, along with an allocator sample.
+pub fn record_report(thread_id: usize, base: &str, num: usize, cost: Duration) {
+    let mut stat_map = STAT_MAP.lock().unwrap();
+    let entry = ReportEntry::new(base, num, cost);
+    let v = stat_map.entry(base.to_string()).or_insert(vec![]);
+    v.push((thread_id, entry));
+    // Sampled under the lock, so the last thread to finish the phase wins.
+    ALLOC_STAT_MAP
+        .lock()
+        .unwrap()
+        .insert(base.to_string(), allocstats::sample());
+}
+
+/// Prints and clears the aggregated results of all recorded phases.
+pub fn print_stats() {
+    let mut stat_map = STAT_MAP.lock().unwrap();
+
+    for metric in stat_map.keys().sorted() {
+        let entries = &stat_map[metric];
+        let mut total_cost = std::time::Duration::new(0, 0);
+        let mut total_num = 0;
+        let num_thread = entries.len();
+        let mut max_time_cost = std::time::Duration::new(0, 0);
+
+        for (id, entry) in entries.iter() {
+            total_num += entry.size;
+            total_cost += entry.elapsed;
+            if max_time_cost < entry.elapsed {
+                max_time_cost = entry.elapsed;
+            }
+            // println!("[{id}]: {}", entry.format());
         }
-        stat_map.clear();
+
+        let total_throughput = total_num as f64 / max_time_cost.as_secs_f64();
+        let avg_throughput = total_num as f64 / total_cost.as_secs_f64();
+        println!(
+            "metric: {}, malloc: {}, #threads: {}, total_throughput: {}, avg_throughput: {}",
+            metric, MALLOC_NOTE, num_thread, total_throughput, avg_throughput,
+        );
+    }
+
+    let mut alloc_stat_map = ALLOC_STAT_MAP.lock().unwrap();
+    for phase in alloc_stat_map.keys().sorted() {
+        println!(
+            "alloc_stats: phase: {}, malloc: {}, {}",
+            phase,
+            MALLOC_NOTE,
+            allocstats::format_sample(&alloc_stat_map[phase]),
+        );
+    }
+    alloc_stat_map.clear();
+    stat_map.clear();
+
+    let mut failures = FAILURES.lock().unwrap();
+    for error in failures.keys().sorted() {
+        println!("failures: {}, count: {}", error, failures[error]);
+    }
+    failures.clear();
+}
+
+/// Prints how many keys the tree ended up with and the memory they take.
+pub fn print_tree_stats<D: KeyType, T: TestTree<D>>(tree: &T) {
+    let keys = tree.len();
+    let bytes = tree.memory_usage();
+    println!(
+        "tree_stats: malloc: {}, keys: {}, bytes: {}, bytes_per_key: {:.1}",
+        MALLOC_NOTE,
+        keys,
+        bytes,
+        bytes as f64 / keys.max(1) as f64,
+    );
+}
+
+/// Feeds results of the `benchmarks` allocator suite into the same report.
+pub struct StatReporter;
+
+impl benchmarks::Reporter for StatReporter {
+    fn report(&self, thread_id: usize, metric: &str, num: usize, cost: Duration) {
+        record_report(thread_id, metric, num, cost);
     }
 }
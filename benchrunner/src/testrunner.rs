@@ -3,31 +3,32 @@ use std::{sync::Arc, thread};
 use core_affinity::get_core_ids;
 use cpuprofiler::PROFILER;
 
-use crate::testclient::{MultiThreadClient, MultiThreadShmClient, TestTree};
+use crate::testclient::{
+    print_stats, print_tree_stats, MultiThreadClient, MultiThreadShmClient, StatReporter, TestTree,
+};
+use benchmarks::{BenchConfig, Benchmark};
+use data_structures::interfaces::KeyType;
 
-#[allow(dead_code)]
+#[allow(dead_code, clippy::too_many_arguments)]
 pub fn multithread_run<D, T>(
+    tree: T,
     nthreads: usize,
     size: usize,
+    batch: usize,
     pin_to_thread: bool,
     run_name: String,
     run_profiler: bool,
     test_fn: fn(&mut MultiThreadClient<D, T>, size: usize) -> (),
-) where
-    D: 'static,
+) -> Arc<T>
+where
+    D: KeyType + 'static,
     T: TestTree<D> + 'static,
     Arc<T>: Send,
 {
-    let tree = Arc::new(T::new());
+    let tree = Arc::new(tree);
     let core_ids = get_core_ids().unwrap()[..(nthreads)].to_vec();
 
-    if run_profiler {
-        PROFILER
-            .lock()
-            .expect("Failed to lock profiler")
-            .start(format!("{run_name}_j{nthreads}.profile"))
-            .expect("profiler failed to start");
-    }
+    start_profiler(run_profiler, &run_name, nthreads);
 
     let handles = core_ids
         .into_iter()
@@ -40,7 +41,8 @@ pub fn multithread_run<D, T>(
                         panic!("Failed to set core affinity");
                     }
                 }
-                let mut client = MultiThreadClient::<D, T>::new_multithread(core_id.id, nthreads);
+                let mut client =
+                    MultiThreadClient::<D, T>::new_multithread(core_id.id, nthreads, batch);
                 client.set_tree(_tree);
                 test_fn(&mut client, size / nthreads);
             })
@@ -50,6 +52,44 @@ pub fn multithread_run<D, T>(
         handle.join().unwrap();
     }
 
+    stop_profiler(run_profiler);
+    print_tree_stats(tree.as_ref());
+    tree
+}
+
+/// Runs one of the allocator micro-benchmarks from the `benchmarks` crate.
+pub fn alloc_bench_run(
+    nthreads: usize,
+    size: usize,
+    pin_to_thread: bool,
+    run_name: String,
+    run_profiler: bool,
+    bench: Benchmark,
+) {
+    let config = BenchConfig {
+        nthreads,
+        size,
+        pin: pin_to_thread,
+    };
+
+    start_profiler(run_profiler, &run_name, nthreads);
+    bench(&config, &StatReporter);
+    stop_profiler(run_profiler);
+
+    print_stats();
+}
+
+fn start_profiler(run_profiler: bool, run_name: &str, nthreads: usize) {
+    if run_profiler {
+        PROFILER
+            .lock()
+            .expect("Failed to lock profiler")
+            .start(format!("{run_name}_j{nthreads}.profile"))
+            .expect("profiler failed to start");
+    }
+}
+
+fn stop_profiler(run_profiler: bool) {
     if run_profiler {
         PROFILER
             .lock()
@@ -1,6 +1,11 @@
+use std::mem;
+
 use congee::Art;
 
-use crate::interfaces::{GetType, KeyType, Tree, ValueType};
+use crate::error::{Result, TreeError};
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, PointRead, ReadModifyWrite, Remove, Tree, ValueType,
+};
 
 impl<K: KeyType, V: ValueType> Tree<K, V> for Art<K, V>
 where
@@ -8,19 +13,212 @@ where
     V: Clone + From<usize>,
     usize: From<V> + From<K>,
 {
-    const GET_TYPE: crate::interfaces::GetType = GetType::GetVal;
+    fn new() -> Self {
+        Art::default()
+    }
 
-    fn put(&self, key: K, value: V) {
-        self.insert(key, value, &self.pin()).expect("Failed");
+    /// Congee cannot build nodes directly, so this inserts in key order
+    /// under one guard, each insert finding its path still in cache.
+    fn bulk_load(items: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        let tree = Art::default();
+        {
+            let guard = tree.pin();
+            for (key, value) in items {
+                tree.insert(key, value, &guard)
+                    .map_err(|_| TreeError::OutOfMemory)?;
+            }
+        }
+        Ok(tree)
     }
 
-    fn get_val(&self, key: K) -> Option<V> {
-        self.get(&key, &self.pin())
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
     }
 
-    fn new() -> Self {
-        Art::default()
+    fn as_read_modify_write(&self) -> Option<&dyn ReadModifyWrite<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for Art<K, V>
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        Ok(Art::get(self, &key, &self.pin()).map(|v| f(&v)))
+    }
+
+    /// Looks the keys up in ascending order under one guard. Congee does not
+    /// expose its nodes to prefetch, but sorted keys share their upper
+    /// levels, which the previous lookup leaves in cache.
+    fn multi_get(&self, keys: &[K]) -> Result<Vec<Option<V>>> {
+        let guard = self.pin();
+        let mut result = vec![None; keys.len()];
+        for i in sorted_order(keys.len(), |i| &keys[i]) {
+            result[i] = Art::get(self, &keys[i], &guard);
+        }
+        Ok(result)
+    }
+}
+
+impl<K: KeyType, V: ValueType> Insert<K, V> for Art<K, V>
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    fn put(&self, key: K, value: V) -> Result<()> {
+        self.insert(key, value, &self.pin())
+            .map_err(|_| TreeError::OutOfMemory)?;
+        Ok(())
+    }
+
+    /// Inserts in ascending key order under one guard, like `multi_get`.
+    fn multi_put(&self, items: &[(K, V)]) -> Result<()> {
+        let guard = self.pin();
+        for i in sorted_order(items.len(), |i| &items[i].0) {
+            let (key, value) = items[i].clone();
+            self.insert(key, value, &guard)
+                .map_err(|_| TreeError::OutOfMemory)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Remove<K, V> for Art<K, V>
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    fn remove(&self, key: K) -> Result<()> {
+        Art::remove(self, &key, &self.pin());
+        Ok(())
+    }
+}
+
+/// Indices `0..n` ordered by the key at each.
+fn sorted_order<'a, K: Ord + 'a>(n: usize, key_at: impl Fn(usize) -> &'a K) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| key_at(a).cmp(key_at(b)));
+    order
+}
+
+impl<K: KeyType, V: ValueType> ReadModifyWrite<K, V> for Art<K, V>
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    fn insert_if_absent(&self, key: K, value: V) -> Result<bool> {
+        let value = usize::from(value);
+        let mut keep_or_insert = |old: Option<usize>| old.unwrap_or(value);
+        let previous = self
+            .compute_or_insert(key, &mut keep_or_insert, &self.pin())
+            .map_err(|_| TreeError::OutOfMemory)?;
+        Ok(previous.is_none())
+    }
+
+    fn compare_exchange(&self, key: K, expected: &V, new: V) -> Result<()> {
+        Art::compare_exchange(self, &key, expected, Some(new), &self.pin())
+            .map(|_| ())
+            .map_err(|_| TreeError::Conflict)
+    }
+
+    fn update(&self, key: K, f: &mut dyn FnMut(&V) -> V) -> Result<Option<V>> {
+        let mut apply = |old: usize| Some(usize::from(f(&V::from(old))));
+        Ok(self
+            .compute_if_present(&key, &mut apply, &self.pin())
+            .map(|(old, _)| V::from(old)))
+    }
+}
+
+/// Entries fetched per `range` call when walking the whole tree.
+const RANGE_CHUNK: usize = 1024;
+
+/// Calls `f` with every entry of `art`, in key order, a chunk at a time.
+/// `range` excludes its end, so the largest key is looked up on its own.
+fn for_each_chunk<K, V>(art: &Art<K, V>, mut f: impl FnMut(&[(usize, usize)]))
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    let guard = art.pin();
+    let mut chunk = vec![(0, 0); RANGE_CHUNK];
+    let mut start = 0;
+    loop {
+        let n = art.range(&K::from(start), &K::from(usize::MAX), &mut chunk, &guard);
+        f(&chunk[..n]);
+        match chunk[..n].last() {
+            Some(&(last, _)) if n == RANGE_CHUNK => start = last + 1,
+            _ => break,
+        }
+    }
+    if let Some(value) = art.get(&K::from(usize::MAX), &guard) {
+        f(&[(usize::MAX, usize::from(value))]);
+    }
+}
+
+impl<K: KeyType, V: ValueType> Len for Art<K, V>
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    fn len(&self) -> usize {
+        let mut len = 0;
+        for_each_chunk(self, |chunk| len += chunk.len());
+        len
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for Art<K, V>
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    fn clear(&self) {
+        let mut keys = Vec::new();
+        for_each_chunk(self, |chunk| keys.extend(chunk.iter().map(|&(key, _)| key)));
+        let guard = self.pin();
+        for key in keys {
+            self.remove(&K::from(key), &guard);
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for Art<K, V>
+where
+    K: Clone + From<usize>,
+    V: Clone + From<usize>,
+    usize: From<V> + From<K>,
+{
+    /// Congee keeps its nodes private, so only the entries are counted.
+    fn memory_usage(&self) -> usize {
+        self.len() * mem::size_of::<(usize, usize)>()
     }
 }
 
 pub type DefaultArt = Art<usize, usize>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_include_the_largest_key() {
+        let tree = <DefaultArt as Tree<usize, usize>>::new();
+        for key in [0, 5, usize::MAX - 1, usize::MAX] {
+            tree.put(key, key).unwrap();
+        }
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.memory_usage(), 4 * mem::size_of::<(usize, usize)>());
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(PointRead::get(&tree, usize::MAX).unwrap(), None);
+    }
+}
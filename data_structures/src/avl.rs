@@ -1,23 +1,1127 @@
-use crate::interfaces::{GetType, KeyType, Tree, ValueType};
-use cds::avltree::RwLockAVLTree;
-use cds::map::ConcurrentMap;
+use std::{
+    cmp::{max, Ordering as CmpOrdering},
+    hint,
+    marker::PhantomData,
+    mem,
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicI32, AtomicPtr, AtomicU64, Ordering},
+        Mutex,
+    },
+};
 
-#[allow(invalid_reference_casting)]
-impl<K: KeyType + Default, V: ValueType + Default> Tree<K, V> for RwLockAVLTree<K, V> {
-    const GET_TYPE: crate::interfaces::GetType = GetType::GetVal;
+use crossbeam::epoch::{self, Guard};
 
-    fn put(&self, key: K, value: V) {
-        let mut_self = unsafe { &mut *(self as *const Self as *mut Self) };
-        mut_self.insert(&key, value).expect("Insert failed");
+use crate::error::Result;
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, Ordered, PointRead, Remove, Tree, ValueType,
+};
+
+// Optimistic version of a node. Only shrinks (rotations that move keys out of
+// the node's subtree) and unlinks change it; growing is harmless to readers.
+const UNLINKED: u64 = 1;
+const SHRINKING: u64 = 2;
+const SHRINK_COUNT_INCR: u64 = 4;
+
+const SPIN_COUNT: usize = 100;
+
+#[inline(always)]
+fn is_shrinking_or_unlinked(ovl: u64) -> bool {
+    ovl & (SHRINKING | UNLINKED) != 0
+}
+
+#[inline(always)]
+fn is_unlinked(ovl: u64) -> bool {
+    ovl == UNLINKED
+}
+
+/// Marker for an optimistic read that was invalidated by a concurrent change.
+struct Retry;
+
+enum Condition {
+    Unlink,
+    Rebalance,
+    Nothing,
+    Height(i32),
+}
+
+struct Node<K, V> {
+    /// `None` only for the root holder.
+    key: Option<K>,
+    height: AtomicI32,
+    version: AtomicU64,
+    /// Null for routing nodes, whose key was removed but that still have two
+    /// children.
+    value: AtomicPtr<V>,
+    parent: AtomicPtr<Node<K, V>>,
+    left: AtomicPtr<Node<K, V>>,
+    right: AtomicPtr<Node<K, V>>,
+    lock: Mutex<()>,
+}
+
+impl<K, V> Node<K, V> {
+    fn alloc(key: Option<K>, value: *mut V, parent: *mut Node<K, V>) -> *mut Node<K, V> {
+        Box::into_raw(Box::new(Node {
+            key,
+            height: AtomicI32::new(1),
+            version: AtomicU64::new(0),
+            value: AtomicPtr::new(value),
+            parent: AtomicPtr::new(parent),
+            left: AtomicPtr::new(null_mut()),
+            right: AtomicPtr::new(null_mut()),
+            lock: Mutex::new(()),
+        }))
+    }
+
+    #[inline(always)]
+    fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+
+    #[inline(always)]
+    fn as_ptr(&self) -> *mut Node<K, V> {
+        self as *const Node<K, V> as *mut Node<K, V>
+    }
+
+    #[inline(always)]
+    fn child(&self, dir: CmpOrdering) -> *mut Node<K, V> {
+        if dir == CmpOrdering::Less {
+            self.left.load(Ordering::Acquire)
+        } else {
+            self.right.load(Ordering::Acquire)
+        }
+    }
+
+    #[inline(always)]
+    fn set_child(&self, dir: CmpOrdering, child: *mut Node<K, V>) {
+        if dir == CmpOrdering::Less {
+            self.left.store(child, Ordering::Release)
+        } else {
+            self.right.store(child, Ordering::Release)
+        }
+    }
+
+    #[inline(always)]
+    fn has_value(&self) -> bool {
+        !self.value.load(Ordering::Acquire).is_null()
+    }
+
+    fn begin_shrink(&self) -> u64 {
+        let ovl = self.version.load(Ordering::Relaxed);
+        self.version.store(ovl | SHRINKING, Ordering::Release);
+        ovl
+    }
+
+    fn end_shrink(&self, ovl: u64) {
+        self.version
+            .store(ovl + SHRINK_COUNT_INCR, Ordering::Release);
+    }
+
+    /// Waits for a rotation of this node to finish. The rotating thread holds
+    /// the node lock, so after a short spin we block on it.
+    fn wait_until_not_changing(&self) {
+        let ovl = self.version.load(Ordering::Acquire);
+        if ovl & SHRINKING != 0 {
+            for _ in 0..SPIN_COUNT {
+                if self.version.load(Ordering::Acquire) != ovl {
+                    return;
+                }
+                hint::spin_loop();
+            }
+            drop(self.lock.lock().unwrap());
+        }
+    }
+}
+
+#[inline(always)]
+fn deref<'g, K, V>(node: *mut Node<K, V>) -> Option<&'g Node<K, V>> {
+    unsafe { node.as_ref() }
+}
+
+#[inline(always)]
+fn height<K, V>(node: *mut Node<K, V>) -> i32 {
+    deref(node).map_or(0, |n| n.height.load(Ordering::Relaxed))
+}
+
+fn node_condition<K, V>(node: &Node<K, V>) -> Condition {
+    let left = node.left.load(Ordering::Acquire);
+    let right = node.right.load(Ordering::Acquire);
+    if (left.is_null() || right.is_null()) && !node.has_value() {
+        return Condition::Unlink;
+    }
+    let h = node.height.load(Ordering::Relaxed);
+    let hl = height(left);
+    let hr = height(right);
+    let h_repl = 1 + max(hl, hr);
+    if !(-1..=1).contains(&(hl - hr)) {
+        Condition::Rebalance
+    } else if h != h_repl {
+        Condition::Height(h_repl)
+    } else {
+        Condition::Nothing
+    }
+}
+
+/// Concurrent relaxed-balance AVL tree after Bronson, Casper, Chafi and
+/// Olukotun, "A Practical Concurrent Binary Search Tree" (PPoPP '10).
+///
+/// Readers never lock: they validate each hop against the parent's version
+/// and retry when a rotation shrank the subtree under them. Writers lock the
+/// parent and the node they change. Removing a node with two children leaves
+/// a routing node behind, which rebalancing unlinks once it has one child.
+/// Unlinked nodes and replaced values are reclaimed through epochs.
+pub struct AvlTree<K, V> {
+    /// Root holder, its right child is the root.
+    holder: Box<Node<K, V>>,
+    _marker: PhantomData<V>,
+}
+
+impl<K: KeyType, V: ValueType> AvlTree<K, V> {
+    /// The value under `key`, null if absent. Stays valid while pinned.
+    fn attempt_get(
+        &self,
+        key: &K,
+        node: &Node<K, V>,
+        dir: CmpOrdering,
+        node_ovl: u64,
+    ) -> Result<*mut V, Retry> {
+        loop {
+            let child = node.child(dir);
+            let Some(c) = deref(child) else {
+                if node.version.load(Ordering::Acquire) != node_ovl {
+                    return Err(Retry);
+                }
+                return Ok(null_mut());
+            };
+            let child_dir = key.cmp(c.key());
+            if child_dir == CmpOrdering::Equal {
+                return Ok(c.value.load(Ordering::Acquire));
+            }
+            let child_ovl = c.version.load(Ordering::Acquire);
+            if is_shrinking_or_unlinked(child_ovl) {
+                c.wait_until_not_changing();
+                if node.version.load(Ordering::Acquire) != node_ovl {
+                    return Err(Retry);
+                }
+            } else if child != node.child(dir) {
+                if node.version.load(Ordering::Acquire) != node_ovl {
+                    return Err(Retry);
+                }
+            } else {
+                if node.version.load(Ordering::Acquire) != node_ovl {
+                    return Err(Retry);
+                }
+                if let Ok(found) = self.attempt_get(key, c, child_dir, child_ovl) {
+                    return Ok(found);
+                }
+            }
+        }
+    }
+
+    /// Inserts, replaces or (with a null `value`) removes `key`.
+    fn update(&self, key: &K, value: *mut V, guard: &Guard) {
+        let holder = &*self.holder;
+        loop {
+            let root = holder.right.load(Ordering::Acquire);
+            match deref(root) {
+                None => {
+                    if value.is_null() {
+                        return;
+                    }
+                    let _lock = holder.lock.lock().unwrap();
+                    if holder.right.load(Ordering::Acquire).is_null() {
+                        let node = Node::alloc(Some(key.clone()), value, holder.as_ptr());
+                        holder.right.store(node, Ordering::Release);
+                        holder.height.store(2, Ordering::Relaxed);
+                        return;
+                    }
+                }
+                Some(r) => {
+                    let ovl = r.version.load(Ordering::Acquire);
+                    if is_shrinking_or_unlinked(ovl) {
+                        r.wait_until_not_changing();
+                    } else if root == holder.right.load(Ordering::Acquire)
+                        && self
+                            .attempt_update(key, value, holder, r, ovl, guard)
+                            .is_ok()
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn attempt_update(
+        &self,
+        key: &K,
+        value: *mut V,
+        parent: &Node<K, V>,
+        node: &Node<K, V>,
+        node_ovl: u64,
+        guard: &Guard,
+    ) -> Result<(), Retry> {
+        let dir = key.cmp(node.key());
+        if dir == CmpOrdering::Equal {
+            return self.attempt_node_update(value, parent, node, guard);
+        }
+
+        loop {
+            let child = node.child(dir);
+            if node.version.load(Ordering::Acquire) != node_ovl {
+                return Err(Retry);
+            }
+            match deref(child) {
+                None => {
+                    if value.is_null() {
+                        return Ok(());
+                    }
+                    let damaged = {
+                        let _lock = node.lock.lock().unwrap();
+                        if node.version.load(Ordering::Acquire) != node_ovl {
+                            return Err(Retry);
+                        }
+                        if !node.child(dir).is_null() {
+                            continue;
+                        }
+                        node.set_child(dir, Node::alloc(Some(key.clone()), value, node.as_ptr()));
+                        self.fix_height_nl(node)
+                    };
+                    self.fix_height_and_rebalance(damaged, guard);
+                    return Ok(());
+                }
+                Some(c) => {
+                    let child_ovl = c.version.load(Ordering::Acquire);
+                    if is_shrinking_or_unlinked(child_ovl) {
+                        c.wait_until_not_changing();
+                    } else if child == node.child(dir) {
+                        if node.version.load(Ordering::Acquire) != node_ovl {
+                            return Err(Retry);
+                        }
+                        if self
+                            .attempt_update(key, value, node, c, child_ovl, guard)
+                            .is_ok()
+                        {
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn attempt_node_update(
+        &self,
+        value: *mut V,
+        parent: &Node<K, V>,
+        node: &Node<K, V>,
+        guard: &Guard,
+    ) -> Result<(), Retry> {
+        if value.is_null() {
+            if !node.has_value() {
+                return Ok(());
+            }
+            if node.left.load(Ordering::Acquire).is_null()
+                || node.right.load(Ordering::Acquire).is_null()
+            {
+                let damaged = {
+                    let _parent_lock = parent.lock.lock().unwrap();
+                    if is_unlinked(parent.version.load(Ordering::Acquire))
+                        || node.parent.load(Ordering::Acquire) != parent.as_ptr()
+                    {
+                        return Err(Retry);
+                    }
+                    {
+                        let _lock = node.lock.lock().unwrap();
+                        if !node.has_value() {
+                            return Ok(());
+                        }
+                        if !self.attempt_unlink_nl(parent, node, guard) {
+                            return Err(Retry);
+                        }
+                    }
+                    self.fix_height_nl(parent)
+                };
+                self.fix_height_and_rebalance(damaged, guard);
+                return Ok(());
+            }
+        }
+
+        let _lock = node.lock.lock().unwrap();
+        if is_unlinked(node.version.load(Ordering::Acquire)) {
+            return Err(Retry);
+        }
+        // A child went away since we looked: the node must be unlinked instead.
+        if value.is_null()
+            && (node.left.load(Ordering::Acquire).is_null()
+                || node.right.load(Ordering::Acquire).is_null())
+        {
+            return Err(Retry);
+        }
+        let prev = node.value.swap(value, Ordering::AcqRel);
+        if !prev.is_null() {
+            unsafe { guard.defer_unchecked(move || drop(Box::from_raw(prev))) };
+        }
+        Ok(())
+    }
+
+    /// Splices out `node`, which has at most one child. Both locks are held.
+    fn attempt_unlink_nl(&self, parent: &Node<K, V>, node: &Node<K, V>, guard: &Guard) -> bool {
+        let parent_left = parent.left.load(Ordering::Acquire);
+        let parent_right = parent.right.load(Ordering::Acquire);
+        if parent_left != node.as_ptr() && parent_right != node.as_ptr() {
+            return false;
+        }
+        let left = node.left.load(Ordering::Acquire);
+        let right = node.right.load(Ordering::Acquire);
+        if !left.is_null() && !right.is_null() {
+            return false;
+        }
+        let splice = if left.is_null() { right } else { left };
+        if parent_left == node.as_ptr() {
+            parent.left.store(splice, Ordering::Release);
+        } else {
+            parent.right.store(splice, Ordering::Release);
+        }
+        if let Some(s) = deref(splice) {
+            s.parent.store(parent.as_ptr(), Ordering::Release);
+        }
+        node.version.store(UNLINKED, Ordering::Release);
+
+        let value = node.value.swap(null_mut(), Ordering::AcqRel);
+        let node = node.as_ptr();
+        unsafe {
+            guard.defer_unchecked(move || {
+                if !value.is_null() {
+                    drop(Box::from_raw(value));
+                }
+                drop(Box::from_raw(node));
+            })
+        };
+        true
+    }
+
+    /// Updates the height of `node` if that is all it needs. Returns the next
+    /// node to repair, or null.
+    fn fix_height_nl(&self, node: &Node<K, V>) -> *mut Node<K, V> {
+        match node_condition(node) {
+            Condition::Rebalance | Condition::Unlink => node.as_ptr(),
+            Condition::Nothing => null_mut(),
+            Condition::Height(h) => {
+                node.height.store(h, Ordering::Relaxed);
+                node.parent.load(Ordering::Acquire)
+            }
+        }
+    }
+
+    fn fix_height_and_rebalance(&self, mut node: *mut Node<K, V>, guard: &Guard) {
+        while let Some(n) = deref(node) {
+            let n_parent = n.parent.load(Ordering::Acquire);
+            // The root holder is never repaired.
+            let Some(p) = deref(n_parent) else {
+                return;
+            };
+            let condition = node_condition(n);
+            if matches!(condition, Condition::Nothing)
+                || is_unlinked(n.version.load(Ordering::Acquire))
+            {
+                return;
+            }
+            if let Condition::Height(_) = condition {
+                let _lock = n.lock.lock().unwrap();
+                node = self.fix_height_nl(n);
+            } else {
+                let _parent_lock = p.lock.lock().unwrap();
+                if !is_unlinked(p.version.load(Ordering::Acquire))
+                    && n.parent.load(Ordering::Acquire) == n_parent
+                {
+                    let _lock = n.lock.lock().unwrap();
+                    node = self.rebalance_nl(p, n, guard);
+                }
+            }
+        }
+    }
+
+    fn rebalance_nl(
+        &self,
+        n_parent: &Node<K, V>,
+        n: &Node<K, V>,
+        guard: &Guard,
+    ) -> *mut Node<K, V> {
+        let nl = n.left.load(Ordering::Acquire);
+        let nr = n.right.load(Ordering::Acquire);
+        if (nl.is_null() || nr.is_null()) && !n.has_value() {
+            return if self.attempt_unlink_nl(n_parent, n, guard) {
+                self.fix_height_nl(n_parent)
+            } else {
+                n.as_ptr()
+            };
+        }
+
+        let hn = n.height.load(Ordering::Relaxed);
+        let hl0 = height(nl);
+        let hr0 = height(nr);
+        let h_repl = 1 + max(hl0, hr0);
+        let bal = hl0 - hr0;
+        if bal > 1 {
+            self.rebalance_to_right_nl(n_parent, n, deref(nl).unwrap(), hr0)
+        } else if bal < -1 {
+            self.rebalance_to_left_nl(n_parent, n, deref(nr).unwrap(), hl0)
+        } else if h_repl != hn {
+            n.height.store(h_repl, Ordering::Relaxed);
+            self.fix_height_nl(n_parent)
+        } else {
+            null_mut()
+        }
+    }
+
+    fn rebalance_to_right_nl(
+        &self,
+        n_parent: &Node<K, V>,
+        n: &Node<K, V>,
+        nl: &Node<K, V>,
+        hr0: i32,
+    ) -> *mut Node<K, V> {
+        let _left_lock = nl.lock.lock().unwrap();
+        let hl = nl.height.load(Ordering::Relaxed);
+        if hl - hr0 <= 1 {
+            return n.as_ptr();
+        }
+        let nlr = nl.right.load(Ordering::Acquire);
+        let hll0 = height(nl.left.load(Ordering::Acquire));
+        let hlr0 = height(nlr);
+        if hll0 >= hlr0 {
+            return self.rotate_right_nl(n_parent, n, nl, hr0, hll0, nlr, hlr0);
+        }
+
+        let nlr = deref(nlr).unwrap();
+        {
+            let _lock = nlr.lock.lock().unwrap();
+            let hlr = nlr.height.load(Ordering::Relaxed);
+            if hll0 >= hlr {
+                return self.rotate_right_nl(n_parent, n, nl, hr0, hll0, nlr.as_ptr(), hlr);
+            }
+            let hlrl = height(nlr.left.load(Ordering::Acquire));
+            let b = hll0 - hlrl;
+            if (-1..=1).contains(&b) && !((hll0 == 0 || hlrl == 0) && !nl.has_value()) {
+                return self.rotate_right_over_left_nl(n_parent, n, nl, hr0, hll0, nlr, hlrl);
+            }
+        }
+        // A double rotation would leave `nl` unbalanced, rotate it first.
+        self.rebalance_to_left_nl(n, nl, nlr, hll0)
+    }
+
+    fn rebalance_to_left_nl(
+        &self,
+        n_parent: &Node<K, V>,
+        n: &Node<K, V>,
+        nr: &Node<K, V>,
+        hl0: i32,
+    ) -> *mut Node<K, V> {
+        let _right_lock = nr.lock.lock().unwrap();
+        let hr = nr.height.load(Ordering::Relaxed);
+        if hl0 - hr >= -1 {
+            return n.as_ptr();
+        }
+        let nrl = nr.left.load(Ordering::Acquire);
+        let hrl0 = height(nrl);
+        let hrr0 = height(nr.right.load(Ordering::Acquire));
+        if hrr0 >= hrl0 {
+            return self.rotate_left_nl(n_parent, n, hl0, nr, nrl, hrl0, hrr0);
+        }
+
+        let nrl = deref(nrl).unwrap();
+        {
+            let _lock = nrl.lock.lock().unwrap();
+            let hrl = nrl.height.load(Ordering::Relaxed);
+            if hrr0 >= hrl {
+                return self.rotate_left_nl(n_parent, n, hl0, nr, nrl.as_ptr(), hrl, hrr0);
+            }
+            let hrlr = height(nrl.right.load(Ordering::Acquire));
+            let b = hrr0 - hrlr;
+            if (-1..=1).contains(&b) && !((hrr0 == 0 || hrlr == 0) && !nr.has_value()) {
+                return self.rotate_left_over_right_nl(n_parent, n, hl0, nr, nrl, hrr0, hrlr);
+            }
+        }
+        self.rebalance_to_right_nl(n, nr, nrl, hrr0)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_right_nl(
+        &self,
+        n_parent: &Node<K, V>,
+        n: &Node<K, V>,
+        nl: &Node<K, V>,
+        hr: i32,
+        hll: i32,
+        nlr: *mut Node<K, V>,
+        hlr: i32,
+    ) -> *mut Node<K, V> {
+        let n_parent_left = n_parent.left.load(Ordering::Acquire);
+        let ovl = n.begin_shrink();
+
+        n.left.store(nlr, Ordering::Release);
+        if let Some(nlr) = deref(nlr) {
+            nlr.parent.store(n.as_ptr(), Ordering::Release);
+        }
+        nl.right.store(n.as_ptr(), Ordering::Release);
+        n.parent.store(nl.as_ptr(), Ordering::Release);
+        if n_parent_left == n.as_ptr() {
+            n_parent.left.store(nl.as_ptr(), Ordering::Release);
+        } else {
+            n_parent.right.store(nl.as_ptr(), Ordering::Release);
+        }
+        nl.parent.store(n_parent.as_ptr(), Ordering::Release);
+
+        let h_repl = 1 + max(hlr, hr);
+        n.height.store(h_repl, Ordering::Relaxed);
+        nl.height.store(1 + max(hll, h_repl), Ordering::Relaxed);
+
+        n.end_shrink(ovl);
+
+        // Report the deepest node that may still need work.
+        if !(-1..=1).contains(&(hlr - hr)) {
+            return n.as_ptr();
+        }
+        if (nlr.is_null() || hr == 0) && !n.has_value() {
+            return n.as_ptr();
+        }
+        if !(-1..=1).contains(&(hll - h_repl)) {
+            return nl.as_ptr();
+        }
+        if hll == 0 && !nl.has_value() {
+            return nl.as_ptr();
+        }
+        self.fix_height_nl(n_parent)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_left_nl(
+        &self,
+        n_parent: &Node<K, V>,
+        n: &Node<K, V>,
+        hl: i32,
+        nr: &Node<K, V>,
+        nrl: *mut Node<K, V>,
+        hrl: i32,
+        hrr: i32,
+    ) -> *mut Node<K, V> {
+        let n_parent_left = n_parent.left.load(Ordering::Acquire);
+        let ovl = n.begin_shrink();
+
+        n.right.store(nrl, Ordering::Release);
+        if let Some(nrl) = deref(nrl) {
+            nrl.parent.store(n.as_ptr(), Ordering::Release);
+        }
+        nr.left.store(n.as_ptr(), Ordering::Release);
+        n.parent.store(nr.as_ptr(), Ordering::Release);
+        if n_parent_left == n.as_ptr() {
+            n_parent.left.store(nr.as_ptr(), Ordering::Release);
+        } else {
+            n_parent.right.store(nr.as_ptr(), Ordering::Release);
+        }
+        nr.parent.store(n_parent.as_ptr(), Ordering::Release);
+
+        let h_repl = 1 + max(hl, hrl);
+        n.height.store(h_repl, Ordering::Relaxed);
+        nr.height.store(1 + max(h_repl, hrr), Ordering::Relaxed);
+
+        n.end_shrink(ovl);
+
+        if !(-1..=1).contains(&(hrl - hl)) {
+            return n.as_ptr();
+        }
+        if (nrl.is_null() || hl == 0) && !n.has_value() {
+            return n.as_ptr();
+        }
+        if !(-1..=1).contains(&(hrr - h_repl)) {
+            return nr.as_ptr();
+        }
+        if hrr == 0 && !nr.has_value() {
+            return nr.as_ptr();
+        }
+        self.fix_height_nl(n_parent)
     }
 
-    fn get_val(&self, key: K) -> Option<V> {
-        ConcurrentMap::get(self, &key)
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_right_over_left_nl(
+        &self,
+        n_parent: &Node<K, V>,
+        n: &Node<K, V>,
+        nl: &Node<K, V>,
+        hr: i32,
+        hll: i32,
+        nlr: &Node<K, V>,
+        hlrl: i32,
+    ) -> *mut Node<K, V> {
+        let n_parent_left = n_parent.left.load(Ordering::Acquire);
+        let nlrl = nlr.left.load(Ordering::Acquire);
+        let nlrr = nlr.right.load(Ordering::Acquire);
+        let hlrr = height(nlrr);
+
+        let ovl = n.begin_shrink();
+        let left_ovl = nl.begin_shrink();
+
+        n.left.store(nlrr, Ordering::Release);
+        if let Some(nlrr) = deref(nlrr) {
+            nlrr.parent.store(n.as_ptr(), Ordering::Release);
+        }
+        nl.right.store(nlrl, Ordering::Release);
+        if let Some(nlrl) = deref(nlrl) {
+            nlrl.parent.store(nl.as_ptr(), Ordering::Release);
+        }
+        nlr.left.store(nl.as_ptr(), Ordering::Release);
+        nl.parent.store(nlr.as_ptr(), Ordering::Release);
+        nlr.right.store(n.as_ptr(), Ordering::Release);
+        n.parent.store(nlr.as_ptr(), Ordering::Release);
+        if n_parent_left == n.as_ptr() {
+            n_parent.left.store(nlr.as_ptr(), Ordering::Release);
+        } else {
+            n_parent.right.store(nlr.as_ptr(), Ordering::Release);
+        }
+        nlr.parent.store(n_parent.as_ptr(), Ordering::Release);
+
+        let h_repl = 1 + max(hlrr, hr);
+        n.height.store(h_repl, Ordering::Relaxed);
+        let hl_repl = 1 + max(hll, hlrl);
+        nl.height.store(hl_repl, Ordering::Relaxed);
+        nlr.height
+            .store(1 + max(hl_repl, h_repl), Ordering::Relaxed);
+
+        n.end_shrink(ovl);
+        nl.end_shrink(left_ovl);
+
+        if !(-1..=1).contains(&(hlrr - hr)) {
+            return n.as_ptr();
+        }
+        if (nlrr.is_null() || hr == 0) && !n.has_value() {
+            return n.as_ptr();
+        }
+        if !(-1..=1).contains(&(hl_repl - h_repl)) {
+            return nlr.as_ptr();
+        }
+        self.fix_height_nl(n_parent)
     }
 
+    #[allow(clippy::too_many_arguments)]
+    fn rotate_left_over_right_nl(
+        &self,
+        n_parent: &Node<K, V>,
+        n: &Node<K, V>,
+        hl: i32,
+        nr: &Node<K, V>,
+        nrl: &Node<K, V>,
+        hrr: i32,
+        hrlr: i32,
+    ) -> *mut Node<K, V> {
+        let n_parent_left = n_parent.left.load(Ordering::Acquire);
+        let nrll = nrl.left.load(Ordering::Acquire);
+        let nrlr = nrl.right.load(Ordering::Acquire);
+        let hrll = height(nrll);
+
+        let ovl = n.begin_shrink();
+        let right_ovl = nr.begin_shrink();
+
+        n.right.store(nrll, Ordering::Release);
+        if let Some(nrll) = deref(nrll) {
+            nrll.parent.store(n.as_ptr(), Ordering::Release);
+        }
+        nr.left.store(nrlr, Ordering::Release);
+        if let Some(nrlr) = deref(nrlr) {
+            nrlr.parent.store(nr.as_ptr(), Ordering::Release);
+        }
+        nrl.right.store(nr.as_ptr(), Ordering::Release);
+        nr.parent.store(nrl.as_ptr(), Ordering::Release);
+        nrl.left.store(n.as_ptr(), Ordering::Release);
+        n.parent.store(nrl.as_ptr(), Ordering::Release);
+        if n_parent_left == n.as_ptr() {
+            n_parent.left.store(nrl.as_ptr(), Ordering::Release);
+        } else {
+            n_parent.right.store(nrl.as_ptr(), Ordering::Release);
+        }
+        nrl.parent.store(n_parent.as_ptr(), Ordering::Release);
+
+        let h_repl = 1 + max(hl, hrll);
+        n.height.store(h_repl, Ordering::Relaxed);
+        let hr_repl = 1 + max(hrlr, hrr);
+        nr.height.store(hr_repl, Ordering::Relaxed);
+        nrl.height
+            .store(1 + max(h_repl, hr_repl), Ordering::Relaxed);
+
+        n.end_shrink(ovl);
+        nr.end_shrink(right_ovl);
+
+        if !(-1..=1).contains(&(hrll - hl)) {
+            return n.as_ptr();
+        }
+        if (nrll.is_null() || hl == 0) && !n.has_value() {
+            return n.as_ptr();
+        }
+        if !(-1..=1).contains(&(hr_repl - h_repl)) {
+            return nrl.as_ptr();
+        }
+        self.fix_height_nl(n_parent)
+    }
+}
+
+impl<K: KeyType, V: ValueType> Tree<K, V> for AvlTree<K, V> {
     fn new() -> Self {
-        <RwLockAVLTree<K, V> as ConcurrentMap<K, V>>::new()
+        AvlTree {
+            holder: unsafe { Box::from_raw(Node::alloc(None, null_mut(), null_mut())) },
+            _marker: PhantomData,
+        }
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_ordered(&self) -> Option<&dyn Ordered<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for AvlTree<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let _guard = epoch::pin();
+        let holder = &*self.holder;
+        loop {
+            let ovl = holder.version.load(Ordering::Acquire);
+            if let Ok(found) = self.attempt_get(&key, holder, CmpOrdering::Greater, ovl) {
+                return Ok(unsafe { found.as_ref() }.map(f));
+            }
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> Insert<K, V> for AvlTree<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        let guard = &epoch::pin();
+        self.update(&key, Box::into_raw(Box::new(value)), guard);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Remove<K, V> for AvlTree<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        let guard = &epoch::pin();
+        self.update(&key, null_mut(), guard);
+        Ok(())
     }
 }
 
-pub type ConcurrentAVLTree<K, V> = RwLockAVLTree<K, V>;
+impl<K: KeyType, V: ValueType> Ordered<K, V> for AvlTree<K, V> {
+    fn first(&self) -> Result<Option<(K, V)>> {
+        Ok(self.nearest(None, false, false))
+    }
+
+    fn last(&self) -> Result<Option<(K, V)>> {
+        Ok(self.nearest(None, true, false))
+    }
+
+    fn lower_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.nearest(Some(key), false, true))
+    }
+
+    fn upper_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.nearest(Some(key), true, true))
+    }
+
+    fn successor(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.nearest(Some(key), false, false))
+    }
+
+    fn predecessor(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.nearest(Some(key), true, false))
+    }
+}
+
+impl<K: KeyType, V: ValueType> AvlTree<K, V> {
+    /// The entry with the closest key below `key` if `below`, else above
+    /// it, `key` itself counting if `inclusive`. Without a key, the last or
+    /// first entry.
+    fn nearest(&self, mut key: Option<K>, below: bool, mut inclusive: bool) -> Option<(K, V)> {
+        let _guard = epoch::pin();
+        loop {
+            let Ok(node) = self.attempt_nearest(key.as_ref(), below, inclusive) else {
+                continue;
+            };
+            let node = deref(node)?;
+            if let Some(value) = unsafe { node.value.load(Ordering::Acquire).as_ref() } {
+                return Some((node.key().clone(), value.clone()));
+            }
+            // A routing node, or removed since: look past it.
+            key = Some(node.key().clone());
+            inclusive = false;
+        }
+    }
+
+    /// The node `nearest` is after, routing nodes included. Validates every
+    /// hop like `attempt_get`, but starts over from the root on any change
+    /// instead of from the last valid node, since the closest key seen so
+    /// far may have moved.
+    fn attempt_nearest(
+        &self,
+        key: Option<&K>,
+        below: bool,
+        inclusive: bool,
+    ) -> Result<*mut Node<K, V>, Retry> {
+        let mut best = null_mut();
+        let mut node = &*self.holder;
+        let mut node_ovl = node.version.load(Ordering::Acquire);
+        let mut dir = CmpOrdering::Greater;
+        loop {
+            let child = node.child(dir);
+            let Some(c) = deref(child) else {
+                if node.version.load(Ordering::Acquire) != node_ovl {
+                    return Err(Retry);
+                }
+                return Ok(best);
+            };
+            let child_ovl = c.version.load(Ordering::Acquire);
+            if is_shrinking_or_unlinked(child_ovl) {
+                c.wait_until_not_changing();
+                return Err(Retry);
+            }
+            if child != node.child(dir) || node.version.load(Ordering::Acquire) != node_ovl {
+                return Err(Retry);
+            }
+            // Without a key every node is closer than the ones before it.
+            let order = match key {
+                Some(key) => c.key().cmp(key),
+                None if below => CmpOrdering::Less,
+                None => CmpOrdering::Greater,
+            };
+            if order == CmpOrdering::Equal && inclusive {
+                return Ok(child);
+            }
+            let closer = if below {
+                order == CmpOrdering::Less
+            } else {
+                order == CmpOrdering::Greater
+            };
+            if closer {
+                best = child;
+            }
+            dir = if order == CmpOrdering::Less || (order == CmpOrdering::Equal && !below) {
+                CmpOrdering::Greater
+            } else {
+                CmpOrdering::Less
+            };
+            node = c;
+            node_ovl = child_ovl;
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> AvlTree<K, V> {
+    /// Calls `f` on every node reachable from the root. Nodes a concurrent
+    /// rotation moves may be missed or visited twice.
+    fn for_each_node(&self, _guard: &Guard, mut f: impl FnMut(&Node<K, V>)) {
+        let mut stack = vec![self.holder.right.load(Ordering::Acquire)];
+        while let Some(node) = stack.pop() {
+            if let Some(node) = deref(node) {
+                f(node);
+                stack.push(node.left.load(Ordering::Acquire));
+                stack.push(node.right.load(Ordering::Acquire));
+            }
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> Len for AvlTree<K, V> {
+    fn len(&self) -> usize {
+        let mut len = 0;
+        self.for_each_node(&epoch::pin(), |node| len += node.has_value() as usize);
+        len
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for AvlTree<K, V> {
+    fn clear(&self) {
+        let guard = &epoch::pin();
+        let mut keys = Vec::new();
+        self.for_each_node(guard, |node| {
+            if node.has_value() {
+                keys.push(node.key().clone());
+            }
+        });
+        for key in keys.iter() {
+            self.update(key, null_mut(), guard);
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for AvlTree<K, V> {
+    fn memory_usage(&self) -> usize {
+        let mut bytes = mem::size_of::<Node<K, V>>();
+        self.for_each_node(&epoch::pin(), |node| {
+            bytes += mem::size_of::<Node<K, V>>();
+            if node.has_value() {
+                bytes += mem::size_of::<V>();
+            }
+        });
+        bytes
+    }
+}
+
+impl<K, V> Drop for AvlTree<K, V> {
+    fn drop(&mut self) {
+        let mut stack = vec![self.holder.right.load(Ordering::Relaxed)];
+        while let Some(node) = stack.pop() {
+            if node.is_null() {
+                continue;
+            }
+            let node = unsafe { Box::from_raw(node) };
+            stack.push(node.left.load(Ordering::Relaxed));
+            stack.push(node.right.load(Ordering::Relaxed));
+            let value = node.value.load(Ordering::Relaxed);
+            if !value.is_null() {
+                drop(unsafe { Box::from_raw(value) });
+            }
+        }
+    }
+}
+
+pub type ConcurrentAVLTree<K, V> = AvlTree<K, V>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::Arc, thread};
+
+    /// Checks the subtree under `node` is a quiescent AVL tree with correct
+    /// heights and parent links, and returns its height.
+    fn check_balanced(node: *mut Node<usize, usize>, parent: *mut Node<usize, usize>) -> i32 {
+        let Some(n) = deref(node) else {
+            return 0;
+        };
+        assert_eq!(n.parent.load(Ordering::Relaxed), parent);
+        assert_eq!(
+            n.version.load(Ordering::Relaxed) & (SHRINKING | UNLINKED),
+            0
+        );
+        let left = n.left.load(Ordering::Relaxed);
+        let right = n.right.load(Ordering::Relaxed);
+        if let Some(l) = deref(left) {
+            assert!(l.key() < n.key());
+        }
+        if let Some(r) = deref(right) {
+            assert!(r.key() > n.key());
+        }
+        let hl = check_balanced(left, node);
+        let hr = check_balanced(right, node);
+        assert!(
+            (hl - hr).abs() <= 1,
+            "unbalanced at {}: {hl} vs {hr}",
+            n.key()
+        );
+        assert_eq!(n.height.load(Ordering::Relaxed), 1 + max(hl, hr));
+        1 + max(hl, hr)
+    }
+
+    #[test]
+    fn sequential_inserts_stay_balanced() {
+        let tree = AvlTree::<usize, usize>::new();
+        for key in 0..4096usize {
+            tree.put(key, key).unwrap();
+        }
+        let root = tree.holder.right.load(Ordering::Relaxed);
+        let height = check_balanced(root, tree.holder.as_ptr());
+        // An AVL tree of n nodes is at most 1.44 log2(n + 2) high.
+        assert!(height <= 18, "height {height} for 4096 keys");
+        for key in (0..4096usize).rev() {
+            tree.put(key, key + 1).unwrap();
+        }
+        check_balanced(
+            tree.holder.right.load(Ordering::Relaxed),
+            tree.holder.as_ptr(),
+        );
+        assert_eq!(tree.len(), 4096);
+    }
+
+    #[test]
+    fn matches_model() {
+        let tree = AvlTree::<usize, usize>::new();
+        let mut model = BTreeMap::new();
+        assert_eq!(tree.first().unwrap(), None);
+        assert_eq!(tree.last().unwrap(), None);
+        for i in 0..3000usize {
+            let key = i * 7919 % 3000 * 2;
+            tree.put(key, i).unwrap();
+            model.insert(key, i);
+            if i % 3 == 0 {
+                let key = i * 31 % 3000 * 2;
+                tree.remove(key).unwrap();
+                model.remove(&key);
+            }
+        }
+        for key in 0..6001usize {
+            assert_eq!(
+                tree.get(key).unwrap(),
+                model.get(&key).copied(),
+                "key {key}"
+            );
+        }
+        assert_eq!(tree.len(), model.len());
+        let entry = |e: Option<(&usize, &usize)>| e.map(|(k, v)| (*k, *v));
+        assert_eq!(tree.first().unwrap(), entry(model.first_key_value()));
+        assert_eq!(tree.last().unwrap(), entry(model.last_key_value()));
+        // Even keys hit entries or removed keys, odd ones fall between them.
+        for key in (0..6001usize).step_by(7) {
+            assert_eq!(
+                tree.lower_bound(key).unwrap(),
+                entry(model.range(key..).next())
+            );
+            assert_eq!(
+                tree.upper_bound(key).unwrap(),
+                entry(model.range(..=key).next_back())
+            );
+            assert_eq!(
+                tree.successor(key).unwrap(),
+                entry(model.range(key + 1..).next())
+            );
+            assert_eq!(
+                tree.predecessor(key).unwrap(),
+                entry(model.range(..key).next_back())
+            );
+        }
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.first().unwrap(), None);
+        check_balanced(
+            tree.holder.right.load(Ordering::Relaxed),
+            tree.holder.as_ptr(),
+        );
+    }
+
+    #[test]
+    fn concurrent_inserts_and_removes() {
+        let tree = Arc::new(AvlTree::<usize, usize>::new());
+        let threads = 4;
+        let per_thread = 5000;
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    for i in 0..per_thread {
+                        tree.put(i * threads + id, id).unwrap();
+                    }
+                    for i in (0..per_thread).step_by(2) {
+                        tree.remove(i * threads + id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(tree.len(), threads * per_thread / 2);
+        for key in 0..threads * per_thread {
+            let expected = (key / threads % 2 == 1).then_some(key % threads);
+            assert_eq!(tree.get(key).unwrap(), expected, "key {key}");
+        }
+        let first = tree.first().unwrap();
+        assert_eq!(first, Some((threads, 0)));
+    }
+}
@@ -4,25 +4,220 @@ use std::{
     cmp,
     fmt::{Debug, Display},
     marker::PhantomData,
-    ptr::null_mut,
-    sync::atomic::{self, AtomicPtr},
+    mem::ManuallyDrop,
+    ptr::{self, null_mut},
+    sync::atomic::{self, AtomicPtr, AtomicU64},
 };
 
+use crossbeam::epoch::{self, Atomic, Owned};
+
 use crate::{
+    error::{Result, TreeError},
     fix_sized_key::{FixSizedKey, FixSizedKeyParams},
-    interfaces::{KeyType, Tree, ValueType},
+    interfaces::{
+        Clear, Insert, KeyType, Len, MemoryUsage, Ordered, PointRead, ReadModifyWrite, Tree,
+        ValueType,
+    },
 };
 
-pub trait TreeParams: FixSizedKeyParams {
+pub trait TreeParams {
     type ValueType: Debug + Display + PartialEq;
-    type IKeyType: Debug
-        + Clone
-        + Display
-        + Ord
-        + Into<String>
-        + From<String>
-        + for<'a> From<&'a str>
-        + From<&'static str>;
+    /// How a node holds its value: `Boxed` for any value, `Inline` for
+    /// values that fit a word.
+    type IValueType: ValueCell<Self::ValueType>;
+    type IKeyType: Debug + Clone + Display + Ord;
+    /// Whether `IKeyType` sorts keys like the key type they are converted
+    /// from. The tree is only `Ordered` if it does.
+    const KEY_ORDER_PRESERVED: bool;
+}
+
+/// A node's value, always replaced as a whole.
+pub trait ValueCell<V>: Send + Sync {
+    /// Bytes a value takes outside of the node.
+    const HEAP_SIZE: usize;
+
+    fn new(value: V) -> Self;
+    fn into_inner(self) -> V;
+    fn read<R>(&self, f: impl FnOnce(&V) -> R) -> R;
+    fn store(&self, value: V);
+    /// Replaces the value with what `f` makes of it, retrying if it changes
+    /// in between. Returns the value replaced, or the one `f` declined to
+    /// replace.
+    fn update(&self, f: impl FnMut(&V) -> Option<V>) -> Result<V, V>;
+}
+
+/// Value allocated on its own, swapped by CAS on the pointer to it.
+/// Replaced values are retired through the epoch, so readers still cloning
+/// them stay safe and their addresses are not reused under a pending CAS.
+pub struct Boxed<V>(Atomic<V>);
+
+impl<V: Clone + Send + Sync> ValueCell<V> for Boxed<V> {
+    const HEAP_SIZE: usize = std::mem::size_of::<V>();
+
+    fn new(value: V) -> Self {
+        Boxed(Atomic::new(value))
+    }
+
+    fn into_inner(self) -> V {
+        let this = ManuallyDrop::new(self);
+        let value = this
+            .0
+            .load(atomic::Ordering::Relaxed, unsafe { epoch::unprotected() });
+        *unsafe { value.into_owned() }.into_box()
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&V) -> R) -> R {
+        let guard = &epoch::pin();
+        f(unsafe { self.0.load(atomic::Ordering::Acquire, guard).deref() })
+    }
+
+    fn store(&self, value: V) {
+        let guard = &epoch::pin();
+        let previous = self
+            .0
+            .swap(Owned::new(value), atomic::Ordering::AcqRel, guard);
+        unsafe { guard.defer_destroy(previous) };
+    }
+
+    fn update(&self, mut f: impl FnMut(&V) -> Option<V>) -> Result<V, V> {
+        let guard = &epoch::pin();
+        let mut replacement: Option<Owned<V>> = None;
+        loop {
+            let current = self.0.load(atomic::Ordering::Acquire, guard);
+            let previous = unsafe { current.deref() }.clone();
+            let Some(value) = f(&previous) else {
+                return Err(previous);
+            };
+            let new = match replacement.take() {
+                Some(mut owned) => {
+                    *owned = value;
+                    owned
+                }
+                None => Owned::new(value),
+            };
+            match self.0.compare_exchange(
+                current,
+                new,
+                atomic::Ordering::AcqRel,
+                atomic::Ordering::Acquire,
+                guard,
+            ) {
+                Ok(_) => {
+                    unsafe { guard.defer_destroy(current) };
+                    return Ok(previous);
+                }
+                Err(error) => replacement = Some(error.new),
+            }
+        }
+    }
+}
+
+impl<V> Drop for Boxed<V> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            drop(self.0.load(atomic::Ordering::Relaxed, guard).into_owned());
+        }
+    }
+}
+
+/// Values that pack into a word, to be held by `Inline`.
+pub trait InlineValue: Copy + Send + Sync {
+    fn to_word(self) -> u64;
+    fn from_word(word: u64) -> Self;
+}
+
+impl InlineValue for u64 {
+    fn to_word(self) -> u64 {
+        self
+    }
+
+    fn from_word(word: u64) -> Self {
+        word
+    }
+}
+
+impl InlineValue for usize {
+    fn to_word(self) -> u64 {
+        self as u64
+    }
+
+    fn from_word(word: u64) -> Self {
+        word as usize
+    }
+}
+
+/// Byte array of up to 8 bytes, shown in hex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bytes<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Display for Bytes<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Only up to 8 bytes: `8 - N` does not evaluate for longer arrays.
+impl<const N: usize> InlineValue for Bytes<N>
+where
+    [(); 8 - N]: Sized,
+{
+    fn to_word(self) -> u64 {
+        let mut word = [0; 8];
+        word[..N].copy_from_slice(&self.0);
+        u64::from_ne_bytes(word)
+    }
+
+    fn from_word(word: u64) -> Self {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&word.to_ne_bytes()[..N]);
+        Bytes(bytes)
+    }
+}
+
+/// Value stored in the node itself, swapped by CAS on its word.
+pub struct Inline<V>(AtomicU64, PhantomData<V>);
+
+impl<V: InlineValue> ValueCell<V> for Inline<V> {
+    const HEAP_SIZE: usize = 0;
+
+    fn new(value: V) -> Self {
+        Inline(AtomicU64::new(value.to_word()), PhantomData)
+    }
+
+    fn into_inner(self) -> V {
+        V::from_word(self.0.into_inner())
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&V) -> R) -> R {
+        f(&V::from_word(self.0.load(atomic::Ordering::Acquire)))
+    }
+
+    fn store(&self, value: V) {
+        self.0.store(value.to_word(), atomic::Ordering::Release);
+    }
+
+    fn update(&self, mut f: impl FnMut(&V) -> Option<V>) -> Result<V, V> {
+        let mut current = self.0.load(atomic::Ordering::Acquire);
+        loop {
+            let previous = V::from_word(current);
+            let Some(replacement) = f(&previous) else {
+                return Err(previous);
+            };
+            match self.0.compare_exchange(
+                current,
+                replacement.to_word(),
+                atomic::Ordering::AcqRel,
+                atomic::Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(previous),
+                Err(word) => current = word,
+            }
+        }
+    }
 }
 
 pub trait UnsafeGet<T> {
@@ -37,24 +232,38 @@ impl<T> UnsafeGet<T> for AtomicPtr<T> {
     }
 }
 
-#[derive(Debug)]
 pub struct Node<T: TreeParams> {
     _phantom: PhantomData<T>,
     pub key: T::IKeyType,
-    pub p_value: AtomicPtr<T::ValueType>,
+    pub value: T::IValueType,
     pub p_left: AtomicPtr<Node<T>>,
     pub p_right: AtomicPtr<Node<T>>,
 }
 
 impl<T: TreeParams> Node<T> {
-    pub fn new_ptr(key: T::IKeyType) -> *mut Self {
+    pub fn new_ptr(key: T::IKeyType, value: T::ValueType) -> Result<*mut Self> {
         let layout = Layout::from_size_align(std::mem::size_of::<Node<T>>(), 64).unwrap();
         unsafe {
             let ptr = alloc_zeroed(layout) as *mut Node<T>;
-            (*ptr).key = key;
-            return ptr;
+            if ptr.is_null() {
+                return Err(TreeError::OutOfMemory);
+            }
+            ptr::addr_of_mut!((*ptr).key).write(key);
+            ptr::addr_of_mut!((*ptr).value).write(T::IValueType::new(value));
+            return Ok(ptr);
         }
     }
+
+    /// Frees a node that never made it into the tree, handing back its value.
+    unsafe fn into_value(ptr: *mut Self) -> T::ValueType {
+        ptr::drop_in_place(ptr::addr_of_mut!((*ptr).key));
+        let value = ptr::read(ptr::addr_of!((*ptr).value)).into_inner();
+        dealloc(
+            ptr as *mut u8,
+            Layout::from_size_align(std::mem::size_of::<Node<T>>(), 64).unwrap(),
+        );
+        value
+    }
 }
 
 pub struct Cursor<T: TreeParams> {
@@ -63,7 +272,6 @@ pub struct Cursor<T: TreeParams> {
     pub parent: *mut Node<T>,
     pub node: *mut Node<T>,
     pub key: T::IKeyType,
-    pub p_value: *mut T::ValueType,
 }
 
 impl<T: TreeParams> Cursor<T> {
@@ -72,7 +280,6 @@ impl<T: TreeParams> Cursor<T> {
             parent: null_mut(),
             node: null_mut(),
             key,
-            p_value: null_mut(),
             root: root as *const Node<T>,
             _phantom: PhantomData,
         }
@@ -85,26 +292,82 @@ impl<T: TreeParams> Cursor<T> {
 
     pub fn find_from(&mut self, node: *mut Node<T>) -> bool {
         self.node = node;
-        while !self.node.is_null() {
-            unsafe {
-                match (*self.node).key.cmp(&self.key) {
-                    cmp::Ordering::Equal => {
-                        self.p_value = (*self.node).p_value.get();
-                        return true;
-                    }
-                    cmp::Ordering::Less => {
-                        self.parent = self.node;
-                        self.node = (*self.node).p_left.get();
-                    }
-                    cmp::Ordering::Greater => {
-                        self.parent = self.node;
-                        self.node = (*self.node).p_right.get();
-                    }
+        while self.step() {}
+        return !self.node.is_null();
+    }
+
+    /// Moves to the node with the closest key below `key` if `below`, else
+    /// above it, `key` itself counting if `inclusive`. Returns false if
+    /// there is none, `node` then being null.
+    pub fn find_nearest(&mut self, below: bool, inclusive: bool) -> bool {
+        self.node = null_mut();
+        let mut next = self.root as *mut Node<T>;
+        while !next.is_null() {
+            let order = unsafe { (*next).key.cmp(&self.key) };
+            if order == cmp::Ordering::Equal && inclusive {
+                self.node = next;
+                break;
+            }
+            let closer = if below {
+                order == cmp::Ordering::Less
+            } else {
+                order == cmp::Ordering::Greater
+            };
+            if closer {
+                self.node = next;
+            }
+            // Larger keys are on the left.
+            let larger = if below {
+                order == cmp::Ordering::Less
+            } else {
+                order != cmp::Ordering::Greater
+            };
+            next = unsafe {
+                if larger {
+                    (*next).p_left.get()
+                } else {
+                    (*next).p_right.get()
+                }
+            };
+        }
+        return !self.node.is_null();
+    }
+
+    /// Moves one level down. Returns false once the search is over, `node`
+    /// being the match or null if there is none.
+    #[inline(always)]
+    fn step(&mut self) -> bool {
+        if self.node.is_null() {
+            return false;
+        }
+        unsafe {
+            match (*self.node).key.cmp(&self.key) {
+                cmp::Ordering::Equal => return false,
+                cmp::Ordering::Less => {
+                    self.parent = self.node;
+                    self.node = (*self.node).p_left.get();
+                }
+                cmp::Ordering::Greater => {
+                    self.parent = self.node;
+                    self.node = (*self.node).p_right.get();
                 }
             }
         }
-        return false;
+        return true;
+    }
+}
+
+/// Lookups `multi_get` walks down the tree together.
+const PREFETCH_GROUP: usize = 16;
+
+#[inline(always)]
+fn prefetch<T>(ptr: *const T) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::x86_64::_mm_prefetch(ptr as *const i8, std::arch::x86_64::_MM_HINT_T0);
     }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = ptr;
 }
 
 /// Lock-free Binary Search Tree, CAS-based, fixed key size
@@ -119,8 +382,10 @@ unsafe impl<T: TreeParams> Sync for LockFreeBinarySearchTree<T> {}
 
 impl<T: TreeParams<ValueType = V>, K, V> Tree<K, V> for LockFreeBinarySearchTree<T>
 where
-    K: Into<T::IKeyType> + KeyType,
+    K: TryInto<T::IKeyType> + KeyType,
+    TreeError: From<<K as TryInto<T::IKeyType>>::Error>,
     V: ValueType,
+    T::IKeyType: Into<K>,
 {
     fn new() -> Self {
         LockFreeBinarySearchTree {
@@ -129,67 +394,153 @@ where
         }
     }
 
-    fn put(&self, key: K, value: V) {
-        let new_p_value = Box::into_raw(Box::new(value.into()));
-        let mut new_p_node: *mut Node<T> = null_mut();
-        let key = key.into();
-
-        loop {
-            let rootptr = self.root.get();
-            if rootptr.is_null() {
-                new_p_node = Node::<T>::new_ptr(key.clone());
-                unsafe {
-                    (*new_p_node).p_value = AtomicPtr::new(new_p_value);
+    /// Links the nodes up directly, rooting every subtree at the median of
+    /// its keys, so the tree comes out balanced.
+    fn bulk_load(items: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        let mut items = items
+            .into_iter()
+            .map(|(key, value)| Ok((key.try_into()?, value)))
+            .collect::<Result<Vec<(T::IKeyType, V)>>>()?;
+        // Fixed-size keys compared as integers do not keep the order of `K`.
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut nodes = Vec::with_capacity(items.len());
+        for (key, value) in items {
+            match Node::<T>::new_ptr(key, value) {
+                Ok(node) => nodes.push(node),
+                Err(error) => {
+                    for node in nodes {
+                        drop(unsafe { Node::into_value(node) });
+                    }
+                    return Err(error);
                 }
-                if let Ok(_) = self.root.compare_exchange(
-                    null_mut(),
-                    new_p_node,
-                    atomic::Ordering::Release,
-                    atomic::Ordering::Relaxed,
-                ) {
-                    return;
+            }
+        }
+        Ok(LockFreeBinarySearchTree {
+            root: AtomicPtr::new(Self::link_balanced(&nodes)),
+            _phantom: PhantomData,
+        })
+    }
+
+    fn as_read_modify_write(&self) -> Option<&dyn ReadModifyWrite<K, V>> {
+        Some(self)
+    }
+
+    fn as_ordered(&self) -> Option<&dyn Ordered<K, V>> {
+        if T::KEY_ORDER_PRESERVED {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: TreeParams<ValueType = V>, K, V> PointRead<K, V> for LockFreeBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType> + KeyType,
+    TreeError: From<<K as TryInto<T::IKeyType>>::Error>,
+    V: ValueType,
+{
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let key = key.try_into()?;
+        let _guard = &epoch::pin();
+        let root = self.root.get();
+        if root.is_null() {
+            return Ok(None);
+        }
+        let mut cursor = Cursor::<T>::new(key, root);
+        if cursor.find() {
+            return Ok(Some(unsafe { (*cursor.node).value.read(f) }));
+        }
+        return Ok(None);
+    }
+
+    /// Moves a group of lookups down the tree one level at a time, each
+    /// prefetching its next node, so their cache misses overlap instead of
+    /// being taken one after the other.
+    fn multi_get(&self, keys: &[K]) -> Result<Vec<Option<V>>> {
+        let _guard = &epoch::pin();
+        let root = self.root.get();
+        let mut result = Vec::with_capacity(keys.len());
+        let mut cursors = Vec::with_capacity(PREFETCH_GROUP);
+        for group in keys.chunks(PREFETCH_GROUP) {
+            cursors.clear();
+            for key in group {
+                let mut cursor = Cursor::<T>::new(key.clone().try_into()?, root);
+                cursor.node = root;
+                cursors.push(cursor);
+            }
+            let mut searching = true;
+            while searching {
+                searching = false;
+                for cursor in cursors.iter_mut() {
+                    if cursor.step() {
+                        prefetch(cursor.node);
+                        searching = true;
+                    }
                 }
-            } else {
-                break;
             }
+            result.extend(cursors.iter().map(|cursor| {
+                let node = unsafe { cursor.node.as_ref() }?;
+                Some(node.value.read(V::clone))
+            }));
         }
+        Ok(result)
+    }
+}
+
+impl<T: TreeParams<ValueType = V>, K, V> Insert<K, V> for LockFreeBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType> + KeyType,
+    TreeError: From<<K as TryInto<T::IKeyType>>::Error>,
+    V: ValueType,
+{
+    fn put(&self, key: K, value: V) -> Result<()> {
+        let key = key.try_into()?;
+        // Moves into the new node once there is one.
+        let mut value = Some(value);
+        let mut new_p_node: *mut Node<T> = null_mut();
+        let _guard = &epoch::pin();
+
+        // Loaded once: a `clear` may detach it, but then the whole put
+        // happens before the clear.
+        let root = loop {
+            let root = self.root.get();
+            if !root.is_null() {
+                break root;
+            }
+            if new_p_node.is_null() {
+                new_p_node = Node::<T>::new_ptr(key.clone(), value.take().unwrap())?;
+            }
+            if let Ok(_) = self.root.compare_exchange(
+                null_mut(),
+                new_p_node,
+                atomic::Ordering::Release,
+                atomic::Ordering::Relaxed,
+            ) {
+                return Ok(());
+            }
+        };
 
-        let mut cursor = Cursor::<T>::new(key, self.root.get());
+        let mut cursor = Cursor::<T>::new(key, root);
         loop {
             if cursor.find_from(if cursor.parent.is_null() {
-                self.root.get()
+                root
             } else {
                 cursor.parent
             }) {
                 // perform update
                 unsafe {
-                    loop {
-                        if let Ok(_) = (*cursor.node).p_value.compare_exchange(
-                            cursor.p_value,
-                            new_p_value,
-                            atomic::Ordering::Release,
-                            atomic::Ordering::Relaxed,
-                        ) {
-                            break;
-                        }
-                        cursor.p_value = (*cursor.node).p_value.get();
-                    }
-                    dealloc(cursor.p_value as *mut u8, Layout::new::<T::ValueType>());
-                    if !new_p_node.is_null() {
-                        dealloc(
-                            new_p_node as *mut u8,
-                            Layout::from_size_align(std::mem::size_of::<Node<T>>(), 64).unwrap(),
-                        );
-                    }
-                    return;
+                    let value = match value.take() {
+                        Some(value) => value,
+                        None => Node::into_value(new_p_node),
+                    };
+                    (*cursor.node).value.store(value);
+                    return Ok(());
                 }
             } else {
                 // perform insert
                 if new_p_node.is_null() {
-                    new_p_node = Node::<T>::new_ptr(cursor.key.clone());
-                    unsafe {
-                        (*new_p_node).p_value = AtomicPtr::new(new_p_value);
-                    }
+                    new_p_node = Node::<T>::new_ptr(cursor.key.clone(), value.take().unwrap())?;
                 }
                 unsafe {
                     let original = if (*cursor.parent).key < cursor.key {
@@ -204,27 +555,271 @@ where
                         atomic::Ordering::Relaxed,
                     ) {
                         cursor.node = new_p_node;
-                        return;
+                        return Ok(());
                     }
                 }
             }
         }
     }
+}
+
+impl<T: TreeParams<ValueType = V>, K, V> ReadModifyWrite<K, V> for LockFreeBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType> + KeyType,
+    TreeError: From<<K as TryInto<T::IKeyType>>::Error>,
+    V: ValueType,
+{
+    fn insert_if_absent(&self, key: K, value: V) -> Result<bool> {
+        let key = key.try_into()?;
+        Ok(self.find_or_insert(key, value)?.is_none())
+    }
+
+    fn compare_exchange(&self, key: K, expected: &V, new: V) -> Result<()> {
+        let key = key.try_into()?;
+        self.swap_value(key, |current| (current == expected).then(|| new.clone()))
+            .map(|_| ())
+            .map_err(|_| TreeError::Conflict)
+    }
+
+    fn update(&self, key: K, f: &mut dyn FnMut(&V) -> V) -> Result<Option<V>> {
+        let key = key.try_into()?;
+        Ok(self.swap_value(key, |current| Some(f(current))).ok())
+    }
+}
+
+/// Keys come back in the order of `T::IKeyType`, so `as_ordered` withholds
+/// this unless `T::KEY_ORDER_PRESERVED`.
+impl<T: TreeParams<ValueType = V>, K, V> Ordered<K, V> for LockFreeBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType> + KeyType,
+    TreeError: From<<K as TryInto<T::IKeyType>>::Error>,
+    V: ValueType,
+    T::IKeyType: Into<K>,
+{
+    fn first(&self) -> Result<Option<(K, V)>> {
+        let _guard = &epoch::pin();
+        Ok(Self::entry(self.extreme(false)))
+    }
+
+    fn last(&self) -> Result<Option<(K, V)>> {
+        let _guard = &epoch::pin();
+        Ok(Self::entry(self.extreme(true)))
+    }
 
-    fn get(&self, key: K) -> Option<&V> {
+    fn lower_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        self.nearest(key, false, true)
+    }
+
+    fn upper_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        self.nearest(key, true, true)
+    }
+
+    fn successor(&self, key: K) -> Result<Option<(K, V)>> {
+        self.nearest(key, false, false)
+    }
+
+    fn predecessor(&self, key: K) -> Result<Option<(K, V)>> {
+        self.nearest(key, true, false)
+    }
+}
+
+impl<T: TreeParams<ValueType = V>, V: ValueType> LockFreeBinarySearchTree<T> {
+    fn nearest<K>(&self, key: K, below: bool, inclusive: bool) -> Result<Option<(K, V)>>
+    where
+        K: TryInto<T::IKeyType>,
+        TreeError: From<K::Error>,
+        T::IKeyType: Into<K>,
+    {
+        let _guard = &epoch::pin();
+        let mut cursor = Cursor::<T>::new(key.try_into()?, self.root.get());
+        cursor.find_nearest(below, inclusive);
+        Ok(Self::entry(cursor.node))
+    }
+
+    /// The node with the largest key if `largest`, else the smallest.
+    fn extreme(&self, largest: bool) -> *mut Node<T> {
+        let mut node = self.root.get();
+        while !node.is_null() {
+            // Larger keys are on the left.
+            let next = unsafe {
+                if largest {
+                    (*node).p_left.get()
+                } else {
+                    (*node).p_right.get()
+                }
+            };
+            if next.is_null() {
+                break;
+            }
+            node = next;
+        }
+        node
+    }
+
+    fn entry<K>(node: *mut Node<T>) -> Option<(K, V)>
+    where
+        T::IKeyType: Into<K>,
+    {
+        let node = unsafe { node.as_ref() }?;
+        Some((node.key.clone().into(), node.value.read(V::clone)))
+    }
+}
+
+impl<T: TreeParams> LockFreeBinarySearchTree<T> {
+    fn node_layout() -> Layout {
+        Layout::from_size_align(std::mem::size_of::<Node<T>>(), 64).unwrap()
+    }
+
+    /// Links `nodes`, sorted by key, into a tree rooted at their median.
+    /// Larger keys go left, as `Cursor` expects.
+    fn link_balanced(nodes: &[*mut Node<T>]) -> *mut Node<T> {
+        if nodes.is_empty() {
+            return null_mut();
+        }
+        let mid = nodes.len() / 2;
+        let node = nodes[mid];
+        unsafe {
+            (*node).p_left = AtomicPtr::new(Self::link_balanced(&nodes[mid + 1..]));
+            (*node).p_right = AtomicPtr::new(Self::link_balanced(&nodes[..mid]));
+        }
+        node
+    }
+
+    /// Frees every node below `root`, which no thread may reach anymore.
+    unsafe fn free_nodes(root: *mut Node<T>) {
+        Self::for_each_node(root, |node| {
+            ptr::drop_in_place(node);
+            dealloc(node as *mut u8, Self::node_layout());
+        });
+    }
+
+    /// Calls `f` on every node below `root`.
+    fn for_each_node(root: *mut Node<T>, mut f: impl FnMut(*mut Node<T>)) {
+        let mut pending = vec![root];
+        while let Some(node) = pending.pop() {
+            if node.is_null() {
+                continue;
+            }
+            unsafe {
+                pending.push((*node).p_right.get());
+                pending.push((*node).p_left.get());
+            }
+            f(node);
+        }
+    }
+
+    /// The node holding `key`, or `None` after inserting a new one holding
+    /// `value`. Fails if a new node cannot be allocated.
+    fn find_or_insert(
+        &self,
+        key: T::IKeyType,
+        value: T::ValueType,
+    ) -> Result<Option<*mut Node<T>>> {
+        let new_p_node = Node::<T>::new_ptr(key.clone(), value)?;
+        let _guard = &epoch::pin();
+
+        let root = loop {
+            let root = self.root.get();
+            if !root.is_null() {
+                break root;
+            }
+            if self
+                .root
+                .compare_exchange(
+                    null_mut(),
+                    new_p_node,
+                    atomic::Ordering::Release,
+                    atomic::Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return Ok(None);
+            }
+        };
+
+        let mut cursor = Cursor::<T>::new(key, root);
+        loop {
+            if cursor.find_from(if cursor.parent.is_null() {
+                root
+            } else {
+                cursor.parent
+            }) {
+                drop(unsafe { Node::into_value(new_p_node) });
+                return Ok(Some(cursor.node));
+            }
+            unsafe {
+                let original = if (*cursor.parent).key < cursor.key {
+                    &(*cursor.parent).p_left
+                } else {
+                    &(*cursor.parent).p_right
+                };
+                if original
+                    .compare_exchange(
+                        null_mut(),
+                        new_p_node,
+                        atomic::Ordering::Release,
+                        atomic::Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+impl<T: TreeParams<ValueType = V>, V: ValueType> LockFreeBinarySearchTree<T> {
+    /// Swaps the value under `key` for what `f` makes of it. Returns the
+    /// value replaced, or the one `f` declined to replace.
+    fn swap_value(&self, key: T::IKeyType, f: impl FnMut(&V) -> Option<V>) -> Result<V, Option<V>> {
+        let _guard = &epoch::pin();
         let root = self.root.get();
         if root.is_null() {
-            return None;
+            return Err(None);
         }
-        let mut cursor = Cursor::<T>::new(key.into(), root);
-        if cursor.find() && !cursor.p_value.is_null() {
-            return Some(unsafe { &*cursor.p_value });
+        let mut cursor = Cursor::<T>::new(key, root);
+        if !cursor.find() {
+            return Err(None);
         }
-        return None;
+        unsafe { (*cursor.node).value.update(f) }.map_err(Some)
+    }
+}
+
+impl<T: TreeParams> Len for LockFreeBinarySearchTree<T> {
+    fn len(&self) -> usize {
+        let _guard = &epoch::pin();
+        let mut len = 0;
+        Self::for_each_node(self.root.get(), |_| len += 1);
+        len
     }
+}
+
+impl<T: TreeParams> Clear for LockFreeBinarySearchTree<T> {
+    /// Unlinks the whole tree and retires it through the epoch, so operations
+    /// still walking it stay safe. Nodes they link into it meanwhile are
+    /// freed with it.
+    fn clear(&self) {
+        let guard = &epoch::pin();
+        let root = self.root.swap(null_mut(), atomic::Ordering::AcqRel);
+        unsafe { guard.defer_unchecked(move || Self::free_nodes(root)) };
+    }
+}
 
-    fn remove(&self, _key: K) {
-        todo!();
+impl<T: TreeParams> Drop for LockFreeBinarySearchTree<T> {
+    fn drop(&mut self) {
+        unsafe { Self::free_nodes(*self.root.get_mut()) };
+    }
+}
+
+impl<T: TreeParams> MemoryUsage for LockFreeBinarySearchTree<T> {
+    fn memory_usage(&self) -> usize {
+        let _guard = &epoch::pin();
+        let mut bytes = 0;
+        Self::for_each_node(self.root.get(), |_| {
+            bytes += Self::node_layout().pad_to_align().size() + T::IValueType::HEAP_SIZE;
+        });
+        bytes
     }
 }
 
@@ -234,7 +829,10 @@ impl<T: TreeParams> LockFreeBinarySearchTree<T> {
             write!(f, "{}", prefix).unwrap();
             write!(f, "{}", if is_left { "├──" } else { "└──" }).unwrap();
             unsafe {
-                write!(f, "{}:{}\n", (*node).key, (*(*node).p_value.get())).unwrap();
+                (*node)
+                    .value
+                    .read(|value| write!(f, "{}:{}\n", (*node).key, value))
+                    .unwrap();
             }
             LockFreeBinarySearchTree::print(
                 f,
@@ -262,7 +860,10 @@ impl<T: TreeParams> Display for LockFreeBinarySearchTree<T> {
 pub struct DefaultParams {}
 impl TreeParams for DefaultParams {
     type ValueType = String;
+    type IValueType = Boxed<String>;
     type IKeyType = FixSizedKey<DefaultParams>;
+    // Keys compared as integers do not sort like their strings.
+    const KEY_ORDER_PRESERVED: bool = !<Self as FixSizedKeyParams>::ALLOW_INT_CMP;
 }
 
 impl FixSizedKeyParams for DefaultParams {
@@ -270,3 +871,244 @@ impl FixSizedKeyParams for DefaultParams {
     const ALLOW_INT_CMP: bool = true;
 }
 pub type LockFreeBST = LockFreeBinarySearchTree<DefaultParams>;
+
+/// Like `DefaultParams`, but comparing keys byte by byte so they sort like
+/// their strings and the tree is `Ordered`, at the cost of slower compares.
+pub struct OrderedParams {}
+impl TreeParams for OrderedParams {
+    type ValueType = String;
+    type IValueType = Boxed<String>;
+    type IKeyType = FixSizedKey<OrderedParams>;
+    const KEY_ORDER_PRESERVED: bool = !<Self as FixSizedKeyParams>::ALLOW_INT_CMP;
+}
+
+impl FixSizedKeyParams for OrderedParams {
+    const KEY_SIZE: usize = 16;
+    const ALLOW_INT_CMP: bool = false;
+}
+pub type OrderedBST = LockFreeBinarySearchTree<OrderedParams>;
+
+/// `usize` keys and values, the values stored in the nodes.
+pub struct UsizeParams {}
+impl TreeParams for UsizeParams {
+    type ValueType = usize;
+    type IValueType = Inline<usize>;
+    type IKeyType = usize;
+    const KEY_ORDER_PRESERVED: bool = true;
+}
+pub type UsizeBST = LockFreeBinarySearchTree<UsizeParams>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    /// Counts how many of its values have been dropped.
+    #[derive(Clone, Debug, PartialEq)]
+    struct Counted(usize);
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct BytesParams {}
+    impl TreeParams for BytesParams {
+        type ValueType = Bytes<6>;
+        type IValueType = Inline<Bytes<6>>;
+        type IKeyType = u64;
+        const KEY_ORDER_PRESERVED: bool = true;
+    }
+
+    #[test]
+    fn inline_cell_round_trips() {
+        let cell = Inline::new(7usize);
+        assert_eq!(cell.read(|value| *value), 7);
+        cell.store(8);
+        assert_eq!(cell.update(|value| Some(value + 1)), Ok(8));
+        assert_eq!(cell.update(|_| None), Err(9));
+        assert_eq!(cell.into_inner(), 9);
+
+        let bytes = Inline::new(Bytes([1, 2, 3]));
+        bytes.store(Bytes([0xab, 0, 0xff]));
+        assert_eq!(bytes.read(|value| *value), Bytes([0xab, 0, 0xff]));
+        assert_eq!(bytes.into_inner().to_string(), "ab00ff");
+    }
+
+    #[test]
+    fn boxed_cell_drops_replaced_values() {
+        let cell = Boxed::new(Counted(0));
+        for i in 1..=100 {
+            cell.store(Counted(i));
+        }
+        assert_eq!(
+            cell.update(|value| Some(Counted(value.0 + 1))),
+            Ok(Counted(100))
+        );
+        assert_eq!(cell.update(|_| None), Err(Counted(101)));
+        assert_eq!(cell.read(|value| value.0), 101);
+        drop(cell);
+        // Replaced values are dropped once the epoch moves past them.
+        for _ in 0..10_000 {
+            if DROPPED.load(Ordering::SeqCst) >= 102 {
+                break;
+            }
+            epoch::pin().flush();
+        }
+        // 101 replaced, the last one, and clones returned or declined.
+        assert!(DROPPED.load(Ordering::SeqCst) >= 102);
+    }
+
+    #[test]
+    fn usize_bst_stores_values_inline() {
+        let tree = <UsizeBST as Tree<usize, usize>>::new();
+        for key in 0..1000usize {
+            tree.put(key * 7 % 1000, key).unwrap();
+        }
+        for key in 0..1000usize {
+            assert_eq!(tree.get(key * 7 % 1000).unwrap(), Some(key));
+        }
+        tree.put(5usize, 55).unwrap();
+        assert_eq!(tree.get(5usize).unwrap(), Some(55));
+        assert_eq!(tree.get(1000usize).unwrap(), None);
+
+        let rmw = Tree::<usize, usize>::as_read_modify_write(&tree).unwrap();
+        assert_eq!(rmw.update(5, &mut |value| value + 1).unwrap(), Some(55));
+        assert_eq!(tree.get(5usize).unwrap(), Some(56));
+        assert!(!rmw.insert_if_absent(5, 0).unwrap());
+        assert!(rmw.insert_if_absent(2000, 1).unwrap());
+        assert_eq!(rmw.compare_exchange(2000, &0, 2), Err(TreeError::Conflict));
+
+        // One cache line per node, nothing on the heap.
+        assert_eq!(tree.memory_usage(), 1001 * 64);
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.get(5usize).unwrap(), None);
+    }
+
+    #[test]
+    fn ordered_only_where_key_order_is_kept() {
+        let strings = <LockFreeBST as Tree<String, String>>::new();
+        assert!(Tree::<String, String>::as_ordered(&strings).is_none());
+
+        let tree = <UsizeBST as Tree<usize, usize>>::new();
+        for key in [40usize, 10, 30, 20] {
+            tree.put(key, key * 2).unwrap();
+        }
+        let ordered = Tree::<usize, usize>::as_ordered(&tree).unwrap();
+        assert_eq!(ordered.first().unwrap(), Some((10, 20)));
+        assert_eq!(ordered.last().unwrap(), Some((40, 80)));
+        assert_eq!(ordered.successor(10).unwrap(), Some((20, 40)));
+        assert_eq!(ordered.predecessor(30).unwrap(), Some((20, 40)));
+        assert_eq!(ordered.lower_bound(25).unwrap(), Some((30, 60)));
+        assert_eq!(ordered.upper_bound(25).unwrap(), Some((20, 40)));
+        assert_eq!(ordered.successor(40).unwrap(), None);
+    }
+
+    #[test]
+    fn ordered_strings_sort_by_bytes() {
+        let tree = <OrderedBST as Tree<String, String>>::new();
+        for key in ["9", "10", "b", "a", "ab"] {
+            tree.put(key.to_string(), key.to_uppercase()).unwrap();
+        }
+        let ordered = Tree::<String, String>::as_ordered(&tree).unwrap();
+        let entry = |key: &str| Some((key.to_string(), key.to_uppercase()));
+        assert_eq!(ordered.first().unwrap(), entry("10"));
+        assert_eq!(ordered.last().unwrap(), entry("b"));
+        assert_eq!(ordered.successor("10".to_string()).unwrap(), entry("9"));
+        assert_eq!(ordered.successor("a".to_string()).unwrap(), entry("ab"));
+        assert_eq!(ordered.predecessor("b".to_string()).unwrap(), entry("ab"));
+        assert_eq!(ordered.lower_bound("aa".to_string()).unwrap(), entry("ab"));
+        assert_eq!(ordered.upper_bound("aa".to_string()).unwrap(), entry("a"));
+    }
+
+    #[test]
+    fn bytes_values_survive_concurrent_puts() {
+        let tree = <LockFreeBinarySearchTree<BytesParams> as Tree<u64, Bytes<6>>>::new();
+        thread::scope(|s| {
+            for id in 0..4u8 {
+                let tree = &tree;
+                s.spawn(move || {
+                    for key in 0..2000u64 {
+                        tree.put(key, Bytes([id, 1, 2, 3, 4, key as u8])).unwrap();
+                    }
+                });
+            }
+        });
+        for key in 0..2000u64 {
+            let value = tree.get(key).unwrap().unwrap();
+            assert_eq!(value.0[1..], [1, 2, 3, 4, key as u8]);
+        }
+    }
+
+    #[test]
+    fn clear_while_others_run() {
+        let tree = Arc::new(<LockFreeBST as Tree<String, String>>::new());
+        let done = Arc::new(AtomicBool::new(false));
+        let workers: Vec<_> = (0..4)
+            .map(|id| {
+                let tree = tree.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut i = 0usize;
+                    while !done.load(Ordering::Relaxed) {
+                        let key = format!("{:016}", i % 500);
+                        if id % 2 == 0 {
+                            tree.put(key.clone(), key).unwrap();
+                        } else if let Some(value) = tree.get(key.clone()).unwrap() {
+                            assert_eq!(value, key);
+                        }
+                        i += 1;
+                    }
+                })
+            })
+            .collect();
+        for _ in 0..200 {
+            thread::yield_now();
+            tree.clear();
+        }
+        done.store(true, Ordering::Relaxed);
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+    }
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        let tree = <LockFreeBST as Tree<String, String>>::new();
+        for key in 0..4 {
+            tree.put(key.to_string(), "0".to_string()).unwrap();
+        }
+        thread::scope(|s| {
+            for id in 0..8 {
+                let tree = &tree;
+                s.spawn(move || {
+                    let rmw = Tree::<String, String>::as_read_modify_write(tree).unwrap();
+                    for i in 0..20_000 {
+                        let key = ((i + id) % 4).to_string();
+                        rmw.update(key, &mut |count| {
+                            (count.parse::<usize>().unwrap() + 1).to_string()
+                        })
+                        .unwrap();
+                    }
+                });
+            }
+        });
+        let total: usize = (0..4)
+            .map(|key| tree.get(key.to_string()).unwrap().unwrap())
+            .map(|count: String| count.parse::<usize>().unwrap())
+            .sum();
+        assert_eq!(total, 8 * 20_000);
+    }
+}
@@ -0,0 +1,889 @@
+use std::{
+    array, hint,
+    marker::PhantomData,
+    mem,
+    ptr::null_mut,
+    sync::atomic::{fence, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crossbeam::epoch::{self, Guard};
+
+use crate::error::Result;
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, PointRead, RangeScan, Remove, Tree, ValueType,
+};
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+}
+
+/// Common prefix of leaf and inner nodes; `level` tells which one it is.
+///
+/// `version` is an optimistic lock: odd while a writer holds the node, bumped
+/// on every unlock. Readers never lock, they re-validate the version instead.
+#[repr(C)]
+struct Header<K> {
+    version: AtomicU64,
+    level: usize,
+    /// Entries in a leaf, children in an inner node.
+    count: AtomicUsize,
+    /// Exclusive upper bound of the keys in this node, null for +inf.
+    high_key: AtomicPtr<K>,
+    right: AtomicPtr<Header<K>>,
+}
+
+#[repr(C)]
+struct Leaf<K, V, const FANOUT: usize> {
+    header: Header<K>,
+    entries: [AtomicPtr<Entry<K, V>>; FANOUT],
+}
+
+/// `keys[i]` is the low key of `children[i + 1]`, so an inner node with `n`
+/// children holds `n - 1` separators.
+#[repr(C)]
+struct Inner<K, const FANOUT: usize> {
+    header: Header<K>,
+    keys: [AtomicPtr<K>; FANOUT],
+    children: [AtomicPtr<Header<K>>; FANOUT],
+}
+
+impl<K: Ord> Header<K> {
+    fn new(level: usize) -> Self {
+        Header {
+            version: AtomicU64::new(0),
+            level,
+            count: AtomicUsize::new(0),
+            high_key: AtomicPtr::new(null_mut()),
+            right: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    fn read_version(&self) -> u64 {
+        loop {
+            let version = self.version.load(Ordering::Acquire);
+            if version & 1 == 0 {
+                return version;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    fn validate(&self, version: u64) -> bool {
+        fence(Ordering::Acquire);
+        self.version.load(Ordering::Relaxed) == version
+    }
+
+    fn lock(&self) {
+        loop {
+            let version = self.version.load(Ordering::Relaxed);
+            if version & 1 == 0
+                && self
+                    .version
+                    .compare_exchange_weak(
+                        version,
+                        version + 1,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                fence(Ordering::Release);
+                return;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    fn unlock(&self) {
+        self.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// Whether `key` is below the high key. May be stale unless validated.
+    unsafe fn covers(&self, key: &K) -> bool {
+        let high_key = self.high_key.load(Ordering::Acquire);
+        high_key.is_null() || key < &*high_key
+    }
+}
+
+impl<K: Ord, V, const FANOUT: usize> Leaf<K, V, FANOUT> {
+    fn new_ptr() -> *mut Header<K> {
+        Box::into_raw(Box::new(Leaf::<K, V, FANOUT> {
+            header: Header::new(0),
+            entries: array::from_fn(|_| AtomicPtr::new(null_mut())),
+        })) as *mut Header<K>
+    }
+
+    /// Binary search over the first `count` entries; `None` if a concurrent
+    /// writer left the node in a state an optimistic reader cannot use.
+    unsafe fn search(&self, key: &K, count: usize) -> Option<Result<usize, usize>> {
+        let (mut lo, mut hi) = (0, count.min(FANOUT));
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let entry = self.entries[mid].load(Ordering::Acquire);
+            if entry.is_null() {
+                return None;
+            }
+            match (*entry).key.cmp(key) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(Ok(mid)),
+            }
+        }
+        Some(Err(lo))
+    }
+}
+
+impl<K: Ord, const FANOUT: usize> Inner<K, FANOUT> {
+    fn new_ptr(level: usize) -> *mut Inner<K, FANOUT> {
+        Box::into_raw(Box::new(Inner::<K, FANOUT> {
+            header: Header::new(level),
+            keys: array::from_fn(|_| AtomicPtr::new(null_mut())),
+            children: array::from_fn(|_| AtomicPtr::new(null_mut())),
+        }))
+    }
+
+    /// Number of separators strictly below-or-equal to `key`, i.e. the index
+    /// of the child covering it.
+    unsafe fn child_index(&self, key: &K, count: usize) -> Option<usize> {
+        let (mut lo, mut hi) = (0, count.min(FANOUT).saturating_sub(1));
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let sep = self.keys[mid].load(Ordering::Acquire);
+            if sep.is_null() {
+                return None;
+            }
+            if &*sep <= key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Some(lo)
+    }
+}
+
+/// Concurrent B-link tree (Lehman & Yao) with optimistic lock coupling.
+///
+/// Every node carries a high key and a right link, so a reader that lands on
+/// a node which has split under it simply moves right. Reads take no locks:
+/// they validate node versions and retry. Writers lock one node at a time;
+/// a split is published to the right sibling first and posted to the parent
+/// afterwards. Nodes are never merged, so node memory is only reclaimed when
+/// the tree is dropped, while keys and entries are reclaimed through epochs.
+///
+/// `FANOUT` is the maximum number of entries in a leaf and children in an
+/// inner node.
+pub struct BLinkTree<K, V, const FANOUT: usize = 64> {
+    root: AtomicPtr<Header<K>>,
+    _marker: PhantomData<Box<Entry<K, V>>>,
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> BLinkTree<K, V, FANOUT> {
+    #[inline(always)]
+    unsafe fn leaf<'a>(node: *const Header<K>) -> &'a Leaf<K, V, FANOUT> {
+        &*(node as *const Leaf<K, V, FANOUT>)
+    }
+
+    #[inline(always)]
+    unsafe fn inner<'a>(node: *const Header<K>) -> &'a Inner<K, FANOUT> {
+        &*(node as *const Inner<K, FANOUT>)
+    }
+
+    /// Optimistically descends to the node at `level` whose range covers
+    /// `key`, returning it with the version it was validated against.
+    unsafe fn find_node(&self, key: &K, level: usize, _guard: &Guard) -> (*const Header<K>, u64) {
+        let mut node = self.root.load(Ordering::Acquire) as *const Header<K>;
+        debug_assert!((*node).level >= level);
+        loop {
+            let version = (*node).read_version();
+            if !(*node).covers(key) {
+                let right = (*node).right.load(Ordering::Acquire);
+                if (*node).validate(version) && !right.is_null() {
+                    node = right;
+                }
+                continue;
+            }
+            if (*node).level == level {
+                return (node, version);
+            }
+            let inner = Self::inner(node);
+            let count = inner.header.count.load(Ordering::Relaxed);
+            let child = match inner.child_index(key, count) {
+                Some(index) => inner.children[index].load(Ordering::Acquire),
+                None => null_mut(),
+            };
+            // Nodes are never freed, so a failed validation retries in place.
+            if (*node).validate(version) && !child.is_null() {
+                node = child;
+            }
+        }
+    }
+
+    /// Locks `node`, moving right until the locked node covers `key`.
+    unsafe fn lock_covering(mut node: *const Header<K>, key: &K) -> *const Header<K> {
+        (*node).lock();
+        while !(*node).covers(key) {
+            let right = (*node).right.load(Ordering::Acquire);
+            (*node).unlock();
+            node = right;
+            (*node).lock();
+        }
+        node
+    }
+
+    /// Installs a new root above the locked root `left` after it split.
+    unsafe fn grow(&self, left: *const Header<K>, sep: &K, right: *mut Header<K>) {
+        let root = Inner::<K, FANOUT>::new_ptr((*left).level + 1);
+        (*root).keys[0].store(Box::into_raw(Box::new(sep.clone())), Ordering::Relaxed);
+        (*root).children[0].store(left as *mut Header<K>, Ordering::Relaxed);
+        (*root).children[1].store(right, Ordering::Relaxed);
+        (*root).header.count.store(2, Ordering::Relaxed);
+        self.root.store(root as *mut Header<K>, Ordering::Release);
+    }
+
+    /// Links the new right half `right` of a locked, just split `left` and
+    /// unlocks `left`. The caller has already filled `right`.
+    unsafe fn finish_split(
+        &self,
+        left: *const Header<K>,
+        sep: K,
+        right: *mut Header<K>,
+        guard: &Guard,
+    ) {
+        (*right)
+            .high_key
+            .store((*left).high_key.load(Ordering::Relaxed), Ordering::Relaxed);
+        (*right)
+            .right
+            .store((*left).right.load(Ordering::Relaxed), Ordering::Relaxed);
+        (*left)
+            .high_key
+            .store(Box::into_raw(Box::new(sep.clone())), Ordering::Release);
+        (*left).right.store(right, Ordering::Release);
+
+        if std::ptr::eq(self.root.load(Ordering::Acquire), left) {
+            // Nobody can reach `right` before `left` is unlocked, so the root
+            // level cannot split again until the new root is in place.
+            self.grow(left, &sep, right);
+            (*left).unlock();
+        } else {
+            let level = (*left).level + 1;
+            (*left).unlock();
+            self.insert_separator(level, sep, right, guard);
+        }
+    }
+
+    /// Posts `sep -> child` into the inner node at `level` covering `sep`.
+    unsafe fn insert_separator(&self, level: usize, sep: K, child: *mut Header<K>, guard: &Guard) {
+        let (node, _) = self.find_node(&sep, level, guard);
+        let node = Self::lock_covering(node, &sep);
+        let inner = Self::inner(node);
+        let count = inner.header.count.load(Ordering::Relaxed);
+        let pos = inner.child_index(&sep, count).unwrap();
+        let sep_ptr = Box::into_raw(Box::new(sep));
+
+        if count < FANOUT {
+            for i in (pos..count - 1).rev() {
+                inner.keys[i + 1].store(inner.keys[i].load(Ordering::Relaxed), Ordering::Release);
+            }
+            for i in (pos + 1..count).rev() {
+                inner.children[i + 1]
+                    .store(inner.children[i].load(Ordering::Relaxed), Ordering::Release);
+            }
+            inner.keys[pos].store(sep_ptr, Ordering::Release);
+            inner.children[pos + 1].store(child, Ordering::Release);
+            inner.header.count.store(count + 1, Ordering::Release);
+            inner.header.unlock();
+            return;
+        }
+
+        let mut keys = (0..count - 1)
+            .map(|i| inner.keys[i].load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let mut children = (0..count)
+            .map(|i| inner.children[i].load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        keys.insert(pos, sep_ptr);
+        children.insert(pos + 1, child);
+
+        // Left keeps `half` children, the separator between the halves moves
+        // up and becomes the left high key.
+        let half = children.len() / 2;
+        let right = Inner::<K, FANOUT>::new_ptr(inner.header.level);
+        for (i, child) in children[half..].iter().enumerate() {
+            (*right).children[i].store(*child, Ordering::Relaxed);
+        }
+        for (i, key) in keys[half..].iter().enumerate() {
+            (*right).keys[i].store(*key, Ordering::Relaxed);
+        }
+        (*right)
+            .header
+            .count
+            .store(children.len() - half, Ordering::Relaxed);
+
+        for i in half..FANOUT {
+            inner.children[i].store(null_mut(), Ordering::Release);
+            inner.keys[i].store(null_mut(), Ordering::Release);
+        }
+        for (slot, child) in inner.children.iter().zip(&children[..half]) {
+            slot.store(*child, Ordering::Release);
+        }
+        for (slot, key) in inner.keys.iter().zip(&keys[..half - 1]) {
+            slot.store(*key, Ordering::Release);
+        }
+        inner.keys[half - 1].store(null_mut(), Ordering::Release);
+        inner.header.count.store(half, Ordering::Release);
+
+        // Optimistic readers may still be comparing against the promoted
+        // separator, so its box is retired rather than freed.
+        let promoted = keys[half - 1];
+        let sep = (*promoted).clone();
+        guard.defer_unchecked(move || drop(Box::from_raw(promoted)));
+        self.finish_split(node, sep, right as *mut Header<K>, guard);
+    }
+
+    /// Inserts `entry`, replacing an existing entry for its key if `replace`
+    /// is set. Otherwise the existing entry is returned and `entry` is left to
+    /// the caller.
+    unsafe fn insert_entry(
+        &self,
+        entry: *mut Entry<K, V>,
+        replace: bool,
+        guard: &Guard,
+    ) -> Option<*mut Entry<K, V>> {
+        let key = &(*entry).key;
+        let (node, _) = self.find_node(key, 0, guard);
+        let node = Self::lock_covering(node, key);
+        let leaf = Self::leaf(node);
+        let count = leaf.header.count.load(Ordering::Relaxed);
+
+        let pos = match leaf.search(key, count).unwrap() {
+            Ok(pos) if !replace => {
+                let existing = leaf.entries[pos].load(Ordering::Relaxed);
+                leaf.header.unlock();
+                return Some(existing);
+            }
+            Ok(pos) => {
+                let old = leaf.entries[pos].swap(entry, Ordering::AcqRel);
+                leaf.header.unlock();
+                guard.defer_unchecked(move || drop(Box::from_raw(old)));
+                return None;
+            }
+            Err(pos) => pos,
+        };
+
+        if count < FANOUT {
+            for i in (pos..count).rev() {
+                leaf.entries[i + 1]
+                    .store(leaf.entries[i].load(Ordering::Relaxed), Ordering::Release);
+            }
+            leaf.entries[pos].store(entry, Ordering::Release);
+            leaf.header.count.store(count + 1, Ordering::Release);
+            leaf.header.unlock();
+            return None;
+        }
+
+        let mut entries = (0..count)
+            .map(|i| leaf.entries[i].load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        entries.insert(pos, entry);
+
+        let half = entries.len() / 2;
+        let right = Leaf::<K, V, FANOUT>::new_ptr();
+        for (i, entry) in entries[half..].iter().enumerate() {
+            Self::leaf(right).entries[i].store(*entry, Ordering::Relaxed);
+        }
+        (*right)
+            .count
+            .store(entries.len() - half, Ordering::Relaxed);
+
+        for i in half..FANOUT {
+            leaf.entries[i].store(null_mut(), Ordering::Release);
+        }
+        for (i, entry) in entries[..half].iter().enumerate() {
+            leaf.entries[i].store(*entry, Ordering::Release);
+        }
+        leaf.header.count.store(half, Ordering::Release);
+
+        let sep = (*entries[half]).key.clone();
+        self.finish_split(node, sep, right, guard);
+        None
+    }
+
+    /// Returns the value stored for `key`, inserting `value` first if there is
+    /// none.
+    pub(crate) fn get_or_insert(&self, key: K, value: V) -> V {
+        let guard = epoch::pin();
+        let entry = Box::into_raw(Box::new(Entry { key, value }));
+        unsafe {
+            match self.insert_entry(entry, false, &guard) {
+                Some(existing) => {
+                    drop(Box::from_raw(entry));
+                    (*existing).value.clone()
+                }
+                None => (*entry).value.clone(),
+            }
+        }
+    }
+
+    unsafe fn delete(&self, key: &K, guard: &Guard) {
+        let (node, _) = self.find_node(key, 0, guard);
+        let node = Self::lock_covering(node, key);
+        let leaf = Self::leaf(node);
+        let count = leaf.header.count.load(Ordering::Relaxed);
+        if let Ok(pos) = leaf.search(key, count).unwrap() {
+            let old = leaf.entries[pos].load(Ordering::Relaxed);
+            for i in pos..count - 1 {
+                leaf.entries[i].store(
+                    leaf.entries[i + 1].load(Ordering::Relaxed),
+                    Ordering::Release,
+                );
+            }
+            leaf.entries[count - 1].store(null_mut(), Ordering::Release);
+            leaf.header.count.store(count - 1, Ordering::Release);
+            leaf.header.unlock();
+            guard.defer_unchecked(move || drop(Box::from_raw(old)));
+        } else {
+            leaf.header.unlock();
+        }
+    }
+
+    /// Calls `f` on every node, level by level, the same walk as `drop`.
+    /// Nodes are only freed with the tree, so this is safe under writers,
+    /// but counts and entries read along the way are not validated.
+    unsafe fn for_each_node(&self, _guard: &Guard, mut f: impl FnMut(*const Header<K>)) {
+        let mut head = self.root.load(Ordering::Acquire) as *const Header<K>;
+        while !head.is_null() {
+            let next = if (*head).level == 0 {
+                null_mut()
+            } else {
+                Self::inner(head).children[0].load(Ordering::Acquire)
+            };
+            let mut node = head;
+            while !node.is_null() {
+                f(node);
+                node = (*node).right.load(Ordering::Acquire);
+            }
+            head = next;
+        }
+    }
+
+    /// Entries of a leaf, skipping the slot a writer may be shifting.
+    unsafe fn leaf_entries<'a>(node: *const Header<K>) -> impl Iterator<Item = &'a Entry<K, V>> {
+        let leaf = Self::leaf(node);
+        let count = leaf.header.count.load(Ordering::Acquire).min(FANOUT);
+        leaf.entries[..count]
+            .iter()
+            .filter_map(|slot| slot.load(Ordering::Acquire).as_ref())
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> Tree<K, V>
+    for BLinkTree<K, V, FANOUT>
+{
+    fn new() -> Self {
+        assert!(FANOUT >= 4, "fanout must be at least 4");
+        BLinkTree {
+            root: AtomicPtr::new(Leaf::<K, V, FANOUT>::new_ptr()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Fills leaves to three quarters from left to right, then builds each
+    /// inner level over the one below, leaving room for later puts. Panics
+    /// if the items are not sorted, which would leave keys unreachable.
+    fn bulk_load(items: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        assert!(FANOUT >= 4, "fanout must be at least 4");
+        let fill = FANOUT * 3 / 4;
+        let mut items = items.into_iter().peekable();
+        let mut last: *mut Entry<K, V> = null_mut();
+        // Nodes of the level being built, each with its lowest key.
+        let mut level: Vec<(*mut Header<K>, K)> = Vec::new();
+        while let Some((low, _)) = items.peek() {
+            let low = low.clone();
+            let node = Leaf::<K, V, FANOUT>::new_ptr();
+            let leaf = unsafe { Self::leaf(node) };
+            let mut count = 0;
+            for (key, value) in items.by_ref().take(fill) {
+                assert!(
+                    unsafe { last.as_ref() }.is_none_or(|last| last.key < key),
+                    "bulk_load items must be sorted by key without duplicates"
+                );
+                let entry = Box::into_raw(Box::new(Entry { key, value }));
+                last = entry;
+                leaf.entries[count].store(entry, Ordering::Relaxed);
+                count += 1;
+            }
+            leaf.header.count.store(count, Ordering::Relaxed);
+            level.push((node, low));
+        }
+        if level.is_empty() {
+            return Ok(Self::new());
+        }
+
+        let mut height = 0;
+        loop {
+            for pair in level.windows(2) {
+                let high_key = Box::into_raw(Box::new(pair[1].1.clone()));
+                unsafe {
+                    (*pair[0].0).high_key.store(high_key, Ordering::Relaxed);
+                    (*pair[0].0).right.store(pair[1].0, Ordering::Relaxed);
+                }
+            }
+            if level.len() == 1 {
+                break;
+            }
+            height += 1;
+            level = level
+                .chunks(fill)
+                .map(|children| {
+                    let inner = unsafe { &*Inner::<K, FANOUT>::new_ptr(height) };
+                    for (i, (child, low)) in children.iter().enumerate() {
+                        inner.children[i].store(*child, Ordering::Relaxed);
+                        if i > 0 {
+                            let key = Box::into_raw(Box::new(low.clone()));
+                            inner.keys[i - 1].store(key, Ordering::Relaxed);
+                        }
+                    }
+                    inner.header.count.store(children.len(), Ordering::Relaxed);
+                    let node = inner as *const Inner<K, FANOUT> as *mut Header<K>;
+                    (node, children[0].1.clone())
+                })
+                .collect();
+        }
+        Ok(BLinkTree {
+            root: AtomicPtr::new(level[0].0),
+            _marker: PhantomData,
+        })
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> PointRead<K, V>
+    for BLinkTree<K, V, FANOUT>
+{
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let guard = epoch::pin();
+        unsafe {
+            loop {
+                let (node, version) = self.find_node(&key, 0, &guard);
+                let leaf = Self::leaf(node);
+                let count = leaf.header.count.load(Ordering::Relaxed);
+                let entry = match leaf.search(&key, count) {
+                    Some(Ok(pos)) => Some(leaf.entries[pos].load(Ordering::Acquire)),
+                    Some(Err(_)) => None,
+                    None => continue,
+                };
+                // Entries are replaced, never changed in place, so once the
+                // version checks out `f` can read the one we found.
+                if leaf.header.validate(version) {
+                    return Ok(entry.map(|entry| f(&(*entry).value)));
+                }
+            }
+        }
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> Insert<K, V>
+    for BLinkTree<K, V, FANOUT>
+{
+    fn put(&self, key: K, value: V) -> Result<()> {
+        let guard = epoch::pin();
+        let entry = Box::into_raw(Box::new(Entry { key, value }));
+        unsafe {
+            self.insert_entry(entry, true, &guard);
+        }
+        Ok(())
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> Remove<K, V>
+    for BLinkTree<K, V, FANOUT>
+{
+    fn remove(&self, key: K) -> Result<()> {
+        let guard = epoch::pin();
+        unsafe {
+            self.delete(&key, &guard);
+        }
+        Ok(())
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> RangeScan<K, V>
+    for BLinkTree<K, V, FANOUT>
+{
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        let guard = epoch::pin();
+        let mut result = Vec::with_capacity(n.min(FANOUT));
+        let mut batch = Vec::with_capacity(FANOUT);
+        unsafe {
+            let (mut node, mut version) = self.find_node(&start, 0, &guard);
+            while result.len() < n {
+                let leaf = Self::leaf(node);
+                let count = leaf.header.count.load(Ordering::Relaxed).min(FANOUT);
+                batch.clear();
+                let mut consistent = true;
+                for slot in leaf.entries[..count].iter() {
+                    let entry = slot.load(Ordering::Acquire);
+                    if entry.is_null() {
+                        consistent = false;
+                        break;
+                    }
+                    if (*entry).key >= start {
+                        batch.push(((*entry).key.clone(), (*entry).value.clone()));
+                    }
+                }
+                let right = leaf.header.right.load(Ordering::Acquire);
+                if !consistent || !leaf.header.validate(version) {
+                    version = leaf.header.read_version();
+                    continue;
+                }
+                result.extend(batch.drain(..).take(n - result.len()));
+                if right.is_null() {
+                    break;
+                }
+                node = right;
+                version = (*node).read_version();
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> Len
+    for BLinkTree<K, V, FANOUT>
+{
+    fn len(&self) -> usize {
+        let guard = epoch::pin();
+        let mut len = 0;
+        unsafe {
+            self.for_each_node(&guard, |node| {
+                if (*node).level == 0 {
+                    len += (*node).count.load(Ordering::Acquire).min(FANOUT);
+                }
+            });
+        }
+        len
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> Clear
+    for BLinkTree<K, V, FANOUT>
+{
+    fn clear(&self) {
+        let guard = epoch::pin();
+        let mut keys = Vec::new();
+        unsafe {
+            self.for_each_node(&guard, |node| {
+                if (*node).level == 0 {
+                    keys.extend(Self::leaf_entries(node).map(|entry| entry.key.clone()));
+                }
+            });
+            for key in keys.iter() {
+                self.delete(key, &guard);
+            }
+        }
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize> MemoryUsage
+    for BLinkTree<K, V, FANOUT>
+{
+    fn memory_usage(&self) -> usize {
+        let guard = epoch::pin();
+        let mut bytes = 0;
+        unsafe {
+            self.for_each_node(&guard, |node| {
+                let count = (*node).count.load(Ordering::Acquire).min(FANOUT);
+                if !(*node).high_key.load(Ordering::Acquire).is_null() {
+                    bytes += mem::size_of::<K>();
+                }
+                if (*node).level == 0 {
+                    bytes += mem::size_of::<Leaf<K, V, FANOUT>>()
+                        + count * mem::size_of::<Entry<K, V>>();
+                } else {
+                    bytes += mem::size_of::<Inner<K, FANOUT>>()
+                        + count.saturating_sub(1) * mem::size_of::<K>();
+                }
+            });
+        }
+        bytes
+    }
+}
+
+impl<K, V, const FANOUT: usize> Drop for BLinkTree<K, V, FANOUT> {
+    fn drop(&mut self) {
+        unsafe {
+            let mut head = self.root.load(Ordering::Relaxed);
+            while !head.is_null() {
+                // The leftmost node of a level never changes, so walking the
+                // first children and the right links visits every node.
+                let next = if (*head).level == 0 {
+                    null_mut()
+                } else {
+                    (*(head as *mut Inner<K, FANOUT>)).children[0].load(Ordering::Relaxed)
+                };
+                free_level::<K, V, FANOUT>(head);
+                head = next;
+            }
+        }
+    }
+}
+
+unsafe fn free_level<K, V, const FANOUT: usize>(mut node: *mut Header<K>) {
+    while !node.is_null() {
+        let right = (*node).right.load(Ordering::Relaxed);
+        let count = (*node).count.load(Ordering::Relaxed);
+        let high_key = (*node).high_key.load(Ordering::Relaxed);
+        if !high_key.is_null() {
+            drop(Box::from_raw(high_key));
+        }
+        if (*node).level == 0 {
+            let leaf = Box::from_raw(node as *mut Leaf<K, V, FANOUT>);
+            for entry in leaf.entries[..count].iter() {
+                drop(Box::from_raw(entry.load(Ordering::Relaxed)));
+            }
+        } else {
+            let inner = Box::from_raw(node as *mut Inner<K, FANOUT>);
+            for key in inner.keys[..count - 1].iter() {
+                drop(Box::from_raw(key.load(Ordering::Relaxed)));
+            }
+        }
+        node = right;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::Arc, thread};
+
+    fn height<K: KeyType + 'static, V: ValueType + 'static, const FANOUT: usize>(
+        tree: &BLinkTree<K, V, FANOUT>,
+    ) -> usize {
+        unsafe { (*tree.root.load(Ordering::Acquire)).level }
+    }
+
+    fn matches_model<const FANOUT: usize>() {
+        let tree = BLinkTree::<usize, usize, FANOUT>::new();
+        let mut model = BTreeMap::new();
+        for i in 0..4000usize {
+            let key = i * 7919 % 4000;
+            tree.put(key, i).unwrap();
+            model.insert(key, i);
+            if i % 3 == 0 {
+                let key = i * 31 % 4000;
+                tree.remove(key).unwrap();
+                model.remove(&key);
+            }
+        }
+        assert!(height(&tree) >= 2, "fanout {FANOUT} built no inner levels");
+        for key in 0..4001usize {
+            assert_eq!(
+                tree.get(key).unwrap(),
+                model.get(&key).copied(),
+                "key {key}"
+            );
+        }
+        assert_eq!(tree.len(), model.len());
+        // Scans longer than a leaf, starting on and between keys.
+        for start in [0usize, 1, 1999, 3990, 4000] {
+            let expected: Vec<_> = model
+                .range(start..)
+                .take(FANOUT * 3)
+                .map(|(k, v)| (*k, *v))
+                .collect();
+            assert_eq!(
+                tree.scan(start, FANOUT * 3).unwrap(),
+                expected,
+                "scan from {start}"
+            );
+        }
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.scan(0, 10).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn matches_model_at_any_fanout() {
+        matches_model::<4>();
+        matches_model::<5>();
+        matches_model::<64>();
+    }
+
+    #[test]
+    fn stale_nodes_reach_their_keys_through_right_links() {
+        let tree = BLinkTree::<usize, usize, 4>::new();
+        let first = tree.root.load(Ordering::Acquire) as *const Header<usize>;
+        for key in 0..100usize {
+            tree.put(key, key).unwrap();
+        }
+        unsafe {
+            let node = BLinkTree::<usize, usize, 4>::lock_covering(first, &90);
+            assert!(!std::ptr::eq(node, first));
+            assert!((*node).covers(&90));
+            assert_eq!((*node).level, 0);
+            (*node).unlock();
+        }
+    }
+
+    #[test]
+    fn bulk_load_builds_a_tree_puts_can_grow() {
+        let tree =
+            BLinkTree::<usize, usize, 8>::bulk_load((0..1000usize).map(|i| (i * 2, i))).unwrap();
+        assert!(height(&tree) >= 2);
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(tree.get(1000).unwrap(), Some(500));
+        assert_eq!(tree.get(1001).unwrap(), None);
+        for key in (1..2000usize).step_by(2) {
+            tree.put(key, key).unwrap();
+        }
+        assert_eq!(tree.len(), 2000);
+        let expected: Vec<_> = (0..2000usize)
+            .map(|key| (key, if key % 2 == 0 { key / 2 } else { key }))
+            .collect();
+        assert_eq!(tree.scan(0, 5000).unwrap(), expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted")]
+    fn bulk_load_rejects_unsorted_items() {
+        let _ = BLinkTree::<usize, usize, 8>::bulk_load([(2, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn disjoint_keys_across_threads() {
+        let tree = Arc::new(BLinkTree::<usize, usize, 4>::new());
+        let threads = 4;
+        let per_thread = 5000;
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    for i in 0..per_thread {
+                        tree.put(i * threads + id, id).unwrap();
+                    }
+                    for i in (0..per_thread).step_by(2) {
+                        tree.remove(i * threads + id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(tree.len(), threads * per_thread / 2);
+        for key in 0..threads * per_thread {
+            let expected = (key / threads % 2 == 1).then_some(key % threads);
+            assert_eq!(tree.get(key).unwrap(), expected, "key {key}");
+        }
+    }
+}
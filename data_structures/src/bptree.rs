@@ -1,20 +1,139 @@
+use std::mem;
+
 use bplustree::BPlusTree;
 
-use crate::interfaces::{GetType, KeyType, Tree, ValueType};
+use crate::error::Result;
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, Ordered, PointRead, Remove, Tree, ValueType,
+};
 
 impl<K: KeyType, V: ValueType> Tree<K, V> for BPlusTree<K, V> {
-    const GET_TYPE: crate::interfaces::GetType = GetType::GetVal;
+    fn new() -> Self {
+        BPlusTree::new()
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_ordered(&self) -> Option<&dyn Ordered<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for BPlusTree<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        // `lookup` may run its closure more than once, so copy out first.
+        Ok(self.lookup(&key, V::clone).map(|v| f(&v)))
+    }
+}
 
-    fn put(&self, key: K, value: V) {
+impl<K: KeyType, V: ValueType> Insert<K, V> for BPlusTree<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
         self.insert(key, value);
+        Ok(())
     }
 
-    fn get_val(&self, key: K) -> Option<V> {
-        self.lookup(&key, |v| v.clone())
+    /// Inserts the batch sorted by key, so runs of keys going to the same
+    /// leaf find the path to it still in cache.
+    fn multi_put(&self, items: &[(K, V)]) -> Result<()> {
+        let mut sorted: Vec<&(K, V)> = items.iter().collect();
+        sorted.sort_by(|a, b| a.0.cmp(&b.0));
+        for (key, value) in sorted {
+            self.insert(key.clone(), value.clone());
+        }
+        Ok(())
     }
+}
 
-    fn new() -> Self {
-        BPlusTree::new()
+impl<K: KeyType, V: ValueType> Remove<K, V> for BPlusTree<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        BPlusTree::remove(self, &key);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Ordered<K, V> for BPlusTree<K, V> {
+    fn first(&self) -> Result<Option<(K, V)>> {
+        let mut iter = self.raw_iter();
+        iter.seek_to_first();
+        Ok(iter.next().map(cloned))
+    }
+
+    fn last(&self) -> Result<Option<(K, V)>> {
+        let mut iter = self.raw_iter();
+        iter.seek_to_last();
+        Ok(iter.prev().map(cloned))
+    }
+
+    fn lower_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        let mut iter = self.raw_iter();
+        iter.seek(&key);
+        Ok(iter.next().map(cloned))
+    }
+
+    fn upper_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        let mut iter = self.raw_iter();
+        iter.seek_for_prev(&key);
+        Ok(iter.prev().map(cloned))
+    }
+
+    fn successor(&self, key: K) -> Result<Option<(K, V)>> {
+        let mut iter = self.raw_iter();
+        iter.seek(&key);
+        let mut entry = iter.next();
+        if entry.is_some_and(|(k, _)| *k == key) {
+            entry = iter.next();
+        }
+        Ok(entry.map(cloned))
+    }
+
+    fn predecessor(&self, key: K) -> Result<Option<(K, V)>> {
+        let mut iter = self.raw_iter();
+        iter.seek_for_prev(&key);
+        let mut entry = iter.prev();
+        if entry.is_some_and(|(k, _)| *k == key) {
+            entry = iter.prev();
+        }
+        Ok(entry.map(cloned))
+    }
+}
+
+fn cloned<K: Clone, V: Clone>((key, value): (&K, &V)) -> (K, V) {
+    (key.clone(), value.clone())
+}
+
+impl<K: KeyType, V: ValueType> Len for BPlusTree<K, V> {
+    fn len(&self) -> usize {
+        let mut iter = self.raw_iter();
+        iter.seek_to_first();
+        let mut len = 0;
+        while iter.next().is_some() {
+            len += 1;
+        }
+        len
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for BPlusTree<K, V> {
+    fn clear(&self) {
+        let mut keys = Vec::new();
+        let mut iter = self.raw_iter();
+        iter.seek_to_first();
+        while let Some((key, _)) = iter.next() {
+            keys.push(key.clone());
+        }
+        drop(iter);
+        for key in keys.iter() {
+            self.remove(key);
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for BPlusTree<K, V> {
+    /// The crate keeps its nodes private, so only the entries are counted.
+    fn memory_usage(&self) -> usize {
+        self.len() * mem::size_of::<(K, V)>()
     }
 }
 
@@ -0,0 +1,911 @@
+use std::{
+    collections::BTreeMap,
+    mem,
+    ptr::{self, null_mut},
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+
+use crate::error::Result;
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, PointRead, RangeScan, Remove, Tree, ValueType,
+};
+
+/// Logical page id, an index into the mapping table.
+type Pid = usize;
+const NULL_PID: Pid = usize::MAX;
+
+const CHUNK_SIZE: usize = 1 << 12;
+const MAX_CHUNKS: usize = 1 << 12;
+
+/// Deltas a page accumulates before it is consolidated.
+const MAX_DELTA_CHAIN: usize = 8;
+/// Entries (leaf items or inner children) a page holds before it splits.
+const MAX_PAGE_SIZE: usize = 64;
+
+enum Kind<K, V> {
+    Leaf {
+        items: Vec<(K, V)>,
+        high: Option<K>,
+        right: Pid,
+    },
+    /// `children[i + 1]` holds the keys `>= keys[i]`.
+    Inner {
+        keys: Vec<K>,
+        children: Vec<Pid>,
+        high: Option<K>,
+        right: Pid,
+    },
+    Insert {
+        key: K,
+        value: V,
+    },
+    Delete {
+        key: K,
+    },
+    /// Keys `>= sep` moved to `right`.
+    Split {
+        sep: K,
+        right: Pid,
+    },
+    /// `child` covers `[sep, high)`, posted in the parent after a split.
+    IndexEntry {
+        sep: K,
+        high: Option<K>,
+        child: Pid,
+    },
+}
+
+struct Node<K, V> {
+    /// Number of deltas between this node and the base page.
+    depth: usize,
+    /// Next older node of the chain, null for base pages. Never changes once
+    /// the node is published.
+    next: *const Node<K, V>,
+    kind: Kind<K, V>,
+}
+
+impl<K, V> Node<K, V> {
+    fn base(kind: Kind<K, V>) -> Owned<Self> {
+        Owned::new(Node {
+            depth: 0,
+            next: ptr::null(),
+            kind,
+        })
+    }
+
+    fn delta(kind: Kind<K, V>, next: Shared<'_, Node<K, V>>) -> Owned<Self> {
+        Owned::new(Node {
+            depth: unsafe { next.deref() }.depth + 1,
+            next: next.as_raw(),
+            kind,
+        })
+    }
+}
+
+/// Mapping table entry: the head of a page's chain.
+type Slot<K, V> = Atomic<Node<K, V>>;
+
+/// Where a page chain sends a key.
+enum Route<'g, K, V> {
+    /// Leaf page owning the key, with its current value.
+    Leaf(Option<&'g V>),
+    Child(Pid),
+    /// The key moved to the right sibling, which starts at `sep`. Its entry
+    /// in the parent may still be missing.
+    Right {
+        pid: Pid,
+        sep: &'g K,
+    },
+}
+
+fn route<'g, K: Ord, V>(mut node: &'g Node<K, V>, key: &K) -> Route<'g, K, V> {
+    loop {
+        match &node.kind {
+            Kind::Insert { key: k, value } if k == key => return Route::Leaf(Some(value)),
+            Kind::Delete { key: k } if k == key => return Route::Leaf(None),
+            Kind::Split { sep, right } if key >= sep => return Route::Right { pid: *right, sep },
+            Kind::IndexEntry { sep, high, child }
+                if key >= sep && high.as_ref().is_none_or(|h| key < h) =>
+            {
+                return Route::Child(*child)
+            }
+            Kind::Leaf { items, high, right } => {
+                if let Some(sep) = high.as_ref().filter(|h| key >= *h) {
+                    return Route::Right { pid: *right, sep };
+                }
+                return Route::Leaf(
+                    items
+                        .binary_search_by(|(k, _)| k.cmp(key))
+                        .ok()
+                        .map(|i| &items[i].1),
+                );
+            }
+            Kind::Inner {
+                keys,
+                children,
+                high,
+                right,
+            } => {
+                if let Some(sep) = high.as_ref().filter(|h| key >= *h) {
+                    return Route::Right { pid: *right, sep };
+                }
+                return Route::Child(children[keys.partition_point(|k| k <= key)]);
+            }
+            _ => node = unsafe { &*node.next },
+        }
+    }
+}
+
+/// Exclusive upper bound of the page whose chain starts at `node`: the newest
+/// split, or the bound of the base page.
+fn high<K, V>(mut node: &Node<K, V>) -> Option<&K> {
+    loop {
+        match &node.kind {
+            Kind::Split { sep, .. } => return Some(sep),
+            Kind::Leaf { high, .. } | Kind::Inner { high, .. } => return high.as_ref(),
+            _ => node = unsafe { &*node.next },
+        }
+    }
+}
+
+/// Leaf page owning a key, as seen by one traversal.
+struct LeafPos<'g, K, V> {
+    pid: Pid,
+    head: Shared<'g, Node<K, V>>,
+    value: Option<&'g V>,
+    /// Inner pages passed on the way down, the parent last.
+    path: Vec<Pid>,
+}
+
+/// Logical content of a page, with all of its deltas applied.
+enum Page<K, V> {
+    Leaf {
+        items: Vec<(K, V)>,
+        high: Option<K>,
+        right: Pid,
+    },
+    Inner {
+        keys: Vec<K>,
+        children: Vec<Pid>,
+        high: Option<K>,
+        right: Pid,
+    },
+}
+
+impl<K: KeyType, V: ValueType> Page<K, V> {
+    fn materialize(head: &Node<K, V>) -> Self {
+        let mut chain = vec![head];
+        while !chain.last().unwrap().next.is_null() {
+            chain.push(unsafe { &*chain.last().unwrap().next });
+        }
+
+        let mut page = match &chain.pop().unwrap().kind {
+            Kind::Leaf { items, high, right } => Page::Leaf {
+                items: items.clone(),
+                high: high.clone(),
+                right: *right,
+            },
+            Kind::Inner {
+                keys,
+                children,
+                high,
+                right,
+            } => Page::Inner {
+                keys: keys.clone(),
+                children: children.clone(),
+                high: high.clone(),
+                right: *right,
+            },
+            _ => unreachable!("chain without a base page"),
+        };
+        if chain.is_empty() {
+            return page;
+        }
+
+        // Replay the deltas, oldest first.
+        match &mut page {
+            Page::Leaf { items, high, right } => {
+                let mut map: BTreeMap<K, V> = items.drain(..).collect();
+                for node in chain.into_iter().rev() {
+                    match &node.kind {
+                        Kind::Insert { key, value } => {
+                            map.insert(key.clone(), value.clone());
+                        }
+                        Kind::Delete { key } => {
+                            map.remove(key);
+                        }
+                        Kind::Split {
+                            sep,
+                            right: sibling,
+                            ..
+                        } => {
+                            map.split_off(sep);
+                            *high = Some(sep.clone());
+                            *right = *sibling;
+                        }
+                        _ => unreachable!("inner delta on a leaf page"),
+                    }
+                }
+                *items = map.into_iter().collect();
+            }
+            Page::Inner {
+                keys,
+                children,
+                high,
+                right,
+            } => {
+                let first = children[0];
+                let mut map: BTreeMap<K, Pid> = keys.drain(..).zip(children.drain(1..)).collect();
+                for node in chain.into_iter().rev() {
+                    match &node.kind {
+                        Kind::IndexEntry { sep, child, .. } => {
+                            map.insert(sep.clone(), *child);
+                        }
+                        Kind::Split {
+                            sep,
+                            right: sibling,
+                            ..
+                        } => {
+                            map.split_off(sep);
+                            *high = Some(sep.clone());
+                            *right = *sibling;
+                        }
+                        _ => unreachable!("leaf delta on an inner page"),
+                    }
+                }
+                children.clear();
+                children.push(first);
+                for (k, child) in map {
+                    keys.push(k);
+                    children.push(child);
+                }
+            }
+        }
+        page
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Page::Leaf { items, .. } => items.len(),
+            Page::Inner { children, .. } => children.len(),
+        }
+    }
+
+    fn into_base(self) -> Owned<Node<K, V>> {
+        Node::base(match self {
+            Page::Leaf { items, high, right } => Kind::Leaf { items, high, right },
+            Page::Inner {
+                keys,
+                children,
+                high,
+                right,
+            } => Kind::Inner {
+                keys,
+                children,
+                high,
+                right,
+            },
+        })
+    }
+}
+
+/// Latch-free Bw-tree (Levandoski, Lomet and Sengupta, ICDE '13).
+///
+/// Pages are reached through a mapping table of logical page ids, so a page
+/// is changed by CAS-ing a new delta record onto the head of its chain instead
+/// of updating it in place. Long chains are consolidated into a new base page,
+/// and oversized pages split in two steps: a split delta on the page, then an
+/// index entry delta on its parent. Traversals that run into an unfinished
+/// split post the index entry themselves. Pages are never merged.
+pub struct BwTree<K, V> {
+    chunks: Box<[AtomicPtr<Slot<K, V>>]>,
+    next_pid: AtomicUsize,
+    root: AtomicUsize,
+}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for BwTree<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for BwTree<K, V> {}
+
+impl<K: KeyType, V: ValueType> BwTree<K, V> {
+    fn slot(&self, pid: Pid) -> &Slot<K, V> {
+        let chunk = &self.chunks[pid / CHUNK_SIZE];
+        let mut slots = chunk.load(Ordering::Acquire);
+        if slots.is_null() {
+            let fresh = Box::into_raw(
+                (0..CHUNK_SIZE)
+                    .map(|_| Slot::<K, V>::null())
+                    .collect::<Box<[_]>>(),
+            ) as *mut Slot<K, V>;
+            match chunk.compare_exchange(null_mut(), fresh, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => slots = fresh,
+                Err(current) => {
+                    unsafe { free_chunk(fresh) };
+                    slots = current;
+                }
+            }
+        }
+        unsafe { &*slots.add(pid % CHUNK_SIZE) }
+    }
+
+    fn load<'g>(&self, pid: Pid, guard: &'g Guard) -> &'g Node<K, V> {
+        unsafe { self.slot(pid).load(Ordering::Acquire, guard).deref() }
+    }
+
+    fn allocate(&self, page: Owned<Node<K, V>>) -> Pid {
+        let pid = self.next_pid.fetch_add(1, Ordering::Relaxed);
+        assert!(pid < CHUNK_SIZE * MAX_CHUNKS, "mapping table is full");
+        self.slot(pid).store(page, Ordering::Release);
+        pid
+    }
+
+    /// Releases a page that never became reachable. Its id is not reused.
+    fn discard(&self, pid: Pid, guard: &Guard) {
+        let page = self.slot(pid).swap(Shared::null(), Ordering::AcqRel, guard);
+        drop(unsafe { page.into_owned() });
+    }
+
+    fn find_leaf<'g>(&self, key: &K, guard: &'g Guard) -> LeafPos<'g, K, V> {
+        let mut path = Vec::new();
+        let mut pid = self.root.load(Ordering::Acquire);
+        loop {
+            let head = self.slot(pid).load(Ordering::Acquire, guard);
+            match route(unsafe { head.deref() }, key) {
+                Route::Leaf(value) => {
+                    return LeafPos {
+                        pid,
+                        head,
+                        value,
+                        path,
+                    }
+                }
+                Route::Child(child) => {
+                    path.push(pid);
+                    pid = child;
+                }
+                Route::Right { pid: right, sep } => {
+                    self.complete_split(&path, pid, sep, right, guard);
+                    pid = right;
+                }
+            }
+        }
+    }
+
+    /// Prepends `delta` to the page `pid` of the leaf owning `key`. Returns the
+    /// page and the path to it once installed.
+    fn install(&self, key: &K, mut delta: Owned<Node<K, V>>, guard: &Guard) -> (Pid, Vec<Pid>) {
+        loop {
+            let LeafPos {
+                pid, head, path, ..
+            } = self.find_leaf(key, guard);
+            delta.next = head.as_raw();
+            delta.depth = unsafe { head.deref() }.depth + 1;
+            match self.slot(pid).compare_exchange(
+                head,
+                delta,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            ) {
+                Ok(_) => return (pid, path),
+                Err(e) => delta = e.new,
+            }
+        }
+    }
+
+    fn maybe_consolidate(&self, pid: Pid, path: &[Pid], guard: &Guard) {
+        if self.load(pid, guard).depth >= MAX_DELTA_CHAIN {
+            self.consolidate(pid, path, guard);
+        }
+    }
+
+    /// Replaces the chain of `pid` with a single base page, then splits the
+    /// page if it grew too large.
+    fn consolidate(&self, pid: Pid, path: &[Pid], guard: &Guard) {
+        let slot = self.slot(pid);
+        let head = slot.load(Ordering::Acquire, guard);
+        let page = Page::materialize(unsafe { head.deref() });
+        let oversized = page.len() > MAX_PAGE_SIZE;
+        if slot
+            .compare_exchange(
+                head,
+                page.into_base(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                guard,
+            )
+            .is_err()
+        {
+            return;
+        }
+
+        let mut node = head;
+        while !node.is_null() {
+            let next = unsafe { node.deref() }.next;
+            unsafe { guard.defer_destroy(node) };
+            node = Shared::from(next);
+        }
+
+        if oversized {
+            self.split(pid, path, guard);
+        }
+    }
+
+    fn split(&self, pid: Pid, path: &[Pid], guard: &Guard) {
+        let slot = self.slot(pid);
+        let head = slot.load(Ordering::Acquire, guard);
+        let (sep, sibling) = match Page::materialize(unsafe { head.deref() }) {
+            Page::Leaf {
+                mut items,
+                high,
+                right,
+            } => {
+                if items.len() <= MAX_PAGE_SIZE {
+                    return;
+                }
+                let upper = items.split_off(items.len() / 2);
+                let sep = upper[0].0.clone();
+                let sibling = Kind::Leaf {
+                    items: upper,
+                    high,
+                    right,
+                };
+                (sep, sibling)
+            }
+            Page::Inner {
+                mut keys,
+                mut children,
+                high,
+                right,
+            } => {
+                if children.len() <= MAX_PAGE_SIZE {
+                    return;
+                }
+                let mid = keys.len() / 2;
+                let upper_keys = keys.split_off(mid + 1);
+                let sep = keys.pop().unwrap();
+                let sibling = Kind::Inner {
+                    keys: upper_keys,
+                    children: children.split_off(mid + 1),
+                    high,
+                    right,
+                };
+                (sep, sibling)
+            }
+        };
+
+        let right = self.allocate(Node::base(sibling));
+        let delta = Node::delta(Kind::Split { sep, right }, head);
+        match slot.compare_exchange(head, delta, Ordering::AcqRel, Ordering::Acquire, guard) {
+            Ok(delta) => {
+                let Kind::Split { sep, .. } = &unsafe { delta.deref() }.kind else {
+                    unreachable!()
+                };
+                self.complete_split(path, pid, sep, right, guard);
+            }
+            Err(_) => self.discard(right, guard),
+        }
+    }
+
+    /// Makes the parent of `left` route `[sep, high)` to `right`, `high` being
+    /// the bound `right` has now, or grows a new root if `left` was the root.
+    /// `path` is the way down to `left`.
+    fn complete_split(&self, path: &[Pid], left: Pid, sep: &K, right: Pid, guard: &Guard) {
+        let Some((&parent, grand_path)) = path.split_last() else {
+            // Only the root has no parent. If it changed, the new root
+            // already covers `left`.
+            if self.root.load(Ordering::Acquire) != left {
+                return;
+            }
+            let root = self.allocate(Node::base(Kind::Inner {
+                keys: vec![sep.clone()],
+                children: vec![left, right],
+                high: None,
+                right: NULL_PID,
+            }));
+            if self
+                .root
+                .compare_exchange(left, root, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                self.discard(root, guard);
+            }
+            return;
+        };
+
+        let mut parent = parent;
+        let mut entry: Option<Owned<Node<K, V>>> = None;
+        loop {
+            let slot = self.slot(parent);
+            let head = slot.load(Ordering::Acquire, guard);
+            match route(unsafe { head.deref() }, sep) {
+                Route::Child(child) if child == right => return,
+                Route::Child(_) => {
+                    let mut delta = entry.take().unwrap_or_else(|| {
+                        Node::delta(
+                            Kind::IndexEntry {
+                                sep: sep.clone(),
+                                high: high(self.load(right, guard)).cloned(),
+                                child: right,
+                            },
+                            head,
+                        )
+                    });
+                    delta.next = head.as_raw();
+                    delta.depth = unsafe { head.deref() }.depth + 1;
+                    match slot.compare_exchange(
+                        head,
+                        delta,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                        guard,
+                    ) {
+                        Ok(_) => {
+                            self.maybe_consolidate(parent, grand_path, guard);
+                            return;
+                        }
+                        Err(e) => entry = Some(e.new),
+                    }
+                }
+                // The parent split too, the entry belongs to its sibling.
+                Route::Right { pid, .. } => parent = pid,
+                Route::Leaf(_) => unreachable!("leaf page above a split page"),
+            }
+        }
+    }
+
+    /// Calls `f` with the items of every leaf page, left to right. The
+    /// leftmost leaf keeps its id through splits, so the walk starts there.
+    fn for_each_leaf(&self, guard: &Guard, mut f: impl FnMut(Vec<(K, V)>)) {
+        let mut pid = self.root.load(Ordering::Acquire);
+        loop {
+            match Page::materialize(self.load(pid, guard)) {
+                Page::Inner { children, .. } => pid = children[0],
+                Page::Leaf { items, right, .. } => {
+                    f(items);
+                    if right == NULL_PID {
+                        return;
+                    }
+                    pid = right;
+                }
+            }
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> Tree<K, V> for BwTree<K, V> {
+    fn new() -> Self {
+        let tree = BwTree {
+            chunks: (0..MAX_CHUNKS)
+                .map(|_| AtomicPtr::new(null_mut()))
+                .collect(),
+            next_pid: AtomicUsize::new(0),
+            root: AtomicUsize::new(0),
+        };
+        tree.allocate(Node::base(Kind::Leaf {
+            items: Vec::new(),
+            high: None,
+            right: NULL_PID,
+        }));
+        tree
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for BwTree<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let guard = &epoch::pin();
+        Ok(self.find_leaf(&key, guard).value.map(f))
+    }
+}
+
+impl<K: KeyType, V: ValueType> Insert<K, V> for BwTree<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        let guard = &epoch::pin();
+        let delta = Node::base(Kind::Insert {
+            key: key.clone(),
+            value,
+        });
+        let (pid, path) = self.install(&key, delta, guard);
+        self.maybe_consolidate(pid, &path, guard);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Remove<K, V> for BwTree<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        let guard = &epoch::pin();
+        if self.find_leaf(&key, guard).value.is_none() {
+            return Ok(());
+        }
+        let delta = Node::base(Kind::Delete { key: key.clone() });
+        let (pid, path) = self.install(&key, delta, guard);
+        self.maybe_consolidate(pid, &path, guard);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> RangeScan<K, V> for BwTree<K, V> {
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        let guard = &epoch::pin();
+        let mut result = Vec::new();
+        let mut pid = self.find_leaf(&start, guard).pid;
+        let mut from = start;
+        while result.len() < n {
+            let Page::Leaf { items, high, right } = Page::materialize(self.load(pid, guard)) else {
+                unreachable!("scan left the leaf level")
+            };
+            let begin = items.partition_point(|(k, _)| *k < from);
+            let want = n - result.len();
+            result.extend(items.into_iter().skip(begin).take(want));
+            match high {
+                Some(high) if right != NULL_PID => {
+                    pid = right;
+                    from = high;
+                }
+                _ => break,
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<K: KeyType, V: ValueType> Len for BwTree<K, V> {
+    fn len(&self) -> usize {
+        let guard = &epoch::pin();
+        let mut len = 0;
+        self.for_each_leaf(guard, |items| len += items.len());
+        len
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for BwTree<K, V> {
+    fn clear(&self) {
+        let guard = &epoch::pin();
+        let mut keys = Vec::new();
+        self.for_each_leaf(guard, |items| {
+            keys.extend(items.into_iter().map(|(k, _)| k))
+        });
+        for key in keys {
+            let delta = Node::base(Kind::Delete { key: key.clone() });
+            let (pid, path) = self.install(&key, delta, guard);
+            self.maybe_consolidate(pid, &path, guard);
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for BwTree<K, V> {
+    /// Counts every chain in the mapping table, so deltas not yet
+    /// consolidated away are included.
+    fn memory_usage(&self) -> usize {
+        let guard = &epoch::pin();
+        let mut bytes = self.chunks.len() * mem::size_of::<AtomicPtr<Slot<K, V>>>();
+        for chunk in self.chunks.iter() {
+            if !chunk.load(Ordering::Acquire).is_null() {
+                bytes += CHUNK_SIZE * mem::size_of::<Slot<K, V>>();
+            }
+        }
+        for pid in 0..self.next_pid.load(Ordering::Acquire) {
+            let mut node = self.slot(pid).load(Ordering::Acquire, guard).as_raw();
+            while let Some(current) = unsafe { node.as_ref() } {
+                bytes += mem::size_of::<Node<K, V>>();
+                match &current.kind {
+                    Kind::Leaf { items, .. } => {
+                        bytes += items.capacity() * mem::size_of::<(K, V)>();
+                    }
+                    Kind::Inner { keys, children, .. } => {
+                        bytes += keys.capacity() * mem::size_of::<K>()
+                            + children.capacity() * mem::size_of::<Pid>();
+                    }
+                    _ => {}
+                }
+                node = current.next;
+            }
+        }
+        bytes
+    }
+}
+
+unsafe fn free_chunk<T>(slots: *mut T) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+        slots, CHUNK_SIZE,
+    )));
+}
+
+impl<K, V> Drop for BwTree<K, V> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            for pid in 0..*self.next_pid.get_mut() {
+                let slots = self.chunks[pid / CHUNK_SIZE].load(Ordering::Relaxed);
+                let mut node = (*slots.add(pid % CHUNK_SIZE)).load(Ordering::Relaxed, guard);
+                while !node.is_null() {
+                    let next = node.deref().next;
+                    drop(node.into_owned());
+                    node = Shared::from(next);
+                }
+            }
+            for chunk in self.chunks.iter() {
+                let slots = chunk.load(Ordering::Relaxed);
+                if !slots.is_null() {
+                    free_chunk(slots);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn put_get_remove_scan() {
+        let tree = BwTree::<usize, usize>::new();
+        let mut model = BTreeMap::new();
+        for i in 0..5000usize {
+            let key = i * 7919 % 5000;
+            tree.put(key, i).unwrap();
+            model.insert(key, i);
+            if i % 3 == 0 {
+                let key = i * 31 % 5000;
+                tree.remove(key).unwrap();
+                model.remove(&key);
+            }
+        }
+        for key in 0..5001usize {
+            assert_eq!(
+                tree.get(key).unwrap(),
+                model.get(&key).copied(),
+                "key {key}"
+            );
+        }
+        assert_eq!(tree.len(), model.len());
+        for start in [0usize, 1, 777, 4990, 5000] {
+            let expected: Vec<_> = model
+                .range(start..)
+                .take(100)
+                .map(|(k, v)| (*k, *v))
+                .collect();
+            assert_eq!(
+                tree.scan(start, 100).unwrap(),
+                expected,
+                "scan from {start}"
+            );
+        }
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.get(42).unwrap(), None);
+    }
+
+    #[test]
+    fn consolidates_long_chains() {
+        let tree = BwTree::<usize, usize>::new();
+        let guard = &epoch::pin();
+        for key in 0..MAX_DELTA_CHAIN - 1 {
+            tree.put(key, key).unwrap();
+        }
+        assert_eq!(tree.load(0, guard).depth, MAX_DELTA_CHAIN - 1);
+        tree.put(MAX_DELTA_CHAIN, 0).unwrap();
+        let root = tree.load(0, guard);
+        assert_eq!(root.depth, 0);
+        let Kind::Leaf { items, .. } = &root.kind else {
+            panic!("root is not a leaf base page")
+        };
+        assert_eq!(items.len(), MAX_DELTA_CHAIN);
+    }
+
+    #[test]
+    fn splits_leaves_and_inner_pages() {
+        let tree = BwTree::<usize, usize>::new();
+        // Enough leaves for the root to outgrow a page as well.
+        let n = MAX_PAGE_SIZE * MAX_PAGE_SIZE;
+        for key in 0..n {
+            tree.put(key, key).unwrap();
+        }
+        let guard = &epoch::pin();
+        let root = tree.root.load(Ordering::Acquire);
+        assert_ne!(root, 0);
+        let Page::Inner { children, .. } = Page::materialize(tree.load(root, guard)) else {
+            panic!("root is a leaf")
+        };
+        assert!(matches!(
+            Page::materialize(tree.load(children[0], guard)),
+            Page::Inner { .. }
+        ));
+        assert_eq!(tree.len(), n);
+        assert_eq!(tree.scan(n - 10, 20).unwrap().len(), 10);
+        for key in (0..n).step_by(97) {
+            assert_eq!(tree.get(key).unwrap(), Some(key));
+        }
+    }
+
+    #[test]
+    fn entries_keep_the_bound_of_consolidated_splits() {
+        let tree = BwTree::<usize, usize>::new();
+        for key in 0..4 * MAX_PAGE_SIZE {
+            tree.put(key, key).unwrap();
+        }
+        let guard = &epoch::pin();
+        let root = tree.root.load(Ordering::Acquire);
+
+        // Split the leftmost leaf without posting its entry, then consolidate
+        // the split delta away.
+        let head = tree.slot(0).load(Ordering::Acquire, guard);
+        let Page::Leaf {
+            mut items,
+            high: bound,
+            right,
+        } = Page::materialize(unsafe { head.deref() })
+        else {
+            panic!("leftmost page is not a leaf")
+        };
+        assert!(bound.is_some());
+        let upper = items.split_off(items.len() / 2);
+        let sep = upper[0].0;
+        let sibling = tree.allocate(Node::base(Kind::Leaf {
+            items: upper,
+            high: bound,
+            right,
+        }));
+        let split = Node::delta(
+            Kind::Split {
+                sep,
+                right: sibling,
+            },
+            head,
+        );
+        tree.slot(0).store(split, Ordering::Release);
+        tree.consolidate(0, &[root], guard);
+        assert_eq!(tree.load(0, guard).depth, 0);
+
+        // The next traversal posts the entry, bounded like the sibling.
+        assert_eq!(tree.get(sep).unwrap(), Some(sep));
+        let mut node = tree.load(root, guard);
+        let entry = loop {
+            match &node.kind {
+                Kind::IndexEntry { child, high, .. } if *child == sibling => break high,
+                Kind::Inner { .. } => panic!("no index entry for the sibling"),
+                _ => node = unsafe { &*node.next },
+            }
+        };
+        assert_eq!(entry.as_ref(), high(tree.load(sibling, guard)));
+    }
+
+    #[test]
+    fn disjoint_keys_across_threads() {
+        let tree = Arc::new(BwTree::<usize, usize>::new());
+        let threads = 4;
+        let per_thread = 5000;
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    for i in 0..per_thread {
+                        tree.put(i * threads + id, id).unwrap();
+                    }
+                    for i in (0..per_thread).step_by(2) {
+                        tree.remove(i * threads + id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(tree.len(), threads * per_thread / 2);
+        for key in 0..threads * per_thread {
+            let expected = (key / threads % 2 == 1).then_some(key % threads);
+            assert_eq!(tree.get(key).unwrap(), expected, "key {key}");
+        }
+    }
+}
@@ -0,0 +1,599 @@
+use std::{
+    collections::BTreeMap,
+    mem,
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+        Mutex, MutexGuard, TryLockError,
+    },
+};
+
+use crossbeam::epoch::{self, Guard};
+
+use crate::{
+    error::Result,
+    interfaces::{
+        Clear, Insert, KeyType, Len, MemoryUsage, PointRead, RangeScan, ReadModifyWrite, Remove,
+        Tree, ValueType,
+    },
+    locked_btree::{compare_exchange_in, insert_if_absent_in, map_memory_usage, update_in},
+};
+
+/// Added to a base's statistic when its lock was contended.
+const CONTENDED: i64 = 250;
+/// Subtracted when its lock was taken without waiting.
+const UNCONTENDED: i64 = 1;
+/// Subtracted from every base a range query had to lock together.
+const RANGE_SPANNED: i64 = 10;
+const SPLIT_THRESHOLD: i64 = 1000;
+const JOIN_THRESHOLD: i64 = -1000;
+
+struct Route<K, V> {
+    key: K,
+    /// Keys below `key` go left, the others right.
+    children: [AtomicPtr<Node<K, V>>; 2],
+    parent: AtomicPtr<Node<K, V>>,
+    /// Only taken by joins, which splice route nodes out.
+    lock: Mutex<()>,
+    valid: AtomicBool,
+}
+
+struct BaseData<K, V> {
+    map: BTreeMap<K, V>,
+    stat: i64,
+}
+
+struct Base<K, V> {
+    data: Mutex<BaseData<K, V>>,
+    /// Null for the root. Never changes: bases are replaced, not moved.
+    parent: AtomicPtr<Node<K, V>>,
+    /// Cleared under the lock once the base was split or joined.
+    valid: AtomicBool,
+}
+
+enum Node<K, V> {
+    Route(Route<K, V>),
+    Base(Base<K, V>),
+}
+
+impl<K, V> Node<K, V> {
+    fn base(map: BTreeMap<K, V>, parent: *mut Node<K, V>) -> *mut Self {
+        Box::into_raw(Box::new(Node::Base(Base {
+            data: Mutex::new(BaseData { map, stat: 0 }),
+            parent: AtomicPtr::new(parent),
+            valid: AtomicBool::new(true),
+        })))
+    }
+
+    fn parent(&self) -> &AtomicPtr<Node<K, V>> {
+        match self {
+            Node::Route(route) => &route.parent,
+            Node::Base(base) => &base.parent,
+        }
+    }
+}
+
+unsafe fn route<'a, K, V>(node: *mut Node<K, V>) -> &'a Route<K, V> {
+    match &*node {
+        Node::Route(route) => route,
+        Node::Base(_) => unreachable!("base node used as a route node"),
+    }
+}
+
+unsafe fn retire<K, V>(node: *mut Node<K, V>, guard: &Guard) {
+    guard.defer_unchecked(move || drop(Box::from_raw(node)));
+}
+
+/// Split and join counts of a `CaTree`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaTreeStats {
+    pub splits: usize,
+    pub joins: usize,
+}
+
+/// Contention-adapting search tree (Sagonas and Winblad, 2015).
+///
+/// Keys live in sequential `BTreeMap` base containers, each behind its own
+/// lock, under a binary tree of route nodes that is read without locking.
+/// Every lock acquisition tells the base whether it had to wait: bases that
+/// keep being contended split in two, bases that keep being taken uncontended
+/// or locked together by range queries join with their neighbour. Range
+/// queries lock the bases they cover from left to right and hold them until
+/// done, so they are atomic. Joins only try-lock the nodes they need beyond
+/// their own base and give up when busy, so they cannot deadlock with that.
+pub struct CaTree<K, V> {
+    root: AtomicPtr<Node<K, V>>,
+    splits: AtomicUsize,
+    joins: AtomicUsize,
+}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for CaTree<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for CaTree<K, V> {}
+
+impl<K: KeyType, V: ValueType> CaTree<K, V> {
+    pub fn stats(&self) -> CaTreeStats {
+        CaTreeStats {
+            splits: self.splits.load(Ordering::Relaxed),
+            joins: self.joins.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The base whose range covers `key`, its node, and the exclusive upper
+    /// bound of that range.
+    fn find_base<'g>(
+        &self,
+        key: &K,
+        _guard: &'g Guard,
+    ) -> (&'g Base<K, V>, *mut Node<K, V>, Option<&'g K>) {
+        let mut node = self.root.load(Ordering::Acquire);
+        let mut bound = None;
+        loop {
+            match unsafe { &*node } {
+                Node::Route(route) => {
+                    let dir = if key < &route.key {
+                        bound = Some(&route.key);
+                        0
+                    } else {
+                        1
+                    };
+                    node = route.children[dir].load(Ordering::Acquire);
+                }
+                Node::Base(base) => return (base, node, bound),
+            }
+        }
+    }
+
+    /// Locks `base`, recording whether the lock was contended.
+    fn lock<'g>(base: &'g Base<K, V>) -> MutexGuard<'g, BaseData<K, V>> {
+        match base.data.try_lock() {
+            Ok(mut data) => {
+                data.stat -= UNCONTENDED;
+                data
+            }
+            Err(TryLockError::WouldBlock) => {
+                let mut data = base.data.lock().unwrap();
+                data.stat += CONTENDED;
+                data
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+        }
+    }
+
+    /// Runs `f` on the locked, valid base covering `key`, then adapts it.
+    fn with_base<R>(&self, key: &K, f: impl FnOnce(&mut BTreeMap<K, V>) -> R) -> R {
+        let guard = &epoch::pin();
+        loop {
+            let (base, node, _) = self.find_base(key, guard);
+            let mut data = Self::lock(base);
+            if !base.valid.load(Ordering::Acquire) {
+                continue;
+            }
+            let result = f(&mut data.map);
+            if data.stat > SPLIT_THRESHOLD && data.map.len() >= 2 {
+                self.split(node, data, guard);
+            } else if data.stat < JOIN_THRESHOLD {
+                self.join(node, data, guard);
+            }
+            return result;
+        }
+    }
+
+    /// Points the child of `parent` (the root if null) that was `old` at `new`.
+    unsafe fn replace_child(
+        &self,
+        parent: *mut Node<K, V>,
+        old: *mut Node<K, V>,
+        new: *mut Node<K, V>,
+    ) {
+        if parent.is_null() {
+            self.root.store(new, Ordering::Release);
+            return;
+        }
+        let children = &route(parent).children;
+        let dir = usize::from(children[0].load(Ordering::Relaxed) != old);
+        children[dir].store(new, Ordering::Release);
+    }
+
+    /// Replaces the locked base `node` by a route node over its two halves.
+    fn split(&self, node: *mut Node<K, V>, mut data: MutexGuard<BaseData<K, V>>, guard: &Guard) {
+        let Node::Base(base) = (unsafe { &*node }) else {
+            unreachable!()
+        };
+        let mut left = mem::take(&mut data.map);
+        let key = left.keys().nth(left.len() / 2).unwrap().clone();
+        let right = left.split_off(&key);
+        let parent = base.parent.load(Ordering::Relaxed);
+
+        let route = Box::into_raw(Box::new(Node::Route(Route {
+            key,
+            children: [AtomicPtr::new(null_mut()), AtomicPtr::new(null_mut())],
+            parent: AtomicPtr::new(parent),
+            lock: Mutex::new(()),
+            valid: AtomicBool::new(true),
+        })));
+        let children = &unsafe { self::route(route) }.children;
+        children[0].store(Node::base(left, route), Ordering::Relaxed);
+        children[1].store(Node::base(right, route), Ordering::Relaxed);
+
+        unsafe { self.replace_child(parent, node, route) };
+        base.valid.store(false, Ordering::Release);
+        drop(data);
+        unsafe { retire(node, guard) };
+        self.splits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Merges the locked base `node` with its neighbour across its parent
+    /// route node, which is spliced out. Gives up if anything is busy.
+    fn join(&self, node: *mut Node<K, V>, mut data: MutexGuard<BaseData<K, V>>, guard: &Guard) {
+        let Node::Base(base) = (unsafe { &*node }) else {
+            unreachable!()
+        };
+        let parent = base.parent.load(Ordering::Relaxed);
+        if parent.is_null() {
+            data.stat = 0;
+            return;
+        }
+        let parent_route = unsafe { route(parent) };
+        let Ok(_parent_lock) = parent_route.lock.try_lock() else {
+            return;
+        };
+        if !parent_route.valid.load(Ordering::Acquire) {
+            return;
+        }
+        let grand = parent_route.parent.load(Ordering::Relaxed);
+        let _grand_lock = match unsafe { grand.as_ref() } {
+            None => None,
+            Some(_) => {
+                let grand_route = unsafe { route(grand) };
+                match grand_route.lock.try_lock() {
+                    Ok(lock) if grand_route.valid.load(Ordering::Acquire) => Some(lock),
+                    _ => return,
+                }
+            }
+        };
+
+        // The neighbour is the base of the sibling subtree closest to `node`.
+        let from_left = parent_route.children[0].load(Ordering::Relaxed) == node;
+        let toward = usize::from(!from_left);
+        let sibling = parent_route.children[usize::from(from_left)].load(Ordering::Acquire);
+        let mut neighbor = sibling;
+        while let Node::Route(route) = unsafe { &*neighbor } {
+            neighbor = route.children[toward].load(Ordering::Acquire);
+        }
+        let Node::Base(neighbor_base) = (unsafe { &*neighbor }) else {
+            unreachable!()
+        };
+        let Ok(mut neighbor_data) = neighbor_base.data.try_lock() else {
+            return;
+        };
+        if !neighbor_base.valid.load(Ordering::Acquire) {
+            return;
+        }
+        let _sibling_lock = match unsafe { &*sibling } {
+            Node::Route(route) => match route.lock.try_lock() {
+                Ok(lock) if route.valid.load(Ordering::Acquire) => Some(lock),
+                _ => return,
+            },
+            Node::Base(_) => None,
+        };
+
+        let mut map = mem::take(&mut data.map);
+        map.append(&mut neighbor_data.map);
+        unsafe {
+            if sibling == neighbor {
+                let joined = Node::base(map, grand);
+                self.replace_child(grand, parent, joined);
+            } else {
+                let neighbor_parent = neighbor_base.parent.load(Ordering::Relaxed);
+                let joined = Node::base(map, neighbor_parent);
+                self.replace_child(neighbor_parent, neighbor, joined);
+                (*sibling).parent().store(grand, Ordering::Release);
+                self.replace_child(grand, parent, sibling);
+            }
+        }
+        base.valid.store(false, Ordering::Release);
+        neighbor_base.valid.store(false, Ordering::Release);
+        parent_route.valid.store(false, Ordering::Release);
+        drop(data);
+        drop(neighbor_data);
+        unsafe {
+            retire(node, guard);
+            retire(neighbor, guard);
+            retire(parent, guard);
+        }
+        self.joins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Calls `f` with the locked map of every valid base, and with `None`
+    /// for every route node. Bases are locked one at a time, so the walk is
+    /// not atomic.
+    fn for_each_node(&self, mut f: impl FnMut(Option<&mut BTreeMap<K, V>>)) {
+        let _guard = &epoch::pin();
+        let mut pending = vec![self.root.load(Ordering::Acquire)];
+        while let Some(node) = pending.pop() {
+            match unsafe { &*node } {
+                Node::Route(route) => {
+                    f(None);
+                    pending.extend(
+                        route
+                            .children
+                            .iter()
+                            .rev()
+                            .map(|child| child.load(Ordering::Acquire)),
+                    );
+                }
+                Node::Base(base) => {
+                    let mut data = base.data.lock().unwrap();
+                    if base.valid.load(Ordering::Acquire) {
+                        f(Some(&mut data.map));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> Tree<K, V> for CaTree<K, V> {
+    fn new() -> Self {
+        CaTree {
+            root: AtomicPtr::new(Node::base(BTreeMap::new(), null_mut())),
+            splits: AtomicUsize::new(0),
+            joins: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts out as a single base, which contention then splits.
+    fn bulk_load(items: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        Ok(CaTree {
+            root: AtomicPtr::new(Node::base(BTreeMap::from_iter(items), null_mut())),
+            splits: AtomicUsize::new(0),
+            joins: AtomicUsize::new(0),
+        })
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+
+    fn as_read_modify_write(&self) -> Option<&dyn ReadModifyWrite<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for CaTree<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        Ok(self.with_base(&key, |map| map.get(&key).map(f)))
+    }
+}
+
+impl<K: KeyType, V: ValueType> Insert<K, V> for CaTree<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        self.with_base(&key.clone(), |map| map.insert(key, value));
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Remove<K, V> for CaTree<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        self.with_base(&key, |map| map.remove(&key));
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> ReadModifyWrite<K, V> for CaTree<K, V> {
+    fn insert_if_absent(&self, key: K, value: V) -> Result<bool> {
+        Ok(self.with_base(&key.clone(), |map| insert_if_absent_in(map, key, value)))
+    }
+
+    fn compare_exchange(&self, key: K, expected: &V, new: V) -> Result<()> {
+        self.with_base(&key, |map| compare_exchange_in(map, &key, expected, new))
+    }
+
+    fn update(&self, key: K, f: &mut dyn FnMut(&V) -> V) -> Result<Option<V>> {
+        Ok(self.with_base(&key, |map| update_in(map, &key, f)))
+    }
+}
+
+impl<K: KeyType, V: ValueType> RangeScan<K, V> for CaTree<K, V> {
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        let guard = &epoch::pin();
+        let mut result = Vec::new();
+        let mut held = Vec::new();
+        let mut from = start;
+        loop {
+            let (data, bound) = loop {
+                let (base, _, bound) = self.find_base(&from, guard);
+                let data = Self::lock(base);
+                if base.valid.load(Ordering::Acquire) {
+                    break (data, bound);
+                }
+            };
+            let want = n - result.len();
+            result.extend(
+                data.map
+                    .range(from..)
+                    .take(want)
+                    .map(|(k, v)| (k.clone(), v.clone())),
+            );
+            held.push(data);
+            match bound {
+                Some(bound) if result.len() < n => from = bound.clone(),
+                _ => break,
+            }
+        }
+        if held.len() > 1 {
+            for data in held.iter_mut() {
+                data.stat -= RANGE_SPANNED;
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<K: KeyType, V: ValueType> Len for CaTree<K, V> {
+    fn len(&self) -> usize {
+        let mut len = 0;
+        self.for_each_node(|map| len += map.map_or(0, |map| map.len()));
+        len
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for CaTree<K, V> {
+    fn clear(&self) {
+        self.for_each_node(|map| {
+            if let Some(map) = map {
+                map.clear();
+            }
+        });
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for CaTree<K, V> {
+    fn memory_usage(&self) -> usize {
+        let mut bytes = 0;
+        self.for_each_node(|map| {
+            bytes += mem::size_of::<Node<K, V>>() + map.map_or(0, |map| map_memory_usage(map));
+        });
+        bytes
+    }
+}
+
+impl<K, V> Drop for CaTree<K, V> {
+    fn drop(&mut self) {
+        let mut pending = vec![*self.root.get_mut()];
+        while let Some(node) = pending.pop() {
+            let mut node = unsafe { Box::from_raw(node) };
+            if let Node::Route(route) = node.as_mut() {
+                pending.extend(route.children.iter_mut().map(|child| *child.get_mut()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread, time::Duration};
+
+    /// Holds the lock of the base covering `key` while another thread puts
+    /// into it, so that put has to wait.
+    fn contended_put(tree: &Arc<CaTree<usize, usize>>, key: usize) {
+        let guard = &epoch::pin();
+        let (base, _, _) = tree.find_base(&key, guard);
+        let held = base.data.lock().unwrap();
+        let writer = {
+            let tree = tree.clone();
+            thread::spawn(move || tree.put(key, key).unwrap())
+        };
+        thread::sleep(Duration::from_millis(5));
+        drop(held);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn put_get_remove_scan() {
+        let tree = CaTree::<usize, usize>::new();
+        let mut model = BTreeMap::new();
+        for i in 0..3000usize {
+            let key = i * 7919 % 3000;
+            tree.put(key, i).unwrap();
+            model.insert(key, i);
+            if i % 3 == 0 {
+                let key = i * 31 % 3000;
+                tree.remove(key).unwrap();
+                model.remove(&key);
+            }
+        }
+        for key in 0..3001usize {
+            assert_eq!(
+                tree.get(key).unwrap(),
+                model.get(&key).copied(),
+                "key {key}"
+            );
+        }
+        assert_eq!(tree.len(), model.len());
+        for start in [0usize, 1, 1500, 2990, 3000] {
+            let expected: Vec<_> = model
+                .range(start..)
+                .take(100)
+                .map(|(k, v)| (*k, *v))
+                .collect();
+            assert_eq!(
+                tree.scan(start, 100).unwrap(),
+                expected,
+                "scan from {start}"
+            );
+        }
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.get(42).unwrap(), None);
+    }
+
+    #[test]
+    fn scan_spans_bases() {
+        let tree = Arc::new(CaTree::bulk_load((0..100usize).map(|i| (i, i))).unwrap());
+        while tree.stats().splits == 0 {
+            contended_put(&tree, 50);
+        }
+        assert_eq!(
+            tree.scan(40, 20).unwrap(),
+            (40..60).map(|i| (i, i)).collect::<Vec<_>>()
+        );
+        assert_eq!(tree.scan(90, 20).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn disjoint_keys_across_threads() {
+        let tree = Arc::new(CaTree::<usize, usize>::new());
+        let threads = 4;
+        let per_thread = 5000;
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    for i in 0..per_thread {
+                        tree.put(i * threads + id, id).unwrap();
+                    }
+                    for i in (0..per_thread).step_by(2) {
+                        tree.remove(i * threads + id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(tree.len(), threads * per_thread / 2);
+        for key in 0..threads * per_thread {
+            let expected = (key / threads % 2 == 1).then_some(key % threads);
+            assert_eq!(tree.get(key).unwrap(), expected, "key {key}");
+        }
+    }
+
+    #[test]
+    fn contention_splits_and_quiet_joins() {
+        let tree = Arc::new(CaTree::bulk_load((0..100usize).map(|i| (i, i))).unwrap());
+        for _ in 0..100 {
+            if tree.stats().splits > 0 {
+                break;
+            }
+            contended_put(&tree, 50);
+        }
+        assert_eq!(tree.stats().splits, 1);
+        assert_eq!(tree.stats().joins, 0);
+
+        for _ in 0..=-JOIN_THRESHOLD {
+            assert_eq!(tree.get(10).unwrap(), Some(10));
+        }
+        assert_eq!(tree.stats().joins, 1);
+        assert_eq!(tree.len(), 100);
+        assert_eq!(
+            tree.scan(0, 200).unwrap(),
+            (0..100).map(|i| (i, i)).collect::<Vec<_>>()
+        );
+    }
+}
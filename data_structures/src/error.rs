@@ -0,0 +1,44 @@
+use std::{
+    convert::Infallible,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::interfaces::Capability;
+
+/// Why a tree operation failed. The tree is left unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeError {
+    /// The key does not fit the tree's fixed key size.
+    KeyTooLong { len: usize, max: usize },
+    /// The tree could not allocate a node.
+    OutOfMemory,
+    /// The tree does not implement the operation.
+    Unsupported(Capability),
+    /// A conditional write found a different value than it expected.
+    Conflict,
+}
+
+impl Display for TreeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::KeyTooLong { len, max } => {
+                write!(f, "key of {len} bytes is longer than {max}")
+            }
+            TreeError::OutOfMemory => f.write_str("out of memory"),
+            TreeError::Unsupported(capability) => write!(f, "{capability} not supported"),
+            TreeError::Conflict => f.write_str("conflicting value"),
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
+
+/// For key conversions that cannot fail, so trees can take any of them
+/// with `?`.
+impl From<Infallible> for TreeError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+pub type Result<T, E = TreeError> = std::result::Result<T, E>;
@@ -0,0 +1,665 @@
+use std::{marker::PhantomData, mem, sync::atomic::Ordering};
+
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+
+use crate::{
+    binary_search_tree::{DefaultParams, TreeParams},
+    error::{Result, TreeError},
+    interfaces::{Clear, Insert, KeyType, Len, MemoryUsage, PointRead, Remove, Tree, ValueType},
+};
+
+// State of an internal node, kept in the low bits of its `update` pointer.
+const CLEAN: usize = 0;
+const IFLAG: usize = 1;
+const DFLAG: usize = 2;
+const MARK: usize = 3;
+
+/// Keys of the two sentinel leaves, larger than every real key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Bound {
+    Key,
+    Inf1,
+    Inf2,
+}
+
+struct Node<T: TreeParams> {
+    bound: Bound,
+    key: T::IKeyType,
+    leaf: bool,
+    /// Leaves only. When a leaf is split its value moves to the copy.
+    value: *mut T::ValueType,
+    left: Atomic<Node<T>>,
+    right: Atomic<Node<T>>,
+    update: Atomic<Info<T>>,
+}
+
+impl<T: TreeParams> Node<T> {
+    fn leaf(bound: Bound, key: T::IKeyType, value: *mut T::ValueType) -> Owned<Self> {
+        Owned::new(Node {
+            bound,
+            key,
+            leaf: true,
+            value,
+            left: Atomic::null(),
+            right: Atomic::null(),
+            update: Atomic::null(),
+        })
+    }
+
+    fn internal<'g>(
+        bound: Bound,
+        key: T::IKeyType,
+        left: Shared<'g, Node<T>>,
+        right: Shared<'g, Node<T>>,
+    ) -> Owned<Self> {
+        Owned::new(Node {
+            bound,
+            key,
+            leaf: false,
+            value: std::ptr::null_mut(),
+            left: Atomic::from(left),
+            right: Atomic::from(right),
+            update: Atomic::null(),
+        })
+    }
+
+    #[inline(always)]
+    fn goes_left(&self, key: &T::IKeyType) -> bool {
+        self.bound != Bound::Key || *key < self.key
+    }
+
+    #[inline(always)]
+    fn matches(&self, key: &T::IKeyType) -> bool {
+        self.bound == Bound::Key && self.key == *key
+    }
+}
+
+/// Descriptor of a pending operation, published in `update` so that other
+/// threads can finish it instead of waiting.
+enum Info<T: TreeParams> {
+    Insert {
+        p: *const Node<T>,
+        l: *const Node<T>,
+        new: *const Node<T>,
+        /// `new` is a leaf replacing `l`, not an internal node above it.
+        replace: bool,
+    },
+    Delete {
+        gp: *const Node<T>,
+        p: *const Node<T>,
+        l: *const Node<T>,
+        pupdate: *const Info<T>,
+    },
+}
+
+struct Search<'g, T: TreeParams> {
+    gp: Shared<'g, Node<T>>,
+    p: Shared<'g, Node<T>>,
+    l: Shared<'g, Node<T>>,
+    gpupdate: Shared<'g, Info<T>>,
+    pupdate: Shared<'g, Info<T>>,
+}
+
+/// Lock-free external Binary Search Tree, after Ellen, Fatourou, Ruppert and
+/// van Breugel, "Non-blocking Binary Search Trees" (PODC '10).
+///
+/// Values live in leaves, internal nodes only route. Updates flag the parent
+/// (and grandparent for removals) with an info record, which any thread that
+/// runs into it helps complete. Unbalanced, like `LockFreeBinarySearchTree`.
+pub struct ExternalBinarySearchTree<T: TreeParams> {
+    _phantom: PhantomData<T>,
+    root: *mut Node<T>,
+}
+
+unsafe impl<T: TreeParams> Send for ExternalBinarySearchTree<T> {}
+unsafe impl<T: TreeParams> Sync for ExternalBinarySearchTree<T> {}
+
+impl<T: TreeParams> ExternalBinarySearchTree<T> {
+    fn search<'g>(&self, key: &T::IKeyType, guard: &'g Guard) -> Search<'g, T> {
+        let mut s = Search {
+            gp: Shared::null(),
+            p: Shared::null(),
+            l: Shared::from(self.root as *const Node<T>),
+            gpupdate: Shared::null(),
+            pupdate: Shared::null(),
+        };
+        while !unsafe { s.l.deref() }.leaf {
+            s.gp = s.p;
+            s.p = s.l;
+            s.gpupdate = s.pupdate;
+            let p = unsafe { s.p.deref() };
+            s.pupdate = p.update.load(Ordering::Acquire, guard);
+            s.l = if p.goes_left(key) {
+                p.left.load(Ordering::Acquire, guard)
+            } else {
+                p.right.load(Ordering::Acquire, guard)
+            };
+        }
+        s
+    }
+
+    fn help<'g>(&self, update: Shared<'g, Info<T>>, guard: &'g Guard) {
+        match update.tag() {
+            IFLAG => self.help_insert(update, guard),
+            MARK => self.help_marked(update, guard),
+            DFLAG => {
+                self.help_delete(update, guard);
+            }
+            _ => {}
+        }
+    }
+
+    fn help_insert<'g>(&self, op: Shared<'g, Info<T>>, guard: &'g Guard) {
+        let info = unsafe { op.deref() };
+        let &Info::Insert { p, l, new, replace } = info else {
+            unreachable!()
+        };
+        let p = unsafe { &*p };
+        if Self::cas_child(p, l, new, guard) {
+            // A split leaf handed its value to the copy next to `new`.
+            unsafe { Self::retire(l, replace, guard) };
+        }
+        let _ = p.update.compare_exchange(
+            op.with_tag(IFLAG),
+            op.with_tag(CLEAN),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            guard,
+        );
+    }
+
+    fn help_delete<'g>(&self, op: Shared<'g, Info<T>>, guard: &'g Guard) -> bool {
+        let info = unsafe { op.deref() };
+        let &Info::Delete { gp, p, pupdate, .. } = info else {
+            unreachable!()
+        };
+        let p = unsafe { &*p };
+        let expected = Shared::from(pupdate);
+        match p.update.compare_exchange(
+            expected,
+            op.with_tag(MARK),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            guard,
+        ) {
+            Ok(_) => {
+                if !expected.is_null() {
+                    unsafe { Self::retire_info(expected, guard) };
+                }
+                self.help_marked(op, guard);
+                true
+            }
+            Err(e) if e.current == op.with_tag(MARK) => {
+                self.help_marked(op, guard);
+                true
+            }
+            Err(e) => {
+                // `p` changed under us: help whoever won, then back off.
+                self.help(e.current, guard);
+                let _ = unsafe { &*gp }.update.compare_exchange(
+                    op.with_tag(DFLAG),
+                    op.with_tag(CLEAN),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    guard,
+                );
+                false
+            }
+        }
+    }
+
+    fn help_marked<'g>(&self, op: Shared<'g, Info<T>>, guard: &'g Guard) {
+        let info = unsafe { op.deref() };
+        let &Info::Delete { gp, p, l, .. } = info else {
+            unreachable!()
+        };
+        let gp = unsafe { &*gp };
+        let pn = unsafe { &*p };
+        let right = pn.right.load(Ordering::Acquire, guard);
+        let other = if right.as_raw() == l {
+            pn.left.load(Ordering::Acquire, guard)
+        } else {
+            right
+        };
+        if Self::cas_child(gp, p, other.as_raw(), guard) {
+            unsafe {
+                Self::retire(p, false, guard);
+                Self::retire(l, true, guard);
+            }
+        }
+        let _ = gp.update.compare_exchange(
+            op.with_tag(DFLAG),
+            op.with_tag(CLEAN),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            guard,
+        );
+    }
+
+    fn cas_child(
+        parent: &Node<T>,
+        old: *const Node<T>,
+        new: *const Node<T>,
+        guard: &Guard,
+    ) -> bool {
+        let link = if parent.left.load(Ordering::Acquire, guard).as_raw() == old {
+            &parent.left
+        } else {
+            &parent.right
+        };
+        link.compare_exchange(
+            Shared::from(old),
+            Shared::from(new),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+            guard,
+        )
+        .is_ok()
+    }
+
+    /// Frees an unlinked node. Its info record is reclaimed by whoever
+    /// replaces it in the node that stays in the tree.
+    unsafe fn retire(node: *const Node<T>, with_value: bool, guard: &Guard) {
+        let node = node as *mut Node<T>;
+        defer_twice(guard, move || {
+            let node = Box::from_raw(node);
+            if with_value && !node.value.is_null() {
+                drop(Box::from_raw(node.value));
+            }
+        });
+    }
+
+    unsafe fn retire_info(info: Shared<'_, Info<T>>, guard: &Guard) {
+        let info = info.as_raw() as *mut Info<T>;
+        defer_twice(guard, move || drop(Box::from_raw(info)));
+    }
+
+    /// Publishes `op` in `node.update` if it still holds `expected`.
+    fn flag<'g>(
+        node: &Node<T>,
+        expected: Shared<'g, Info<T>>,
+        op: Shared<'g, Info<T>>,
+        guard: &'g Guard,
+    ) -> Result<(), Shared<'g, Info<T>>> {
+        match node
+            .update
+            .compare_exchange(expected, op, Ordering::AcqRel, Ordering::Acquire, guard)
+        {
+            Ok(_) => {
+                if !expected.is_null() {
+                    unsafe { Self::retire_info(expected, guard) };
+                }
+                Ok(())
+            }
+            Err(e) => Err(e.current),
+        }
+    }
+
+    fn delete(&self, key: &T::IKeyType, guard: &Guard) {
+        loop {
+            let s = self.search(key, guard);
+            if !unsafe { s.l.deref() }.matches(key) {
+                return;
+            }
+            if s.gpupdate.tag() != CLEAN {
+                self.help(s.gpupdate, guard);
+                continue;
+            }
+            if s.pupdate.tag() != CLEAN {
+                self.help(s.pupdate, guard);
+                continue;
+            }
+            let op = Owned::new(Info::Delete {
+                gp: s.gp.as_raw(),
+                p: s.p.as_raw(),
+                l: s.l.as_raw(),
+                pupdate: s.pupdate.as_raw(),
+            })
+            .into_shared(guard)
+            .with_tag(DFLAG);
+
+            match Self::flag(unsafe { s.gp.deref() }, s.gpupdate, op, guard) {
+                Ok(()) => {
+                    if self.help_delete(op, guard) {
+                        return;
+                    }
+                }
+                Err(current) => {
+                    drop(unsafe { op.into_owned() });
+                    self.help(current, guard);
+                }
+            }
+        }
+    }
+
+    /// Calls `f` on every node reachable from the root, sentinels included.
+    fn for_each_node(&self, guard: &Guard, mut f: impl FnMut(&Node<T>)) {
+        let mut stack = vec![Shared::from(self.root as *const Node<T>)];
+        while let Some(node) = stack.pop() {
+            let node = unsafe { node.deref() };
+            if !node.leaf {
+                stack.push(node.right.load(Ordering::Acquire, guard));
+                stack.push(node.left.load(Ordering::Acquire, guard));
+            }
+            f(node);
+        }
+    }
+}
+
+/// Runs `f` after two grace periods instead of one.
+///
+/// Helpers compare against addresses taken from an info record (the expected
+/// `pupdate`, the leaf or parent to swing away from) after reading the record
+/// from a flagged node. A helper that pinned after the address was retired
+/// is not covered by the first grace period, but it must have read the
+/// record before its owner unflagged the node and unpinned, so the second
+/// grace period covers it and the address cannot be reused under its CAS.
+unsafe fn defer_twice<F: FnOnce()>(guard: &Guard, f: F) {
+    guard.defer_unchecked(move || epoch::pin().defer_unchecked(f));
+}
+
+impl<T: TreeParams<ValueType = V>, K, V> Tree<K, V> for ExternalBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType, Error = TreeError> + KeyType,
+    V: ValueType,
+    T::IKeyType: for<'a> TryFrom<&'a str, Error = TreeError>,
+{
+    fn new() -> Self {
+        let guard = unsafe { epoch::unprotected() };
+        // Sentinels ignore their key, and an empty one always fits.
+        let empty = || T::IKeyType::try_from("").unwrap();
+        let inf1 = Node::leaf(Bound::Inf1, empty(), std::ptr::null_mut()).into_shared(guard);
+        let inf2 = Node::leaf(Bound::Inf2, empty(), std::ptr::null_mut()).into_shared(guard);
+        let root = Node::internal(Bound::Inf2, empty(), inf1, inf2);
+        ExternalBinarySearchTree {
+            _phantom: PhantomData,
+            root: Box::into_raw(root.into_box()),
+        }
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+}
+
+impl<T: TreeParams<ValueType = V>, K, V> PointRead<K, V> for ExternalBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType, Error = TreeError> + KeyType,
+    V: ValueType,
+{
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let key = key.try_into()?;
+        let guard = &epoch::pin();
+        let l = unsafe { self.search(&key, guard).l.deref() };
+        if l.matches(&key) {
+            Ok(Some(f(unsafe { &*l.value })))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: TreeParams<ValueType = V>, K, V> Insert<K, V> for ExternalBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType, Error = TreeError> + KeyType,
+    V: ValueType,
+{
+    fn put(&self, key: K, value: V) -> Result<()> {
+        let key = key.try_into()?;
+        let value = Box::into_raw(Box::new(value));
+        let guard = &epoch::pin();
+        loop {
+            let s = self.search(&key, guard);
+            if s.pupdate.tag() != CLEAN {
+                self.help(s.pupdate, guard);
+                continue;
+            }
+            let l = unsafe { s.l.deref() };
+            let replace = l.matches(&key);
+            let new = if replace {
+                Node::leaf(Bound::Key, key.clone(), value)
+            } else {
+                let leaf = Node::leaf(Bound::Key, key.clone(), value).into_shared(guard);
+                let sibling = Node::leaf(l.bound, l.key.clone(), l.value).into_shared(guard);
+                if l.goes_left(&key) {
+                    Node::internal(l.bound, l.key.clone(), leaf, sibling)
+                } else {
+                    Node::internal(Bound::Key, key.clone(), sibling, leaf)
+                }
+            }
+            .into_shared(guard);
+            let op = Owned::new(Info::Insert {
+                p: s.p.as_raw(),
+                l: s.l.as_raw(),
+                new: new.as_raw(),
+                replace,
+            })
+            .into_shared(guard)
+            .with_tag(IFLAG);
+
+            match Self::flag(unsafe { s.p.deref() }, s.pupdate, op, guard) {
+                Ok(()) => {
+                    self.help_insert(op, guard);
+                    return Ok(());
+                }
+                Err(current) => unsafe {
+                    // Never published: free the attempt, but keep the value.
+                    drop(op.into_owned());
+                    let new = new.into_owned();
+                    if !replace {
+                        drop(new.left.load(Ordering::Relaxed, guard).into_owned());
+                        drop(new.right.load(Ordering::Relaxed, guard).into_owned());
+                    }
+                    drop(new);
+                    self.help(current, guard);
+                },
+            }
+        }
+    }
+}
+
+impl<T: TreeParams<ValueType = V>, K, V> Remove<K, V> for ExternalBinarySearchTree<T>
+where
+    K: TryInto<T::IKeyType, Error = TreeError> + KeyType,
+    V: ValueType,
+{
+    fn remove(&self, key: K) -> Result<()> {
+        self.delete(&key.try_into()?, &epoch::pin());
+        Ok(())
+    }
+}
+
+impl<T: TreeParams> Len for ExternalBinarySearchTree<T> {
+    fn len(&self) -> usize {
+        let guard = &epoch::pin();
+        let mut len = 0;
+        self.for_each_node(guard, |node| {
+            if node.leaf && node.bound == Bound::Key {
+                len += 1;
+            }
+        });
+        len
+    }
+}
+
+impl<T: TreeParams> Clear for ExternalBinarySearchTree<T> {
+    fn clear(&self) {
+        let guard = &epoch::pin();
+        let mut keys = Vec::new();
+        self.for_each_node(guard, |node| {
+            if node.leaf && node.bound == Bound::Key {
+                keys.push(node.key.clone());
+            }
+        });
+        for key in keys.iter() {
+            self.delete(key, guard);
+        }
+    }
+}
+
+impl<T: TreeParams> MemoryUsage for ExternalBinarySearchTree<T> {
+    fn memory_usage(&self) -> usize {
+        let guard = &epoch::pin();
+        let mut bytes = 0;
+        self.for_each_node(guard, |node| {
+            bytes += mem::size_of::<Node<T>>();
+            if !node.value.is_null() {
+                bytes += mem::size_of::<T::ValueType>();
+            }
+        });
+        bytes
+    }
+}
+
+impl<T: TreeParams> Drop for ExternalBinarySearchTree<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut infos = Vec::new();
+            let mut stack = vec![Shared::from(self.root as *const Node<T>)];
+            while let Some(node) = stack.pop() {
+                let node = node.into_owned();
+                if node.leaf {
+                    if !node.value.is_null() {
+                        drop(Box::from_raw(node.value));
+                    }
+                } else {
+                    stack.push(node.left.load(Ordering::Relaxed, guard));
+                    stack.push(node.right.load(Ordering::Relaxed, guard));
+                    let info = node.update.load(Ordering::Relaxed, guard).with_tag(0);
+                    if !info.is_null() {
+                        infos.push(info.as_raw());
+                    }
+                }
+            }
+            // Only nodes left in the tree are walked, so no record repeats.
+            for info in infos {
+                drop(Shared::from(info).into_owned());
+            }
+        }
+    }
+}
+
+pub type ExternalBST = ExternalBinarySearchTree<DefaultParams>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::Arc, thread};
+
+    fn new_tree() -> ExternalBST {
+        <ExternalBST as Tree<String, String>>::new()
+    }
+
+    fn key(i: usize) -> String {
+        format!("k{i:05}")
+    }
+
+    #[test]
+    fn empty_tree_only_has_sentinels() {
+        let tree = new_tree();
+        assert_eq!(tree.get(key(1)).unwrap(), None);
+        assert_eq!(tree.get(String::new()).unwrap(), None);
+        tree.remove(key(1)).unwrap();
+        tree.remove(String::new()).unwrap();
+        assert_eq!(tree.len(), 0);
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(
+            tree.memory_usage(),
+            3 * mem::size_of::<Node<DefaultParams>>()
+        );
+    }
+
+    #[test]
+    fn keys_next_to_the_sentinels() {
+        let tree = new_tree();
+        // The sentinels carry the empty key, and the longest keys sort last.
+        let largest = "\u{10FFFF}".repeat(4);
+        let keys = [String::new(), "\u{1}".to_string(), largest.clone()];
+        for (i, key) in keys.iter().enumerate() {
+            tree.put(key.clone(), i.to_string()).unwrap();
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(tree.get(key.clone()).unwrap(), Some(i.to_string()));
+        }
+        assert_eq!(tree.len(), 3);
+        assert!(matches!(
+            tree.put(format!("{largest}x"), String::new()),
+            Err(TreeError::KeyTooLong { .. })
+        ));
+        tree.remove(largest.clone()).unwrap();
+        tree.remove(String::new()).unwrap();
+        assert_eq!(tree.get(largest).unwrap(), None);
+        assert_eq!(tree.get(String::new()).unwrap(), None);
+        assert_eq!(
+            tree.get("\u{1}".to_string()).unwrap(),
+            Some("1".to_string())
+        );
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+    }
+
+    #[test]
+    fn matches_model() {
+        let tree = new_tree();
+        let mut model = BTreeMap::new();
+        for i in 0..2000 {
+            let k = key(i * 7919 % 2000);
+            tree.put(k.clone(), i.to_string()).unwrap();
+            model.insert(k, i.to_string());
+            if i % 3 == 0 {
+                let k = key(i * 31 % 2000);
+                tree.remove(k.clone()).unwrap();
+                model.remove(&k);
+            }
+        }
+        for i in 0..2001 {
+            assert_eq!(
+                tree.get(key(i)).unwrap().as_ref(),
+                model.get(&key(i)),
+                "key {i}"
+            );
+        }
+        assert_eq!(tree.len(), model.len());
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        tree.put(key(1), "again".to_string()).unwrap();
+        assert_eq!(tree.get(key(1)).unwrap(), Some("again".to_string()));
+    }
+
+    #[test]
+    fn overlapping_writers_help_each_other() {
+        let tree = Arc::new(new_tree());
+        let threads = 4;
+        let keys = 32;
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    for round in 0..2000 {
+                        let k = (round * 7 + id * 5) % keys;
+                        if (round + id) % 3 == 0 {
+                            tree.remove(key(k)).unwrap();
+                        } else {
+                            tree.put(key(k), format!("{k}-{id}")).unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut present = 0;
+        for k in 0..keys {
+            if let Some(value) = tree.get(key(k)).unwrap() {
+                assert!(value.starts_with(&format!("{k}-")), "{value} under {k}");
+                present += 1;
+            }
+        }
+        assert_eq!(tree.len(), present);
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+    }
+}
@@ -4,6 +4,8 @@ use std::{
     str::Utf8Error,
 };
 
+use crate::error::TreeError;
+
 pub trait AsRaw {
     fn as_raw(&self) -> &[u8];
 }
@@ -161,30 +163,54 @@ where
         }
     }
 }
-impl<T: FixSizedKeyParams> From<&[u8]> for FixSizedKey<T>
+impl<T: FixSizedKeyParams> TryFrom<&[u8]> for FixSizedKey<T>
 where
     [(); T::KEY_SIZE]: Sized,
     [(); T::KEY_SIZE / 8]: Sized,
 {
-    fn from(value: &[u8]) -> Self {
+    type Error = TreeError;
+
+    fn try_from(value: &[u8]) -> Result<Self, TreeError> {
+        if value.len() > T::KEY_SIZE {
+            return Err(TreeError::KeyTooLong {
+                len: value.len(),
+                max: T::KEY_SIZE,
+            });
+        }
         let mut buf: [u8; T::KEY_SIZE] = [0; T::KEY_SIZE];
-        assert!(value.len() <= T::KEY_SIZE, "key size is too large");
         buf[..value.len()].copy_from_slice(value);
-        return Self {
+        return Ok(Self {
             key_u_: FixSizedKeyInner { c_: buf },
             _phantom: PhantomData,
-        };
+        });
     }
 }
 
-impl<T: FixSizedKeyParams, K: AsRaw> From<K> for FixSizedKey<T>
+// Spelled out per `AsRaw` type: a blanket `TryFrom<K: AsRaw>` would overlap
+// with the standard `TryFrom<U: Into<T>>`.
+impl<T: FixSizedKeyParams> TryFrom<String> for FixSizedKey<T>
 where
     [(); T::KEY_SIZE]: Sized,
     [(); T::KEY_SIZE / 8]: Sized,
 {
+    type Error = TreeError;
+
     #[inline(always)]
-    fn from(value: K) -> Self {
-        return Self::from(value.as_raw());
+    fn try_from(value: String) -> Result<Self, TreeError> {
+        return Self::try_from(value.as_raw());
+    }
+}
+
+impl<'a, T: FixSizedKeyParams> TryFrom<&'a str> for FixSizedKey<T>
+where
+    [(); T::KEY_SIZE]: Sized,
+    [(); T::KEY_SIZE / 8]: Sized,
+{
+    type Error = TreeError;
+
+    #[inline(always)]
+    fn try_from(value: &'a str) -> Result<Self, TreeError> {
+        return Self::try_from(value.as_raw());
     }
 }
 
@@ -243,8 +269,8 @@ mod tests {
     #[test]
     fn fix_sized_key_works() {
         type FixSizedKey_ = FixSizedKey<Params>;
-        let k1: FixSizedKey_ = FixSizedKey_::from("hello");
-        let k2 = "world".into();
+        let k1: FixSizedKey_ = FixSizedKey_::try_from("hello").unwrap();
+        let k2 = "world".try_into().unwrap();
         let k3 = k1.clone();
         assert_eq!(k1, k3);
         assert_ne!(k1, k2);
@@ -252,4 +278,14 @@ mod tests {
         println!("{}", k1);
         println!("{}", k2);
     }
+
+    #[test]
+    fn oversized_key_is_rejected() {
+        type FixSizedKey_ = FixSizedKey<Params>;
+        assert_eq!(
+            FixSizedKey_::try_from("seventeen bytes!!"),
+            Err(TreeError::KeyTooLong { len: 17, max: 16 })
+        );
+        assert!(FixSizedKey_::try_from("sixteen bytes!!!").is_ok());
+    }
 }
@@ -0,0 +1,545 @@
+use std::{
+    cmp::Ordering as CmpOrdering,
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    mem,
+    ptr::{self, null_mut},
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+
+use crate::error::Result;
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, PointRead, Remove, Tree, ValueType,
+};
+
+/// Average number of items per bucket before the table doubles.
+const LOAD_FACTOR: usize = 2;
+/// Segment `s` holds buckets `[2^(s-1), 2^s)`, segment 0 holds bucket 0.
+const MAX_SEGMENTS: usize = 48;
+
+struct Node<K, V> {
+    /// Bit-reversed hash; odd for items, even for bucket sentinels.
+    so_key: u64,
+    /// `None` for bucket sentinels.
+    key: Option<K>,
+    /// Null once the item is logically removed.
+    value: Atomic<V>,
+    /// Tagged with 1 once the node is marked for unlinking.
+    next: Link<K, V>,
+}
+
+type Link<K, V> = Atomic<Node<K, V>>;
+
+impl<K: Ord, V> Node<K, V> {
+    fn cmp_to(&self, so_key: u64, key: Option<&K>) -> CmpOrdering {
+        self.so_key
+            .cmp(&so_key)
+            .then_with(|| self.key.as_ref().cmp(&key))
+    }
+}
+
+#[inline(always)]
+fn item_so_key(hash: u64) -> u64 {
+    (hash | 1 << 63).reverse_bits()
+}
+
+#[inline(always)]
+fn sentinel_so_key(bucket: usize) -> u64 {
+    (bucket as u64).reverse_bits()
+}
+
+#[inline(always)]
+fn segment_of(bucket: usize) -> (usize, usize) {
+    if bucket == 0 {
+        (0, 0)
+    } else {
+        let segment = (usize::BITS - bucket.leading_zeros()) as usize;
+        (segment, bucket - (1 << (segment - 1)))
+    }
+}
+
+#[inline(always)]
+fn segment_len(segment: usize) -> usize {
+    if segment == 0 {
+        1
+    } else {
+        1 << (segment - 1)
+    }
+}
+
+/// Lock-free resizable hash map using split-ordered lists (Shalev & Shavit).
+///
+/// All items live in one Harris-Michael linked list sorted by bit-reversed
+/// hash; buckets are shortcuts to sentinel nodes in that list, created lazily,
+/// so doubling the table never moves an item. Items are unordered by key,
+/// hence no scans.
+pub struct LockFreeHashMap<K, V> {
+    segments: [AtomicPtr<AtomicPtr<Node<K, V>>>; MAX_SEGMENTS],
+    /// Number of buckets in use, always a power of two.
+    size: AtomicUsize,
+    count: AtomicUsize,
+    hasher: RandomState,
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> LockFreeHashMap<K, V> {
+    fn bucket_slot(&self, bucket: usize) -> &AtomicPtr<Node<K, V>> {
+        let (segment, offset) = segment_of(bucket);
+        let mut slots = self.segments[segment].load(Ordering::Acquire);
+        if slots.is_null() {
+            let fresh = Box::into_raw(
+                (0..segment_len(segment))
+                    .map(|_| AtomicPtr::<Node<K, V>>::new(null_mut()))
+                    .collect::<Box<[_]>>(),
+            ) as *mut AtomicPtr<Node<K, V>>;
+            match self.segments[segment].compare_exchange(
+                null_mut(),
+                fresh,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => slots = fresh,
+                Err(current) => {
+                    unsafe { free_segment(fresh, segment) };
+                    slots = current;
+                }
+            }
+        }
+        unsafe { &*slots.add(offset) }
+    }
+
+    /// The sentinel of `bucket`, inserting it (and its parents) if needed.
+    fn bucket_head<'g>(&self, bucket: usize, guard: &'g Guard) -> &'g Link<K, V> {
+        let slot = self.bucket_slot(bucket);
+        let sentinel = slot.load(Ordering::Acquire);
+        if !sentinel.is_null() {
+            return unsafe { &(*sentinel).next };
+        }
+
+        // The parent bucket is this one with its highest bit cleared.
+        let parent = bucket & !(1 << (usize::BITS - 1 - bucket.leading_zeros()));
+        let parent_head = self.bucket_head(parent, guard);
+        let so_key = sentinel_so_key(bucket);
+        let mut node = Owned::new(Node {
+            so_key,
+            key: None,
+            value: Atomic::null(),
+            next: Atomic::null(),
+        });
+        let sentinel = loop {
+            let (prev, curr, found) = self.find(parent_head, so_key, None, guard);
+            if found {
+                break curr;
+            }
+            node.next.store(curr, Ordering::Relaxed);
+            match prev.compare_exchange(curr, node, Ordering::AcqRel, Ordering::Acquire, guard) {
+                Ok(inserted) => break inserted,
+                Err(e) => node = e.new,
+            }
+        };
+        let _ = slot.compare_exchange(
+            null_mut(),
+            sentinel.as_raw() as *mut Node<K, V>,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+        unsafe { &sentinel.deref().next }
+    }
+
+    fn head_for<'g>(&self, hash: u64, guard: &'g Guard) -> &'g Link<K, V> {
+        let size = self.size.load(Ordering::Acquire);
+        self.bucket_head(hash as usize & (size - 1), guard)
+    }
+
+    /// Finds the first node not below `(so_key, key)`, unlinking marked nodes
+    /// on the way. Returns the link pointing at it and whether it matches.
+    fn find<'g>(
+        &self,
+        head: &'g Link<K, V>,
+        so_key: u64,
+        key: Option<&K>,
+        guard: &'g Guard,
+    ) -> (&'g Link<K, V>, Shared<'g, Node<K, V>>, bool) {
+        'retry: loop {
+            let mut prev = head;
+            let mut curr = prev.load(Ordering::Acquire, guard);
+            loop {
+                let node = match unsafe { curr.as_ref() } {
+                    Some(node) => node,
+                    None => return (prev, curr, false),
+                };
+                let next = node.next.load(Ordering::Acquire, guard);
+                if next.tag() == 1 {
+                    match prev.compare_exchange(
+                        curr,
+                        next.with_tag(0),
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                        guard,
+                    ) {
+                        Ok(_) => {
+                            unsafe { guard.defer_destroy(curr) };
+                            curr = next.with_tag(0);
+                            continue;
+                        }
+                        Err(_) => continue 'retry,
+                    }
+                }
+                match node.cmp_to(so_key, key) {
+                    CmpOrdering::Less => {
+                        prev = &node.next;
+                        curr = next;
+                    }
+                    CmpOrdering::Equal => return (prev, curr, true),
+                    CmpOrdering::Greater => return (prev, curr, false),
+                }
+            }
+        }
+    }
+
+    fn grow(&self, count: usize) {
+        let size = self.size.load(Ordering::Relaxed);
+        if count / size > LOAD_FACTOR && size < 1 << (MAX_SEGMENTS - 1) {
+            let _ = self
+                .size
+                .compare_exchange(size, size * 2, Ordering::AcqRel, Ordering::Relaxed);
+        }
+    }
+
+    /// Calls `f` on every node of the list, sentinels and marked nodes
+    /// included.
+    fn for_each_node(&self, guard: &Guard, mut f: impl FnMut(&Node<K, V>)) {
+        let first = self.bucket_slot(0).load(Ordering::Acquire) as *const Node<K, V>;
+        let mut node = Shared::from(first);
+        while let Some(current) = unsafe { node.as_ref() } {
+            f(current);
+            node = current.next.load(Ordering::Acquire, guard).with_tag(0);
+        }
+    }
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> Tree<K, V> for LockFreeHashMap<K, V> {
+    fn new() -> Self {
+        let map = LockFreeHashMap {
+            segments: std::array::from_fn(|_| AtomicPtr::new(null_mut())),
+            size: AtomicUsize::new(2),
+            count: AtomicUsize::new(0),
+            hasher: RandomState::new(),
+        };
+        let sentinel = Owned::new(Node {
+            so_key: sentinel_so_key(0),
+            key: None,
+            value: Atomic::null(),
+            next: Atomic::null(),
+        });
+        map.bucket_slot(0)
+            .store(Box::into_raw(sentinel.into_box()), Ordering::Release);
+        map
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> PointRead<K, V>
+    for LockFreeHashMap<K, V>
+{
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let guard = &epoch::pin();
+        let hash = self.hasher.hash_one(&key);
+        let so_key = item_so_key(hash);
+        let head = self.head_for(hash, guard);
+        let (_, curr, found) = self.find(head, so_key, Some(&key), guard);
+        if !found {
+            return Ok(None);
+        }
+        let value = unsafe { curr.deref() }.value.load(Ordering::Acquire, guard);
+        Ok(unsafe { value.as_ref() }.map(f))
+    }
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> Insert<K, V> for LockFreeHashMap<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        let guard = &epoch::pin();
+        let hash = self.hasher.hash_one(&key);
+        let so_key = item_so_key(hash);
+        let head = self.head_for(hash, guard);
+        let value = Owned::new(value).into_shared(guard);
+        let mut node = Owned::new(Node {
+            so_key,
+            key: Some(key),
+            value: Atomic::null(),
+            next: Atomic::null(),
+        });
+
+        loop {
+            let (prev, curr, found) = self.find(head, so_key, node.key.as_ref(), guard);
+            if found {
+                let existing = unsafe { curr.deref() };
+                let old = existing.value.load(Ordering::Acquire, guard);
+                if old.is_null() {
+                    // Being removed: help mark it so `find` unlinks it.
+                    existing.next.fetch_or(1, Ordering::AcqRel, guard);
+                    continue;
+                }
+                if existing
+                    .value
+                    .compare_exchange(old, value, Ordering::AcqRel, Ordering::Acquire, guard)
+                    .is_ok()
+                {
+                    unsafe { guard.defer_destroy(old) };
+                    return Ok(());
+                }
+                continue;
+            }
+
+            node.value.store(value, Ordering::Relaxed);
+            node.next.store(curr, Ordering::Relaxed);
+            match prev.compare_exchange(curr, node, Ordering::AcqRel, Ordering::Acquire, guard) {
+                Ok(_) => {
+                    let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+                    self.grow(count);
+                    return Ok(());
+                }
+                Err(e) => node = e.new,
+            }
+        }
+    }
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> Remove<K, V> for LockFreeHashMap<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        let guard = &epoch::pin();
+        let hash = self.hasher.hash_one(&key);
+        let so_key = item_so_key(hash);
+        let head = self.head_for(hash, guard);
+
+        loop {
+            let (_, curr, found) = self.find(head, so_key, Some(&key), guard);
+            if !found {
+                return Ok(());
+            }
+            let node = unsafe { curr.deref() };
+            let old = node.value.load(Ordering::Acquire, guard);
+            if old.is_null() {
+                // A concurrent remove won, finish its unlink.
+                node.next.fetch_or(1, Ordering::AcqRel, guard);
+                self.find(head, so_key, Some(&key), guard);
+                return Ok(());
+            }
+            if node
+                .value
+                .compare_exchange(
+                    old,
+                    Shared::null(),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    guard,
+                )
+                .is_ok()
+            {
+                unsafe { guard.defer_destroy(old) };
+                node.next.fetch_or(1, Ordering::AcqRel, guard);
+                self.find(head, so_key, Some(&key), guard);
+                self.count.fetch_sub(1, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> Len for LockFreeHashMap<K, V> {
+    fn len(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> Clear for LockFreeHashMap<K, V> {
+    fn clear(&self) {
+        let mut keys = Vec::new();
+        self.for_each_node(&epoch::pin(), |node| {
+            if let Some(key) = node.key.as_ref() {
+                keys.push(key.clone());
+            }
+        });
+        for key in keys {
+            self.remove(key).unwrap();
+        }
+    }
+}
+
+impl<K: KeyType + Hash + 'static, V: ValueType + 'static> MemoryUsage for LockFreeHashMap<K, V> {
+    fn memory_usage(&self) -> usize {
+        let guard = &epoch::pin();
+        let mut bytes = 0;
+        for (segment, slots) in self.segments.iter().enumerate() {
+            if !slots.load(Ordering::Acquire).is_null() {
+                bytes += segment_len(segment) * mem::size_of::<AtomicPtr<Node<K, V>>>();
+            }
+        }
+        self.for_each_node(guard, |node| {
+            bytes += mem::size_of::<Node<K, V>>();
+            if !node.value.load(Ordering::Acquire, guard).is_null() {
+                bytes += mem::size_of::<V>();
+            }
+        });
+        bytes
+    }
+}
+
+unsafe fn free_segment<T>(slots: *mut AtomicPtr<T>, segment: usize) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+        slots,
+        segment_len(segment),
+    )));
+}
+
+impl<K, V> Drop for LockFreeHashMap<K, V> {
+    fn drop(&mut self) {
+        unsafe {
+            let guard = epoch::unprotected();
+            // Every node, sentinels included, hangs off the bucket 0 sentinel.
+            let first = &*self.segments[0].load(Ordering::Relaxed);
+            let mut node = Shared::from(first.load(Ordering::Relaxed) as *const Node<K, V>);
+            while !node.is_null() {
+                let next = node.deref().next.load(Ordering::Relaxed, guard);
+                let value = node.deref().value.load(Ordering::Relaxed, guard);
+                if !value.is_null() {
+                    drop(value.into_owned());
+                }
+                drop(node.into_owned());
+                node = next.with_tag(0);
+            }
+            for (segment, slots) in self.segments.iter().enumerate() {
+                let slots = slots.load(Ordering::Relaxed);
+                if !slots.is_null() {
+                    free_segment(slots, segment);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::Arc, thread};
+
+    fn new_map() -> LockFreeHashMap<usize, usize> {
+        <LockFreeHashMap<usize, usize> as Tree<usize, usize>>::new()
+    }
+
+    fn is_initialized(map: &LockFreeHashMap<usize, usize>, bucket: usize) -> bool {
+        !map.bucket_slot(bucket).load(Ordering::Acquire).is_null()
+    }
+
+    /// The split-order keys of the whole list, which must be sorted.
+    fn so_keys(map: &LockFreeHashMap<usize, usize>) -> Vec<u64> {
+        let mut keys = Vec::new();
+        map.for_each_node(&epoch::pin(), |node| keys.push(node.so_key));
+        assert!(keys.windows(2).all(|pair| pair[0] <= pair[1]));
+        keys
+    }
+
+    #[test]
+    fn buckets_initialize_through_their_parents() {
+        let map = new_map();
+        let guard = &epoch::pin();
+        // 13 splits from 5, which splits from 1, which splits from 0.
+        map.bucket_head(13, guard);
+        for bucket in [0, 1, 5, 13] {
+            assert!(is_initialized(&map, bucket), "bucket {bucket}");
+        }
+        for bucket in [2, 3, 4, 9] {
+            assert!(!is_initialized(&map, bucket), "bucket {bucket}");
+        }
+        let mut expected: Vec<_> = [0, 1, 5, 13].map(sentinel_so_key).into();
+        expected.sort();
+        assert_eq!(so_keys(&map), expected);
+        // Initializing again finds the sentinel instead of adding one.
+        map.bucket_head(13, guard);
+        assert_eq!(so_keys(&map).len(), 4);
+    }
+
+    #[test]
+    fn grows_through_several_doublings() {
+        let map = new_map();
+        let count = 5000;
+        for key in 0..count {
+            map.put(key, key * 2).unwrap();
+        }
+        let size = map.size.load(Ordering::Relaxed);
+        assert!(size >= 1024, "size {size} after {count} puts");
+        assert!(size.is_power_of_two());
+        for key in 0..count {
+            assert_eq!(map.get(key).unwrap(), Some(key * 2));
+        }
+        let items = so_keys(&map)
+            .iter()
+            .filter(|so_key| *so_key & 1 == 1)
+            .count();
+        assert_eq!(items, count);
+        assert_eq!(map.len(), count);
+    }
+
+    #[test]
+    fn matches_model() {
+        let map = new_map();
+        let mut model = BTreeMap::new();
+        for i in 0..4000usize {
+            let key = i * 7919 % 4000;
+            map.put(key, i).unwrap();
+            model.insert(key, i);
+            if i % 3 == 0 {
+                let key = i * 31 % 4000;
+                map.remove(key).unwrap();
+                model.remove(&key);
+            }
+        }
+        for key in 0..4001usize {
+            assert_eq!(map.get(key).unwrap(), model.get(&key).copied(), "key {key}");
+        }
+        assert_eq!(map.len(), model.len());
+        map.clear();
+        assert_eq!(map.len(), 0);
+        assert!(so_keys(&map).iter().all(|so_key| so_key & 1 == 0));
+    }
+
+    #[test]
+    fn has_no_range_scan() {
+        let map = new_map();
+        assert!(map.as_range_scan().is_none());
+        assert!(map.as_remove().is_some());
+    }
+
+    #[test]
+    fn disjoint_keys_across_threads() {
+        let map = Arc::new(new_map());
+        let threads = 4;
+        let per_thread = 5000;
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    for i in 0..per_thread {
+                        map.put(i * threads + id, id).unwrap();
+                    }
+                    for i in (0..per_thread).step_by(2) {
+                        map.remove(i * threads + id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(map.len(), threads * per_thread / 2);
+        for key in 0..threads * per_thread {
+            let expected = (key / threads % 2 == 1).then_some(key % threads);
+            assert_eq!(map.get(key).unwrap(), expected, "key {key}");
+        }
+        so_keys(&map);
+    }
+}
@@ -1,27 +1,166 @@
-use std::fmt::{Debug, Display};
+use std::fmt::{self, Debug, Display, Formatter};
+
+use crate::error::Result;
 
 pub trait KeyType = Debug + Clone + Display + Ord + Send + Sync;
 
 pub trait ValueType = Debug + Display + Clone + PartialEq + Send + Sync;
 
-pub enum GetType {
-    GetRef,
-    GetVal,
+pub trait PointRead<K, V: Clone> {
+    /// Calls `f` on the value under `key`, if any. Trees that can hand out a
+    /// reference into their storage do; the others pass a copy.
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>>;
+    fn get(&self, key: K) -> Result<Option<V>> {
+        self.get_with(key, V::clone)
+    }
+    /// The values under `keys`, in the same order. Trees that can overlap
+    /// the lookups override this.
+    fn multi_get(&self, keys: &[K]) -> Result<Vec<Option<V>>>
+    where
+        K: Clone,
+    {
+        keys.iter().map(|key| self.get(key.clone())).collect()
+    }
 }
 
-#[allow(unused_variables)]
-pub trait Tree<K: KeyType, V: ValueType>: Send + Sync {
-    const GET_TYPE: GetType = GetType::GetRef;
-
-    fn put(&self, key: K, value: V);
-    fn get(&self, key: K) -> Option<&V> {
-        panic!("not implemented")
+pub trait Insert<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()>;
+    /// Puts every pair in turn, stopping at the first failure. Trees that
+    /// insert faster in bulk override this.
+    fn multi_put(&self, items: &[(K, V)]) -> Result<()>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        for (key, value) in items {
+            self.put(key.clone(), value.clone())?;
+        }
+        Ok(())
     }
-    fn get_val(&self, key: K) -> Option<V> {
-        panic!("not implemented")
+}
+
+pub trait Remove<K, V> {
+    fn remove(&self, key: K) -> Result<()>;
+}
+
+pub trait RangeScan<K, V> {
+    /// Up to `n` entries with keys `>= start`, in key order.
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>>;
+}
+
+/// Lookups relative to a key, in the tree's key order. The key passed in
+/// need not be present. Bounds follow `crossbeam_skiplist::SkipMap`.
+pub trait Ordered<K, V> {
+    fn first(&self) -> Result<Option<(K, V)>>;
+    fn last(&self) -> Result<Option<(K, V)>>;
+    /// The entry with the smallest key `>= key`, its ceiling.
+    fn lower_bound(&self, key: K) -> Result<Option<(K, V)>>;
+    /// The entry with the largest key `<= key`, its floor.
+    fn upper_bound(&self, key: K) -> Result<Option<(K, V)>>;
+    /// The entry with the smallest key `> key`.
+    fn successor(&self, key: K) -> Result<Option<(K, V)>>;
+    /// The entry with the largest key `< key`.
+    fn predecessor(&self, key: K) -> Result<Option<(K, V)>>;
+}
+
+/// Writes that depend on the current value, done atomically with reading it.
+pub trait ReadModifyWrite<K, V> {
+    /// Inserts `value` unless `key` is present. Returns whether it did.
+    fn insert_if_absent(&self, key: K, value: V) -> Result<bool>;
+    /// Replaces the value under `key` by `new` if it equals `expected`, and
+    /// fails with `TreeError::Conflict` if it is different or missing.
+    fn compare_exchange(&self, key: K, expected: &V, new: V) -> Result<()>;
+    /// Replaces the value under `key` by `f` of it, leaving missing keys
+    /// alone. `f` may run again if another write gets in between. Returns
+    /// the value it replaced.
+    fn update(&self, key: K, f: &mut dyn FnMut(&V) -> V) -> Result<Option<V>>;
+}
+
+/// Trees without a counter walk their nodes, so the result is only exact
+/// while no other thread writes.
+pub trait Len {
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
     }
-    fn remove(&self, key: K) {
-        panic!("not implemented")
+}
+
+pub trait Clear {
+    /// Removes every key. Operations running at the same time may see some
+    /// of them gone and others not yet.
+    fn clear(&self);
+}
+
+pub trait MemoryUsage {
+    /// Bytes allocated for the tree's nodes, and for keys and values where
+    /// the tree boxes them, not counting heap memory they own themselves.
+    /// Adapters over crates that hide their layout count only the entries,
+    /// a lower bound.
+    fn memory_usage(&self) -> usize;
+}
+
+/// Operations beyond reads and inserts, which not every tree has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Remove,
+    RangeScan,
+    ReadModifyWrite,
+    Ordered,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Remove => "remove",
+            Capability::RangeScan => "range scan",
+            Capability::ReadModifyWrite => "read-modify-write",
+            Capability::Ordered => "ordered navigation",
+        })
     }
+}
+
+/// A concurrent map every benchmark can run against. The optional
+/// capabilities are reached through the `as_*` accessors, which return
+/// `None` unless the tree implements them, so generic code can check for
+/// them up front instead of hitting a missing operation mid-run.
+pub trait Tree<K: KeyType, V: ValueType>:
+    PointRead<K, V> + Insert<K, V> + Len + Clear + MemoryUsage + Send + Sync
+{
     fn new() -> Self;
+
+    /// Builds a tree holding `items`, which must be sorted by key without
+    /// duplicates. Puts them one by one unless the tree overrides this to
+    /// lay out its nodes directly.
+    fn bulk_load(items: impl IntoIterator<Item = (K, V)>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let tree = Self::new();
+        for (key, value) in items {
+            tree.put(key, value)?;
+        }
+        Ok(tree)
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        None
+    }
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        None
+    }
+    fn as_read_modify_write(&self) -> Option<&dyn ReadModifyWrite<K, V>> {
+        None
+    }
+    fn as_ordered(&self) -> Option<&dyn Ordered<K, V>> {
+        None
+    }
+
+    fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Remove => self.as_remove().is_some(),
+            Capability::RangeScan => self.as_range_scan().is_some(),
+            Capability::ReadModifyWrite => self.as_read_modify_write().is_some(),
+            Capability::Ordered => self.as_ordered().is_some(),
+        }
+    }
 }
@@ -4,7 +4,16 @@
 pub mod art;
 pub mod avl;
 pub mod binary_search_tree;
+pub mod blink_tree;
 pub mod bptree;
+pub mod bw_tree;
+pub mod ca_tree;
+pub mod error;
+pub mod external_bst;
 mod fix_sized_key;
+pub mod hash_map;
 pub mod interfaces;
+pub mod locked_btree;
+pub mod masstree;
 pub mod skiplist;
+pub mod treap;
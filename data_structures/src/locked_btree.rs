@@ -0,0 +1,429 @@
+use std::{
+    collections::{btree_map::Entry, hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hash},
+    mem,
+    sync::{Mutex, RwLock},
+};
+
+use crate::error::{Result, TreeError};
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, PointRead, RangeScan, ReadModifyWrite, Remove, Tree,
+    ValueType,
+};
+
+/// `BTreeMap` behind a single `Mutex`: the coarsest possible baseline.
+pub struct MutexBTreeMap<K, V>(Mutex<BTreeMap<K, V>>);
+
+impl<K: KeyType, V: ValueType> Tree<K, V> for MutexBTreeMap<K, V> {
+    fn new() -> Self {
+        MutexBTreeMap(Mutex::new(BTreeMap::new()))
+    }
+
+    /// `BTreeMap` builds its nodes bottom-up from sorted input.
+    fn bulk_load(items: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        Ok(MutexBTreeMap(Mutex::new(BTreeMap::from_iter(items))))
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+
+    fn as_read_modify_write(&self) -> Option<&dyn ReadModifyWrite<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for MutexBTreeMap<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        Ok(self.0.lock().unwrap().get(&key).map(f))
+    }
+}
+
+impl<K: KeyType, V: ValueType> Insert<K, V> for MutexBTreeMap<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        self.0.lock().unwrap().insert(key, value);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Remove<K, V> for MutexBTreeMap<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        self.0.lock().unwrap().remove(&key);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> RangeScan<K, V> for MutexBTreeMap<K, V> {
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        Ok(scan_map(&self.0.lock().unwrap(), start, n))
+    }
+}
+
+impl<K: KeyType, V: ValueType> ReadModifyWrite<K, V> for MutexBTreeMap<K, V> {
+    fn insert_if_absent(&self, key: K, value: V) -> Result<bool> {
+        Ok(insert_if_absent_in(&mut self.0.lock().unwrap(), key, value))
+    }
+
+    fn compare_exchange(&self, key: K, expected: &V, new: V) -> Result<()> {
+        compare_exchange_in(&mut self.0.lock().unwrap(), &key, expected, new)
+    }
+
+    fn update(&self, key: K, f: &mut dyn FnMut(&V) -> V) -> Result<Option<V>> {
+        Ok(update_in(&mut self.0.lock().unwrap(), &key, f))
+    }
+}
+
+impl<K: KeyType, V: ValueType> Len for MutexBTreeMap<K, V> {
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for MutexBTreeMap<K, V> {
+    fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for MutexBTreeMap<K, V> {
+    fn memory_usage(&self) -> usize {
+        map_memory_usage(&self.0.lock().unwrap())
+    }
+}
+
+/// `BTreeMap` behind a single `RwLock`, so readers proceed in parallel.
+pub struct RwLockBTreeMap<K, V>(RwLock<BTreeMap<K, V>>);
+
+impl<K: KeyType, V: ValueType> Tree<K, V> for RwLockBTreeMap<K, V> {
+    fn new() -> Self {
+        RwLockBTreeMap(RwLock::new(BTreeMap::new()))
+    }
+
+    fn bulk_load(items: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        Ok(RwLockBTreeMap(RwLock::new(BTreeMap::from_iter(items))))
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+
+    fn as_read_modify_write(&self) -> Option<&dyn ReadModifyWrite<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for RwLockBTreeMap<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        Ok(self.0.read().unwrap().get(&key).map(f))
+    }
+}
+
+impl<K: KeyType, V: ValueType> Insert<K, V> for RwLockBTreeMap<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        self.0.write().unwrap().insert(key, value);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Remove<K, V> for RwLockBTreeMap<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        self.0.write().unwrap().remove(&key);
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> RangeScan<K, V> for RwLockBTreeMap<K, V> {
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        Ok(scan_map(&self.0.read().unwrap(), start, n))
+    }
+}
+
+impl<K: KeyType, V: ValueType> ReadModifyWrite<K, V> for RwLockBTreeMap<K, V> {
+    fn insert_if_absent(&self, key: K, value: V) -> Result<bool> {
+        Ok(insert_if_absent_in(
+            &mut self.0.write().unwrap(),
+            key,
+            value,
+        ))
+    }
+
+    fn compare_exchange(&self, key: K, expected: &V, new: V) -> Result<()> {
+        compare_exchange_in(&mut self.0.write().unwrap(), &key, expected, new)
+    }
+
+    fn update(&self, key: K, f: &mut dyn FnMut(&V) -> V) -> Result<Option<V>> {
+        Ok(update_in(&mut self.0.write().unwrap(), &key, f))
+    }
+}
+
+impl<K: KeyType, V: ValueType> Len for RwLockBTreeMap<K, V> {
+    fn len(&self) -> usize {
+        self.0.read().unwrap().len()
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for RwLockBTreeMap<K, V> {
+    fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for RwLockBTreeMap<K, V> {
+    fn memory_usage(&self) -> usize {
+        map_memory_usage(&self.0.read().unwrap())
+    }
+}
+
+/// `SHARDS` independent `RwLock<BTreeMap>`s, picked by key hash.
+///
+/// Point operations only touch one shard; a scan has to visit every shard and
+/// merge, which is the price of hashing away the key order.
+pub struct ShardedBTreeMap<K, V, const SHARDS: usize = 16> {
+    shards: [RwLock<BTreeMap<K, V>>; SHARDS],
+    hasher: RandomState,
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> ShardedBTreeMap<K, V, SHARDS> {
+    fn shard(&self, key: &K) -> &RwLock<BTreeMap<K, V>> {
+        &self.shards[self.hasher.hash_one(key) as usize % SHARDS]
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> Tree<K, V>
+    for ShardedBTreeMap<K, V, SHARDS>
+{
+    fn new() -> Self {
+        assert!(SHARDS > 0);
+        ShardedBTreeMap {
+            shards: std::array::from_fn(|_| RwLock::new(BTreeMap::new())),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+
+    fn as_read_modify_write(&self) -> Option<&dyn ReadModifyWrite<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> PointRead<K, V>
+    for ShardedBTreeMap<K, V, SHARDS>
+{
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        Ok(self.shard(&key).read().unwrap().get(&key).map(f))
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> Insert<K, V>
+    for ShardedBTreeMap<K, V, SHARDS>
+{
+    fn put(&self, key: K, value: V) -> Result<()> {
+        self.shard(&key).write().unwrap().insert(key, value);
+        Ok(())
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> Remove<K, V>
+    for ShardedBTreeMap<K, V, SHARDS>
+{
+    fn remove(&self, key: K) -> Result<()> {
+        self.shard(&key).write().unwrap().remove(&key);
+        Ok(())
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> RangeScan<K, V>
+    for ShardedBTreeMap<K, V, SHARDS>
+{
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        let mut entries: Vec<(K, V)> = self
+            .shards
+            .iter()
+            .flat_map(|shard| scan_map(&shard.read().unwrap(), start.clone(), n))
+            .collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        entries.truncate(n);
+        Ok(entries)
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> ReadModifyWrite<K, V>
+    for ShardedBTreeMap<K, V, SHARDS>
+{
+    fn insert_if_absent(&self, key: K, value: V) -> Result<bool> {
+        Ok(insert_if_absent_in(
+            &mut self.shard(&key).write().unwrap(),
+            key,
+            value,
+        ))
+    }
+
+    fn compare_exchange(&self, key: K, expected: &V, new: V) -> Result<()> {
+        compare_exchange_in(&mut self.shard(&key).write().unwrap(), &key, expected, new)
+    }
+
+    fn update(&self, key: K, f: &mut dyn FnMut(&V) -> V) -> Result<Option<V>> {
+        Ok(update_in(&mut self.shard(&key).write().unwrap(), &key, f))
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> Len for ShardedBTreeMap<K, V, SHARDS> {
+    /// Not atomic: shards are counted one after another.
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap().len())
+            .sum()
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> Clear for ShardedBTreeMap<K, V, SHARDS> {
+    fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap().clear();
+        }
+    }
+}
+
+impl<K: KeyType + Hash, V: ValueType, const SHARDS: usize> MemoryUsage
+    for ShardedBTreeMap<K, V, SHARDS>
+{
+    fn memory_usage(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| map_memory_usage(&shard.read().unwrap()))
+            .sum()
+    }
+}
+
+/// `BTreeMap` does not expose its nodes, so only its entries are counted.
+pub(crate) fn map_memory_usage<K, V>(map: &BTreeMap<K, V>) -> usize {
+    map.len() * mem::size_of::<(K, V)>()
+}
+
+fn scan_map<K: KeyType, V: ValueType>(map: &BTreeMap<K, V>, start: K, n: usize) -> Vec<(K, V)> {
+    map.range(start..)
+        .take(n)
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+// `ReadModifyWrite` on a map the caller has locked, shared with `CaTree`.
+
+pub(crate) fn insert_if_absent_in<K: Ord, V>(map: &mut BTreeMap<K, V>, key: K, value: V) -> bool {
+    match map.entry(key) {
+        Entry::Vacant(entry) => {
+            entry.insert(value);
+            true
+        }
+        Entry::Occupied(_) => false,
+    }
+}
+
+pub(crate) fn compare_exchange_in<K: Ord, V: PartialEq>(
+    map: &mut BTreeMap<K, V>,
+    key: &K,
+    expected: &V,
+    new: V,
+) -> Result<()> {
+    match map.get_mut(key) {
+        Some(value) if value == expected => {
+            *value = new;
+            Ok(())
+        }
+        _ => Err(TreeError::Conflict),
+    }
+}
+
+pub(crate) fn update_in<K: Ord, V>(
+    map: &mut BTreeMap<K, V>,
+    key: &K,
+    f: &mut dyn FnMut(&V) -> V,
+) -> Option<V> {
+    let value = map.get_mut(key)?;
+    let new = f(value);
+    Some(mem::replace(value, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sharded_scan_merges_shards_in_key_order() {
+        let map = <ShardedBTreeMap<usize, usize, 4> as Tree<usize, usize>>::new();
+        for key in (0..1000usize).rev() {
+            map.put(key * 3, key).unwrap();
+        }
+        // Keys hash across all shards, so every window mixes them.
+        assert!(map
+            .shards
+            .iter()
+            .all(|shard| !shard.read().unwrap().is_empty()));
+        for (start, n) in [
+            (0usize, 10usize),
+            (1, 50),
+            (1500, 200),
+            (2990, 100),
+            (3000, 5),
+        ] {
+            let expected: Vec<_> = (start.div_ceil(3)..1000)
+                .take(n)
+                .map(|key| (key * 3, key))
+                .collect();
+            assert_eq!(
+                map.scan(start, n).unwrap(),
+                expected,
+                "scan {n} from {start}"
+            );
+        }
+        assert_eq!(map.scan(0, 0).unwrap(), vec![]);
+        assert_eq!(map.len(), 1000);
+        map.clear();
+        assert_eq!(map.scan(0, 10).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn read_modify_write_helpers() {
+        let mut map = BTreeMap::new();
+        assert!(insert_if_absent_in(&mut map, 1, 10));
+        assert!(!insert_if_absent_in(&mut map, 1, 11));
+        assert_eq!(map.get(&1), Some(&10));
+
+        assert!(matches!(
+            compare_exchange_in(&mut map, &1, &11, 12),
+            Err(TreeError::Conflict)
+        ));
+        assert!(matches!(
+            compare_exchange_in(&mut map, &2, &10, 12),
+            Err(TreeError::Conflict)
+        ));
+        assert_eq!(map.get(&1), Some(&10));
+        compare_exchange_in(&mut map, &1, &10, 12).unwrap();
+        assert_eq!(map.get(&1), Some(&12));
+
+        assert_eq!(update_in(&mut map, &1, &mut |value| value + 1), Some(12));
+        assert_eq!(map.get(&1), Some(&13));
+        assert_eq!(update_in(&mut map, &2, &mut |value| value + 1), None);
+        assert!(!map.contains_key(&2));
+
+        assert_eq!(map_memory_usage(&map), mem::size_of::<(i32, i32)>());
+        map.insert(2, 20);
+        assert_eq!(map_memory_usage(&map), 2 * mem::size_of::<(i32, i32)>());
+    }
+}
@@ -0,0 +1,447 @@
+use std::fmt::{self, Debug, Display, Formatter};
+
+use crate::{
+    blink_tree::BLinkTree,
+    error::Result,
+    interfaces::{Clear, Insert, Len, MemoryUsage, PointRead, RangeScan, Remove, Tree, ValueType},
+};
+
+/// Masstree's node width.
+const LAYER_FANOUT: usize = 15;
+const SLICE_LEN: usize = 8;
+/// `len` of the slot linking to the next layer, for keys longer than the
+/// slice. Sorts after every key that ends inside the slice.
+const LAYER_LEN: u8 = SLICE_LEN as u8 + 1;
+
+/// Key of one layer: the next 8 bytes of the key, zero-padded and read
+/// big-endian so integer order is byte order, and how many of them are used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct SliceKey {
+    slice: u64,
+    len: u8,
+}
+
+impl SliceKey {
+    fn of(rest: &[u8]) -> Self {
+        let mut bytes = [0; SLICE_LEN];
+        let used = rest.len().min(SLICE_LEN);
+        bytes[..used].copy_from_slice(&rest[..used]);
+        SliceKey {
+            slice: u64::from_be_bytes(bytes),
+            len: if rest.len() > SLICE_LEN {
+                LAYER_LEN
+            } else {
+                used as u8
+            },
+        }
+    }
+
+    /// The smallest key above this one.
+    fn successor(self) -> Option<Self> {
+        if self.len < LAYER_LEN {
+            Some(SliceKey {
+                len: self.len + 1,
+                ..self
+            })
+        } else {
+            self.slice
+                .checked_add(1)
+                .map(|slice| SliceKey { slice, len: 0 })
+        }
+    }
+}
+
+impl Display for SliceKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}/{}", self.slice, self.len)
+    }
+}
+
+type Layer<V> = BLinkTree<SliceKey, Slot<V>, LAYER_FANOUT>;
+
+/// Layers are only freed with the whole tree, so slots can share them by
+/// plain pointer.
+struct LayerRef<V>(*const Layer<V>);
+
+impl<V> Clone for LayerRef<V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V> Copy for LayerRef<V> {}
+
+unsafe impl<V: Send + Sync> Send for LayerRef<V> {}
+unsafe impl<V: Send + Sync> Sync for LayerRef<V> {}
+
+enum Slot<V> {
+    Value(V),
+    Layer(LayerRef<V>),
+}
+
+impl<V: Clone> Clone for Slot<V> {
+    fn clone(&self) -> Self {
+        match self {
+            Slot::Value(value) => Slot::Value(value.clone()),
+            Slot::Layer(layer) => Slot::Layer(*layer),
+        }
+    }
+}
+
+impl<V: PartialEq> PartialEq for Slot<V> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Slot::Value(a), Slot::Value(b)) => a == b,
+            (Slot::Layer(a), Slot::Layer(b)) => std::ptr::eq(a.0, b.0),
+            _ => false,
+        }
+    }
+}
+
+impl<V: Debug> Debug for Slot<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Slot::Value(value) => f.debug_tuple("Value").field(value).finish(),
+            Slot::Layer(layer) => f.debug_tuple("Layer").field(&layer.0).finish(),
+        }
+    }
+}
+
+impl<V: Display> Display for Slot<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Slot::Value(value) => value.fmt(f),
+            Slot::Layer(layer) => write!(f, "layer@{:p}", layer.0),
+        }
+    }
+}
+
+/// Masstree-style trie of B+trees for string keys (Mao, Kohler and Morris,
+/// EuroSys '12).
+///
+/// Each layer is a concurrent B-link tree indexed by an 8-byte slice of the
+/// key, so comparisons are single integer compares no matter how long the
+/// keys are. Keys that continue past a slice hang off a slot linking to the
+/// next layer, which shared prefixes only walk once. Layers use the B-link
+/// tree's optimistic version validation, readers never lock. Unlike the
+/// original, a slot always links to a layer instead of first storing the key
+/// suffix inline, and emptied layers are kept until the tree is dropped.
+pub struct Masstree<V: ValueType + 'static> {
+    root: Layer<V>,
+}
+
+impl<V: ValueType + 'static> Masstree<V> {
+    /// The layer holding the last slice of `key`, creating missing layers
+    /// if `create` is set, and the rest of the key relative to it.
+    fn final_layer<'a>(
+        &'a self,
+        mut rest: &'a [u8],
+        create: bool,
+    ) -> Result<Option<(&'a Layer<V>, &'a [u8])>> {
+        let mut layer = &self.root;
+        while rest.len() > SLICE_LEN {
+            let key = SliceKey::of(rest);
+            let slot = match layer.get(key)? {
+                Some(slot) => slot,
+                None if create => {
+                    let fresh = Box::into_raw(Box::new(Layer::<V>::new()));
+                    let slot = layer.get_or_insert(key, Slot::Layer(LayerRef(fresh)));
+                    if !matches!(&slot, Slot::Layer(existing) if std::ptr::eq(existing.0, fresh)) {
+                        drop(unsafe { Box::from_raw(fresh) });
+                    }
+                    slot
+                }
+                None => return Ok(None),
+            };
+            let Slot::Layer(next) = slot else {
+                unreachable!("value stored in a layer slot")
+            };
+            layer = unsafe { &*next.0 };
+            rest = &rest[SLICE_LEN..];
+        }
+        Ok(Some((layer, rest)))
+    }
+
+    /// Appends up to `n - out.len()` items of `layer` not below `start`,
+    /// `prefix` being the key bytes leading to it.
+    fn scan_layer(
+        layer: &Layer<V>,
+        prefix: &mut Vec<u8>,
+        start: &[u8],
+        n: usize,
+        out: &mut Vec<(String, V)>,
+    ) -> Result<()> {
+        let first = SliceKey::of(start);
+        let mut from = Some(first);
+        while let Some(key) = from {
+            if out.len() >= n {
+                return Ok(());
+            }
+            let batch = layer.scan(key, n - out.len())?;
+            let done = batch.len() < n - out.len();
+            for (key, slot) in batch {
+                let depth = prefix.len();
+                let bytes = key.slice.to_be_bytes();
+                prefix.extend_from_slice(&bytes[..(key.len as usize).min(SLICE_LEN)]);
+                match slot {
+                    Slot::Value(value) => {
+                        let key = String::from_utf8(prefix.clone()).expect("keys are strings");
+                        out.push((key, value));
+                    }
+                    Slot::Layer(next) => {
+                        let start = if key == first {
+                            &start[SLICE_LEN..]
+                        } else {
+                            &[]
+                        };
+                        Self::scan_layer(unsafe { &*next.0 }, prefix, start, n, out)?;
+                    }
+                }
+                prefix.truncate(depth);
+                from = key.successor();
+                if out.len() >= n {
+                    return Ok(());
+                }
+            }
+            if done {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<V: ValueType + 'static> Tree<String, V> for Masstree<V> {
+    fn new() -> Self {
+        Masstree { root: Layer::new() }
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<String, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<String, V>> {
+        Some(self)
+    }
+}
+
+impl<V: ValueType + 'static> PointRead<String, V> for Masstree<V> {
+    fn get_with<R>(&self, key: String, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let Some((layer, rest)) = self.final_layer(key.as_bytes(), false)? else {
+            return Ok(None);
+        };
+        layer.get_with(SliceKey::of(rest), |slot| match slot {
+            Slot::Value(value) => f(value),
+            Slot::Layer(_) => unreachable!("layer stored in a value slot"),
+        })
+    }
+}
+
+impl<V: ValueType + 'static> Insert<String, V> for Masstree<V> {
+    fn put(&self, key: String, value: V) -> Result<()> {
+        let (layer, rest) = self.final_layer(key.as_bytes(), true)?.unwrap();
+        layer.put(SliceKey::of(rest), Slot::Value(value))
+    }
+}
+
+impl<V: ValueType + 'static> Remove<String, V> for Masstree<V> {
+    fn remove(&self, key: String) -> Result<()> {
+        match self.final_layer(key.as_bytes(), false)? {
+            Some((layer, rest)) => layer.remove(SliceKey::of(rest)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<V: ValueType + 'static> RangeScan<String, V> for Masstree<V> {
+    fn scan(&self, start: String, n: usize) -> Result<Vec<(String, V)>> {
+        let mut out = Vec::new();
+        Self::scan_layer(&self.root, &mut Vec::new(), start.as_bytes(), n, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl<V: ValueType + 'static> Len for Masstree<V> {
+    fn len(&self) -> usize {
+        let mut len = 0;
+        for_each_layer(&self.root, &mut |_, slots| {
+            len += slots
+                .iter()
+                .filter(|(_, slot)| matches!(slot, Slot::Value(_)))
+                .count();
+        });
+        len
+    }
+}
+
+impl<V: ValueType + 'static> Clear for Masstree<V> {
+    /// Removes the values but keeps the layers, like `remove` does.
+    fn clear(&self) {
+        for_each_layer(&self.root, &mut |layer, slots| {
+            for (key, slot) in slots {
+                if let Slot::Value(_) = slot {
+                    layer.remove(key).unwrap();
+                }
+            }
+        });
+    }
+}
+
+impl<V: ValueType + 'static> MemoryUsage for Masstree<V> {
+    fn memory_usage(&self) -> usize {
+        let mut bytes = 0;
+        for_each_layer(&self.root, &mut |layer, _| bytes += layer.memory_usage());
+        bytes
+    }
+}
+
+/// Calls `f` with every layer reachable from `layer` and its slots.
+fn for_each_layer<V: ValueType + 'static>(
+    layer: &Layer<V>,
+    f: &mut impl FnMut(&Layer<V>, Vec<(SliceKey, Slot<V>)>),
+) {
+    let start = SliceKey { slice: 0, len: 0 };
+    let slots = layer.scan(start, usize::MAX).unwrap();
+    for (_, slot) in slots.iter() {
+        if let Slot::Layer(next) = slot {
+            for_each_layer(unsafe { &*next.0 }, f);
+        }
+    }
+    f(layer, slots);
+}
+
+fn free_sublayers<V: ValueType + 'static>(layer: &Layer<V>) {
+    let start = SliceKey { slice: 0, len: 0 };
+    // Layers never fail, a `BLinkTree` has no fallible operation.
+    for (_, slot) in layer.scan(start, usize::MAX).unwrap() {
+        if let Slot::Layer(next) = slot {
+            let next = unsafe { Box::from_raw(next.0 as *mut Layer<V>) };
+            free_sublayers(&next);
+        }
+    }
+}
+
+impl<V: ValueType + 'static> Drop for Masstree<V> {
+    fn drop(&mut self) {
+        free_sublayers(&self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::Arc, thread};
+
+    fn layers(tree: &Masstree<usize>) -> usize {
+        let mut count = 0;
+        for_each_layer(&tree.root, &mut |_, _| count += 1);
+        count
+    }
+
+    #[test]
+    fn put_get_remove_scan() {
+        let tree = Masstree::<usize>::new();
+        let mut model = BTreeMap::new();
+        // Lengths around the slice boundaries, sharing long prefixes.
+        for i in 0..3000usize {
+            let key = format!("{:x}", i * 2654435761 % 100_000).repeat(i % 5 + 1);
+            tree.put(key.clone(), i).unwrap();
+            model.insert(key, i);
+            if i % 4 == 0 {
+                let key = format!("{:x}", i * 7 % 100_000).repeat(i % 3 + 1);
+                tree.remove(key.clone()).unwrap();
+                model.remove(&key);
+            }
+        }
+        for (key, value) in &model {
+            assert_eq!(tree.get(key.clone()).unwrap(), Some(*value), "key {key}");
+        }
+        assert_eq!(tree.get("not there".to_string()).unwrap(), None);
+        assert_eq!(tree.len(), model.len());
+        for start in ["", "1", "abcdefgh", "abcdefgh0", "ffff"] {
+            let expected: Vec<_> = model
+                .range(start.to_string()..)
+                .take(50)
+                .map(|(k, v)| (k.clone(), *v))
+                .collect();
+            assert_eq!(
+                tree.scan(start.to_string(), 50).unwrap(),
+                expected,
+                "scan from {start}"
+            );
+        }
+        tree.clear();
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.scan(String::new(), 10).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn long_keys_create_layers() {
+        let tree = Masstree::<usize>::new();
+        tree.put("short".to_string(), 0).unwrap();
+        tree.put("exactly8".to_string(), 1).unwrap();
+        assert_eq!(layers(&tree), 1);
+
+        // Both continue past the same first slice, so they share one layer.
+        tree.put("exactly8-a".to_string(), 2).unwrap();
+        tree.put("exactly8-b".to_string(), 3).unwrap();
+        assert_eq!(layers(&tree), 2);
+        tree.put("exactly8-and-more!".to_string(), 4).unwrap();
+        assert_eq!(layers(&tree), 3);
+
+        assert_eq!(tree.get("exactly8".to_string()).unwrap(), Some(1));
+        assert_eq!(tree.get("exactly8-b".to_string()).unwrap(), Some(3));
+        assert_eq!(tree.get("exactly8-and-more!".to_string()).unwrap(), Some(4));
+        assert_eq!(tree.get("exactly8-c".to_string()).unwrap(), None);
+        let keys: Vec<_> = tree
+            .scan("exactly8".to_string(), 10)
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "exactly8",
+                "exactly8-a",
+                "exactly8-and-more!",
+                "exactly8-b",
+                "short"
+            ]
+        );
+
+        // Emptied layers stay.
+        tree.remove("exactly8-and-more!".to_string()).unwrap();
+        assert_eq!(tree.get("exactly8-and-more!".to_string()).unwrap(), None);
+        assert_eq!(layers(&tree), 3);
+    }
+
+    #[test]
+    fn disjoint_keys_across_threads() {
+        let tree = Arc::new(Masstree::<usize>::new());
+        let threads = 4;
+        let per_thread = 3000;
+        let key = |i: usize| format!("shared-prefix/{i:08}");
+        let handles: Vec<_> = (0..threads)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    for i in 0..per_thread {
+                        tree.put(key(i * threads + id), id).unwrap();
+                    }
+                    for i in (0..per_thread).step_by(2) {
+                        tree.remove(key(i * threads + id)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(tree.len(), threads * per_thread / 2);
+        for i in 0..threads * per_thread {
+            let expected = (i / threads % 2 == 1).then_some(i % threads);
+            assert_eq!(tree.get(key(i)).unwrap(), expected, "key {i}");
+        }
+    }
+}
@@ -1,6 +1,26 @@
-use crossbeam_skiplist::SkipMap;
+use std::{
+    alloc::{alloc, dealloc, handle_alloc_error, Layout},
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    marker::PhantomData,
+    mem,
+    ops::Bound,
+    ptr,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 
-use crate::interfaces::{KeyType, Tree, ValueType};
+use crossbeam::epoch::{self, Atomic, Guard, Owned, Shared};
+use crossbeam_skiplist::{map::Entry, SkipMap};
+
+use crate::{
+    error::{Result, TreeError},
+    fix_sized_key::{FixSizedKey, FixSizedKeyParams},
+    interfaces::{
+        Clear, Insert, KeyType, Len, MemoryUsage, Ordered, PointRead, RangeScan, Remove, Tree,
+        ValueType,
+    },
+};
 
 pub struct SkipMapWrapper<K: KeyType, V: ValueType>(SkipMap<K, V>);
 unsafe impl<K: KeyType, V: ValueType> Send for SkipMapWrapper<K, V> {}
@@ -11,15 +31,1206 @@ impl<K: KeyType + 'static, V: ValueType + 'static> Tree<K, V> for SkipMapWrapper
         SkipMapWrapper(SkipMap::new())
     }
 
-    fn put(&self, key: K, value: V) {
-        self.0.insert(key, value);
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
     }
 
-    fn get_val(&self, key: K) -> Option<V> {
-        return self.0.get(&key).map(|v| v.value().clone());
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
     }
 
-    fn remove(&self, key: K) {
+    fn as_ordered(&self) -> Option<&dyn Ordered<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> PointRead<K, V> for SkipMapWrapper<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        Ok(self.0.get(&key).map(|e| f(e.value())))
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> Insert<K, V> for SkipMapWrapper<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        self.0.insert(key, value);
+        Ok(())
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> Remove<K, V> for SkipMapWrapper<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
         self.0.remove(&key);
+        Ok(())
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> RangeScan<K, V> for SkipMapWrapper<K, V> {
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        Ok(self
+            .0
+            .range(start..)
+            .take(n)
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect())
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> Len for SkipMapWrapper<K, V> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> Clear for SkipMapWrapper<K, V> {
+    fn clear(&self) {
+        self.0.clear();
+    }
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> Ordered<K, V> for SkipMapWrapper<K, V> {
+    fn first(&self) -> Result<Option<(K, V)>> {
+        Ok(self.0.front().map(cloned))
+    }
+
+    fn last(&self) -> Result<Option<(K, V)>> {
+        Ok(self.0.back().map(cloned))
+    }
+
+    fn lower_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.0.lower_bound(Bound::Included(&key)).map(cloned))
+    }
+
+    fn upper_bound(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.0.upper_bound(Bound::Included(&key)).map(cloned))
+    }
+
+    fn successor(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.0.lower_bound(Bound::Excluded(&key)).map(cloned))
+    }
+
+    fn predecessor(&self, key: K) -> Result<Option<(K, V)>> {
+        Ok(self.0.upper_bound(Bound::Excluded(&key)).map(cloned))
+    }
+}
+
+fn cloned<K: Clone, V: Clone>(entry: Entry<'_, K, V>) -> (K, V) {
+    (entry.key().clone(), entry.value().clone())
+}
+
+impl<K: KeyType + 'static, V: ValueType + 'static> MemoryUsage for SkipMapWrapper<K, V> {
+    fn memory_usage(&self) -> usize {
+        self.0.len() * mem::size_of::<(K, V)>()
+    }
+}
+
+/// Upper bound for `SkipListParams::MAX_LEVEL`.
+const LEVEL_LIMIT: usize = 64;
+
+pub trait SkipListParams: Send + Sync + 'static {
+    /// Key stored in the towers, converted from and to the `Tree` key.
+    type Key: KeyType + 'static;
+    const MAX_LEVEL: usize;
+    /// Chance that a node reaching level `h` is promoted to level `h + 1`.
+    const PROMOTION_PROBABILITY: f64;
+}
+
+/// Towers keyed by `K` itself, up to 32 levels, promoted with probability 1/2.
+pub struct DefaultSkipListParams<K>(PhantomData<K>);
+
+impl<K: KeyType + 'static> SkipListParams for DefaultSkipListParams<K> {
+    type Key = K;
+    const MAX_LEVEL: usize = 32;
+    const PROMOTION_PROBABILITY: f64 = 0.5;
+}
+
+/// Keys stored inline in the towers as 16-byte `FixSizedKey`s.
+pub struct FixedKeySkipListParams;
+
+impl FixSizedKeyParams for FixedKeySkipListParams {
+    const KEY_SIZE: usize = 16;
+    // Byte-wise comparison keeps scans in string order.
+    const ALLOW_INT_CMP: bool = false;
+}
+
+impl SkipListParams for FixedKeySkipListParams {
+    type Key = FixSizedKey<FixedKeySkipListParams>;
+    const MAX_LEVEL: usize = 32;
+    const PROMOTION_PROBABILITY: f64 = 0.5;
+}
+
+/// Stamp of a version nobody has stamped yet.
+const UNSTAMPED: u64 = u64::MAX;
+
+/// A value a key held, `None` once it was removed, stamped with the clock
+/// of the list when it was written.
+struct Version<V> {
+    value: Option<V>,
+    stamp: AtomicU64,
+    /// The version this one replaced, null once no snapshot can read it.
+    prev: Atomic<Version<V>>,
+}
+
+impl<V> Version<V> {
+    fn new(value: Option<V>) -> Self {
+        Version {
+            value,
+            stamp: AtomicU64::new(UNSTAMPED),
+            prev: Atomic::null(),
+        }
+    }
+}
+
+/// Retires `version` and the versions below it.
+unsafe fn retire_versions<'g, V>(mut version: Shared<'g, Version<V>>, guard: &'g Guard) {
+    while !version.is_null() {
+        let prev = version
+            .deref()
+            .prev
+            .swap(Shared::null(), Ordering::AcqRel, guard);
+        guard.defer_destroy(version);
+        version = prev;
+    }
+}
+
+/// A node and its tower, allocated in one block of `Node::layout(height)`.
+#[repr(C)]
+struct Node<K, V> {
+    /// `None` for the head.
+    key: Option<K>,
+    /// Versions of the value, newest first; null for the head. Tagged with 1
+    /// once the key is removed and no snapshot can see it any more, which
+    /// allows the node to be unlinked.
+    versions: Atomic<Version<V>>,
+    /// Levels the node has been unlinked from, or was never linked into. The
+    /// node is retired by whoever brings it to `height`.
+    unlinked: AtomicUsize,
+    height: usize,
+    /// `height` next pointers, tagged with 1 once the node is marked.
+    tower: [Atomic<Node<K, V>>; 0],
+}
+
+impl<K, V> Node<K, V> {
+    fn layout(height: usize) -> Layout {
+        Layout::new::<Self>()
+            .extend(Layout::array::<Atomic<Self>>(height).unwrap())
+            .unwrap()
+            .0
+            .pad_to_align()
+    }
+
+    fn alloc(key: Option<K>, height: usize) -> Result<*mut Self> {
+        unsafe {
+            let node = alloc(Self::layout(height)) as *mut Self;
+            if node.is_null() {
+                return Err(TreeError::OutOfMemory);
+            }
+            ptr::write(
+                node,
+                Node {
+                    key,
+                    versions: Atomic::null(),
+                    unlinked: AtomicUsize::new(0),
+                    height,
+                    tower: [],
+                },
+            );
+            let tower = ptr::addr_of_mut!((*node).tower) as *mut Atomic<Self>;
+            for level in 0..height {
+                ptr::write(tower.add(level), Atomic::null());
+            }
+            Ok(node)
+        }
+    }
+
+    /// Frees the node and its versions.
+    unsafe fn dealloc(node: *mut Self) {
+        let guard = epoch::unprotected();
+        let mut version = (*node).versions.load(Ordering::Relaxed, guard).with_tag(0);
+        while !version.is_null() {
+            let prev = version.deref().prev.load(Ordering::Relaxed, guard);
+            drop(version.into_owned());
+            version = prev;
+        }
+        let height = (*node).height;
+        ptr::drop_in_place(node);
+        dealloc(node as *mut u8, Self::layout(height));
+    }
+
+    #[inline(always)]
+    fn next(&self, level: usize) -> &Atomic<Self> {
+        debug_assert!(level < self.height);
+        unsafe { &*self.tower.as_ptr().add(level) }
+    }
+
+    #[inline(always)]
+    fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+
+    /// Marks every level, top down, so inserters stop building the tower and
+    /// traversals unlink it.
+    fn mark(&self, guard: &Guard) {
+        for level in (0..self.height).rev() {
+            self.next(level).fetch_or(1, Ordering::AcqRel, guard);
+        }
+    }
+}
+
+/// Nodes around a key at every level, as found by `LockFreeSkipList::find`.
+struct Position<'g, K, V> {
+    preds: [Shared<'g, Node<K, V>>; LEVEL_LIMIT],
+    succs: [Shared<'g, Node<K, V>>; LEVEL_LIMIT],
+    found: bool,
+}
+
+/// Lock-free skiplist (Fraser; Herlihy and Shavit).
+///
+/// A node's levels are linked bottom up with CAS, the bottom level deciding
+/// membership. Removal first empties the value, which is the linearization
+/// point, then marks the tower top down; marked nodes are unlinked by any
+/// traversal that runs into them. Towers are allocated inline with their node,
+/// with heights drawn from `P::PROMOTION_PROBABILITY` up to `P::MAX_LEVEL`.
+///
+/// Values are versioned as in vCAS (Wei et al., PPoPP '21) so `snapshot`
+/// can take a linearizable snapshot in constant time. Every write pushes a
+/// version stamped with `clock`, removal pushing an empty one, and a
+/// snapshot reads the newest version not stamped after it. While snapshots
+/// are open, replaced versions and removed nodes are kept; the last
+/// snapshot to close retires what none can read any more.
+pub struct LockFreeSkipList<P: SkipListParams, V> {
+    head: *mut Node<P::Key, V>,
+    clock: AtomicU64,
+    /// Snapshots open.
+    snapshots: AtomicUsize,
+    _marker: PhantomData<(P, Box<V>)>,
+}
+
+unsafe impl<P: SkipListParams, V: Send + Sync> Send for LockFreeSkipList<P, V> {}
+unsafe impl<P: SkipListParams, V: Send + Sync> Sync for LockFreeSkipList<P, V> {}
+
+pub type SkipList<K, V> = LockFreeSkipList<DefaultSkipListParams<K>, V>;
+pub type FixedKeySkipList<V> = LockFreeSkipList<FixedKeySkipListParams, V>;
+
+fn random_height<P: SkipListParams>() -> usize {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().hash_one(0u8) | 1);
+    }
+    let threshold = (P::PROMOTION_PROBABILITY * u64::MAX as f64) as u64;
+    STATE.with(|state| {
+        let mut height = 1;
+        let mut x = state.get();
+        while height < P::MAX_LEVEL {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            if x >= threshold {
+                break;
+            }
+            height += 1;
+        }
+        state.set(x);
+        height
+    })
+}
+
+impl<P: SkipListParams, V: ValueType> LockFreeSkipList<P, V> {
+    fn head<'g>(&self) -> &'g Node<P::Key, V> {
+        unsafe { &*self.head }
+    }
+
+    /// Accounts for `levels` more levels `node` left; the last one retires it.
+    unsafe fn release(&self, node: Shared<'_, Node<P::Key, V>>, levels: usize, guard: &Guard) {
+        let node = node.as_raw() as *mut Node<P::Key, V>;
+        if (*node).unlinked.fetch_add(levels, Ordering::AcqRel) + levels == (*node).height {
+            guard.defer_unchecked(move || Node::dealloc(node));
+        }
+    }
+
+    /// Finds the last node below `key` and the first node not below it on
+    /// every level, unlinking marked nodes on the way.
+    fn find<'g>(&self, key: &P::Key, guard: &'g Guard) -> Position<'g, P::Key, V> {
+        let mut pos = Position {
+            preds: [Shared::null(); LEVEL_LIMIT],
+            succs: [Shared::null(); LEVEL_LIMIT],
+            found: false,
+        };
+        'retry: loop {
+            let mut pred = self.head();
+            for level in (0..P::MAX_LEVEL).rev() {
+                let mut curr = pred.next(level).load(Ordering::Acquire, guard);
+                if curr.tag() == 1 {
+                    continue 'retry;
+                }
+                while let Some(node) = unsafe { curr.as_ref() } {
+                    let succ = node.next(level).load(Ordering::Acquire, guard);
+                    if succ.tag() == 1 {
+                        match pred.next(level).compare_exchange(
+                            curr,
+                            succ.with_tag(0),
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                            guard,
+                        ) {
+                            Ok(_) => {
+                                unsafe { self.release(curr, 1, guard) };
+                                curr = succ.with_tag(0);
+                                continue;
+                            }
+                            Err(_) => continue 'retry,
+                        }
+                    }
+                    if node.key() < key {
+                        pred = node;
+                        curr = succ;
+                    } else {
+                        break;
+                    }
+                }
+                pos.preds[level] = Shared::from(pred as *const _);
+                pos.succs[level] = curr;
+            }
+            pos.found = unsafe { pos.succs[0].as_ref() }.is_some_and(|node| node.key() == key);
+            return pos;
+        }
+    }
+
+    /// Links the levels above the bottom one of the freshly inserted `node`,
+    /// giving up once the node gets marked.
+    fn build_tower<'g>(
+        &self,
+        node: Shared<'g, Node<P::Key, V>>,
+        mut pos: Position<'g, P::Key, V>,
+        guard: &'g Guard,
+    ) {
+        let tower = unsafe { node.deref() };
+        for level in 1..tower.height {
+            loop {
+                let pred = unsafe { pos.preds[level].deref() };
+                if pred
+                    .next(level)
+                    .compare_exchange(
+                        pos.succs[level],
+                        node,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                        guard,
+                    )
+                    .is_ok()
+                {
+                    break;
+                }
+                pos = self.find(tower.key(), guard);
+                let next = tower.next(level).load(Ordering::Acquire, guard);
+                if pos.succs[0] != node
+                    || tower
+                        .next(level)
+                        .compare_exchange(
+                            next.with_tag(0),
+                            pos.succs[level],
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                            guard,
+                        )
+                        .is_err()
+                {
+                    // Removed meanwhile, the remover's `find` unlinked the
+                    // levels linked so far.
+                    unsafe { self.release(node, tower.height - level, guard) };
+                    return;
+                }
+            }
+            if tower.next(level).load(Ordering::Acquire, guard).tag() == 1 {
+                // Marked while this level was linked, which the remover may
+                // have missed.
+                self.find(tower.key(), guard);
+                if level + 1 < tower.height {
+                    unsafe { self.release(node, tower.height - level - 1, guard) };
+                }
+                return;
+            }
+        }
+    }
+
+    fn insert(&self, key: P::Key, value: V) -> Result<()> {
+        let guard = &epoch::pin();
+        let version = Owned::new(Version::new(Some(value))).into_shared(guard);
+        let mut node: *mut Node<P::Key, V> = ptr::null_mut();
+        let mut key = Some(key);
+        loop {
+            let pos = self.find(
+                key.as_ref().unwrap_or_else(|| unsafe { (*node).key() }),
+                guard,
+            );
+            if pos.found {
+                let existing = unsafe { pos.succs[0].deref() };
+                let head = existing.versions.load(Ordering::Acquire, guard);
+                if head.tag() == 1 {
+                    // Being unlinked: make sure `find` does.
+                    existing.mark(guard);
+                    continue;
+                }
+                if self.push_version(existing, head, version, guard) {
+                    if !node.is_null() {
+                        unsafe {
+                            (*node).versions.store(Shared::null(), Ordering::Relaxed);
+                            Node::dealloc(node);
+                        }
+                    }
+                    return Ok(());
+                }
+                continue;
+            }
+
+            if node.is_null() {
+                node = match Node::alloc(key.take(), random_height::<P>()) {
+                    Ok(node) => node,
+                    Err(error) => {
+                        drop(unsafe { version.into_owned() });
+                        return Err(error);
+                    }
+                };
+            }
+            let fresh = unsafe { &*node };
+            unsafe { version.deref() }
+                .prev
+                .store(Shared::null(), Ordering::Relaxed);
+            fresh.versions.store(version, Ordering::Relaxed);
+            for level in 0..fresh.height {
+                fresh.next(level).store(pos.succs[level], Ordering::Relaxed);
+            }
+            let shared = Shared::from(node as *const _);
+            if unsafe { pos.preds[0].deref() }
+                .next(0)
+                .compare_exchange(
+                    pos.succs[0],
+                    shared,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    guard,
+                )
+                .is_ok()
+            {
+                self.stamp(unsafe { version.deref() });
+                self.build_tower(shared, pos, guard);
+                return Ok(());
+            }
+        }
+    }
+
+    fn delete(&self, key: &P::Key, guard: &Guard) {
+        let pos = self.find(key, guard);
+        if !pos.found {
+            return;
+        }
+        let node = unsafe { pos.succs[0].deref() };
+        let mut empty = None;
+        loop {
+            let head = node.versions.load(Ordering::Acquire, guard);
+            if head.tag() == 1 || unsafe { head.deref() }.value.is_none() {
+                break;
+            }
+            let version =
+                *empty.get_or_insert_with(|| Owned::new(Version::new(None)).into_shared(guard));
+            if self.push_version(node, head, version, guard) {
+                empty = None;
+                break;
+            }
+        }
+        if let Some(version) = empty {
+            drop(unsafe { version.into_owned() });
+        }
+        self.unlink_removed(node, guard);
+    }
+
+    /// Stamps `version` with the clock unless somebody did already, and
+    /// returns its stamp.
+    fn stamp(&self, version: &Version<V>) -> u64 {
+        let stamp = version.stamp.load(Ordering::SeqCst);
+        if stamp != UNSTAMPED {
+            return stamp;
+        }
+        let now = self.clock.load(Ordering::SeqCst);
+        match version
+            .stamp
+            .compare_exchange(UNSTAMPED, now, Ordering::SeqCst, Ordering::SeqCst)
+        {
+            Ok(_) => now,
+            Err(stamp) => stamp,
+        }
+    }
+
+    /// The value of `node` now. Its version gets stamped first, so a
+    /// snapshot taken after this read cannot miss it.
+    fn current<'g>(&self, node: &Node<P::Key, V>, guard: &'g Guard) -> Option<&'g V> {
+        let head = node.versions.load(Ordering::Acquire, guard).with_tag(0);
+        let version = unsafe { head.as_ref() }?;
+        self.stamp(version);
+        version.value.as_ref()
+    }
+
+    /// The value of `node` as of the snapshot taken at `at`.
+    fn value_at<'g>(&self, node: &Node<P::Key, V>, at: u64, guard: &'g Guard) -> Option<&'g V> {
+        let mut version = node.versions.load(Ordering::Acquire, guard).with_tag(0);
+        if let Some(head) = unsafe { version.as_ref() } {
+            self.stamp(head);
+        }
+        while let Some(current) = unsafe { version.as_ref() } {
+            if current.stamp.load(Ordering::SeqCst) <= at {
+                return current.value.as_ref();
+            }
+            version = current.prev.load(Ordering::Acquire, guard);
+        }
+        None
+    }
+
+    /// Makes `version` the newest version of `node` if `head` still is, and
+    /// stamps it. With no snapshot open, the versions it replaced are
+    /// retired right away.
+    fn push_version<'g>(
+        &self,
+        node: &Node<P::Key, V>,
+        head: Shared<'g, Version<V>>,
+        version: Shared<'g, Version<V>>,
+        guard: &'g Guard,
+    ) -> bool {
+        // Stamped before being replaced, so stamps only grow towards the
+        // newest version.
+        self.stamp(unsafe { head.deref() });
+        let fresh = unsafe { version.deref() };
+        fresh.prev.store(head, Ordering::Relaxed);
+        if node
+            .versions
+            .compare_exchange(head, version, Ordering::AcqRel, Ordering::Acquire, guard)
+            .is_err()
+        {
+            return false;
+        }
+        self.stamp(fresh);
+        if self.snapshots.load(Ordering::SeqCst) == 0 {
+            self.trim(fresh, guard);
+        }
+        true
+    }
+
+    /// Retires the versions below `version`. Only safe once `version` is
+    /// stamped and no snapshot is open: later snapshots never read past it.
+    fn trim(&self, version: &Version<V>, guard: &Guard) {
+        let prev = version.prev.swap(Shared::null(), Ordering::AcqRel, guard);
+        unsafe { retire_versions(prev, guard) };
+    }
+
+    /// Unlinks `node` if its key is removed and no snapshot can still see it.
+    fn unlink_removed(&self, node: &Node<P::Key, V>, guard: &Guard) {
+        let head = node.versions.load(Ordering::Acquire, guard);
+        if head.tag() == 0 {
+            let version = unsafe { head.deref() };
+            if version.value.is_some() {
+                return;
+            }
+            self.stamp(version);
+            if self.snapshots.load(Ordering::SeqCst) != 0 {
+                // Left to the last snapshot to close.
+                return;
+            }
+            if node
+                .versions
+                .compare_exchange(
+                    head,
+                    head.with_tag(1),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    guard,
+                )
+                .is_err()
+            {
+                // Inserted again meanwhile.
+                return;
+            }
+            self.trim(version, guard);
+        }
+        node.mark(guard);
+        self.find(node.key(), guard);
+    }
+
+    /// Catches up on the work held back while snapshots were open: retires
+    /// versions no snapshot can read and unlinks removed keys. Leaves the rest
+    /// once another snapshot is opened.
+    fn collect(&self) {
+        let guard = &epoch::pin();
+        self.for_each_node(guard, |node| {
+            let head = node.versions.load(Ordering::Acquire, guard);
+            if head.tag() == 1 {
+                return;
+            }
+            let version = unsafe { head.deref() };
+            self.stamp(version);
+            if self.snapshots.load(Ordering::SeqCst) != 0 {
+                return;
+            }
+            self.trim(version, guard);
+            if version.value.is_none() {
+                self.unlink_removed(node, guard);
+            }
+        });
+    }
+
+    /// Takes a snapshot of the list as it is now, which can be read while
+    /// writers go on. Open snapshots keep every version written since the
+    /// oldest of them alive, so they should not be held longer than needed.
+    pub fn snapshot(&self) -> Snapshot<'_, P, V> {
+        self.snapshots.fetch_add(1, Ordering::SeqCst);
+        Snapshot {
+            list: self,
+            at: self.clock.fetch_add(1, Ordering::SeqCst),
+        }
+    }
+
+    /// Calls `f` on every node left on the bottom level, marked or not.
+    fn for_each_node(&self, guard: &Guard, mut f: impl FnMut(&Node<P::Key, V>)) {
+        let mut curr = self.head().next(0).load(Ordering::Acquire, guard);
+        while let Some(node) = unsafe { curr.with_tag(0).as_ref() } {
+            f(node);
+            curr = node.next(0).load(Ordering::Acquire, guard);
+        }
+    }
+}
+
+impl<P: SkipListParams, K, V> Tree<K, V> for LockFreeSkipList<P, V>
+where
+    K: KeyType + TryInto<P::Key>,
+    TreeError: From<<K as TryInto<P::Key>>::Error>,
+    P::Key: Into<K>,
+    V: ValueType,
+{
+    fn new() -> Self {
+        assert!((1..=LEVEL_LIMIT).contains(&P::MAX_LEVEL));
+        assert!((0.0..1.0).contains(&P::PROMOTION_PROBABILITY));
+        LockFreeSkipList {
+            head: Node::alloc(None, P::MAX_LEVEL)
+                .unwrap_or_else(|_| handle_alloc_error(Node::<P::Key, V>::layout(P::MAX_LEVEL))),
+            clock: AtomicU64::new(0),
+            snapshots: AtomicUsize::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+}
+
+impl<P: SkipListParams, K, V> PointRead<K, V> for LockFreeSkipList<P, V>
+where
+    K: KeyType + TryInto<P::Key>,
+    TreeError: From<<K as TryInto<P::Key>>::Error>,
+    P::Key: Into<K>,
+    V: ValueType,
+{
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let key = key.try_into()?;
+        let guard = &epoch::pin();
+        let mut pred = self.head();
+        for level in (0..P::MAX_LEVEL).rev() {
+            let mut curr = pred.next(level).load(Ordering::Acquire, guard).with_tag(0);
+            // Marked nodes are skipped, not unlinked: lookups never write.
+            while let Some(node) = unsafe { curr.as_ref() } {
+                let succ = node.next(level).load(Ordering::Acquire, guard);
+                if succ.tag() == 1 {
+                    curr = succ.with_tag(0);
+                    continue;
+                }
+                match node.key().cmp(&key) {
+                    std::cmp::Ordering::Less => {
+                        pred = node;
+                        curr = succ;
+                    }
+                    std::cmp::Ordering::Equal => return Ok(self.current(node, guard).map(f)),
+                    std::cmp::Ordering::Greater => break,
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<P: SkipListParams, K, V> Insert<K, V> for LockFreeSkipList<P, V>
+where
+    K: KeyType + TryInto<P::Key>,
+    TreeError: From<<K as TryInto<P::Key>>::Error>,
+    P::Key: Into<K>,
+    V: ValueType,
+{
+    fn put(&self, key: K, value: V) -> Result<()> {
+        self.insert(key.try_into()?, value)
+    }
+}
+
+impl<P: SkipListParams, K, V> Remove<K, V> for LockFreeSkipList<P, V>
+where
+    K: KeyType + TryInto<P::Key>,
+    TreeError: From<<K as TryInto<P::Key>>::Error>,
+    P::Key: Into<K>,
+    V: ValueType,
+{
+    fn remove(&self, key: K) -> Result<()> {
+        self.delete(&key.try_into()?, &epoch::pin());
+        Ok(())
+    }
+}
+
+impl<P: SkipListParams, K, V> RangeScan<K, V> for LockFreeSkipList<P, V>
+where
+    K: KeyType + TryInto<P::Key>,
+    TreeError: From<<K as TryInto<P::Key>>::Error>,
+    P::Key: Into<K>,
+    V: ValueType,
+{
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        let guard = &epoch::pin();
+        let mut result = Vec::new();
+        let mut curr = self.find(&start.try_into()?, guard).succs[0];
+        while let Some(node) = unsafe { curr.as_ref() } {
+            if result.len() >= n {
+                break;
+            }
+            let next = node.next(0).load(Ordering::Acquire, guard);
+            if next.tag() == 0 {
+                if let Some(value) = self.current(node, guard) {
+                    result.push((node.key().clone().into(), value.clone()));
+                }
+            }
+            curr = next.with_tag(0);
+        }
+        Ok(result)
+    }
+}
+
+impl<P: SkipListParams, V: ValueType> Len for LockFreeSkipList<P, V> {
+    fn len(&self) -> usize {
+        let guard = &epoch::pin();
+        let mut len = 0;
+        self.for_each_node(guard, |node| {
+            if self.current(node, guard).is_some() {
+                len += 1;
+            }
+        });
+        len
+    }
+}
+
+impl<P: SkipListParams, V: ValueType> Clear for LockFreeSkipList<P, V> {
+    fn clear(&self) {
+        let guard = &epoch::pin();
+        let mut keys = Vec::new();
+        self.for_each_node(guard, |node| {
+            if self.current(node, guard).is_some() {
+                keys.push(node.key().clone());
+            }
+        });
+        for key in keys.iter() {
+            self.delete(key, guard);
+        }
+    }
+}
+
+impl<P: SkipListParams, V: ValueType> MemoryUsage for LockFreeSkipList<P, V> {
+    fn memory_usage(&self) -> usize {
+        let guard = &epoch::pin();
+        let mut bytes = Node::<P::Key, V>::layout(P::MAX_LEVEL).size();
+        self.for_each_node(guard, |node| {
+            bytes += Node::<P::Key, V>::layout(node.height).size();
+            let mut version = node.versions.load(Ordering::Acquire, guard).with_tag(0);
+            while let Some(current) = unsafe { version.as_ref() } {
+                bytes += mem::size_of::<Version<V>>();
+                version = current.prev.load(Ordering::Acquire, guard);
+            }
+        });
+        bytes
+    }
+}
+
+impl<P: SkipListParams, V> Drop for LockFreeSkipList<P, V> {
+    fn drop(&mut self) {
+        unsafe {
+            // Unlinked nodes are already retired; whatever is left sits on
+            // the bottom level.
+            let guard = epoch::unprotected();
+            let mut node = self.head;
+            while !node.is_null() {
+                let next = (*node).next(0).load(Ordering::Relaxed, guard).with_tag(0);
+                Node::dealloc(node);
+                node = next.as_raw() as *mut _;
+            }
+        }
+    }
+}
+
+/// Linearizable snapshot of a `LockFreeSkipList`, taken by
+/// `LockFreeSkipList::snapshot`.
+pub struct Snapshot<'a, P: SkipListParams, V: ValueType> {
+    list: &'a LockFreeSkipList<P, V>,
+    /// Clock of the list when the snapshot was taken.
+    at: u64,
+}
+
+impl<'a, P: SkipListParams, V: ValueType> Snapshot<'a, P, V> {
+    pub fn get<K>(&self, key: K) -> Result<Option<V>>
+    where
+        K: TryInto<P::Key>,
+        TreeError: From<K::Error>,
+    {
+        let guard = &epoch::pin();
+        let pos = self.list.find(&key.try_into()?, guard);
+        if !pos.found {
+            return Ok(None);
+        }
+        let node = unsafe { pos.succs[0].deref() };
+        Ok(self.list.value_at(node, self.at, guard).cloned())
+    }
+
+    /// Entries of the snapshot in key order.
+    pub fn iter<K>(&self) -> SnapshotIter<'_, P, V, K>
+    where
+        P::Key: Into<K>,
+    {
+        let guard = epoch::pin();
+        let first = self.list.head().next(0).load(Ordering::Acquire, &guard);
+        SnapshotIter {
+            snapshot: self,
+            next: first.with_tag(0).as_raw(),
+            guard,
+            _key: PhantomData,
+        }
+    }
+}
+
+impl<'a, P: SkipListParams, V: ValueType> Drop for Snapshot<'a, P, V> {
+    fn drop(&mut self) {
+        if self.list.snapshots.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.list.collect();
+        }
+    }
+}
+
+/// Iterator over a `Snapshot`, holding the epoch pinned while it lives.
+pub struct SnapshotIter<'a, P: SkipListParams, V: ValueType, K> {
+    snapshot: &'a Snapshot<'a, P, V>,
+    next: *const Node<P::Key, V>,
+    guard: Guard,
+    _key: PhantomData<K>,
+}
+
+impl<'a, P: SkipListParams, V: ValueType, K> Iterator for SnapshotIter<'a, P, V, K>
+where
+    P::Key: Into<K>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        // Nodes a snapshot can see are never unlinked while it is open, and
+        // nodes linked after it was taken only hold versions stamped later.
+        while let Some(node) = unsafe { self.next.as_ref() } {
+            let next = node.next(0).load(Ordering::Acquire, &self.guard);
+            self.next = next.with_tag(0).as_raw();
+            let list = self.snapshot.list;
+            if let Some(value) = list.value_at(node, self.snapshot.at, &self.guard) {
+                return Some((node.key().clone().into(), value.clone()));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::atomic::AtomicBool, thread};
+
+    type List = SkipList<usize, usize>;
+
+    /// `usize` keys with towers of at most `MAX` levels.
+    struct Capped<const MAX: usize, const PERCENT: usize>;
+
+    impl<const MAX: usize, const PERCENT: usize> SkipListParams for Capped<MAX, PERCENT> {
+        type Key = usize;
+        const MAX_LEVEL: usize = MAX;
+        const PROMOTION_PROBABILITY: f64 = PERCENT as f64 / 100.0;
+    }
+
+    fn new_list(keys: impl Iterator<Item = usize>) -> List {
+        let list = <List as Tree<usize, usize>>::new();
+        for key in keys {
+            list.put(key, key).unwrap();
+        }
+        list
+    }
+
+    /// Nodes on the bottom level and the versions they hold.
+    fn node_and_version_count(list: &List) -> (usize, usize) {
+        let guard = &epoch::pin();
+        let (mut nodes, mut versions) = (0, 0);
+        list.for_each_node(guard, |node| {
+            nodes += 1;
+            let mut version = node.versions.load(Ordering::Acquire, guard).with_tag(0);
+            while let Some(current) = unsafe { version.as_ref() } {
+                versions += 1;
+                version = current.prev.load(Ordering::Acquire, guard);
+            }
+        });
+        (nodes, versions)
+    }
+
+    #[test]
+    fn snapshot_ignores_later_writes() {
+        let list = new_list(0..100);
+        let snapshot = list.snapshot();
+        for key in 0..50usize {
+            list.put(key, key + 1000).unwrap();
+        }
+        for key in 50..100usize {
+            list.remove(key).unwrap();
+        }
+        for key in 100..150usize {
+            list.put(key, key).unwrap();
+        }
+        let seen: Vec<(usize, usize)> = snapshot.iter().collect();
+        assert_eq!(seen, (0..100).map(|key| (key, key)).collect::<Vec<_>>());
+        assert_eq!(snapshot.get(75usize).unwrap(), Some(75));
+        assert_eq!(snapshot.get(125usize).unwrap(), None);
+        assert_eq!(list.get(75usize).unwrap(), None);
+        assert_eq!(list.get(25usize).unwrap(), Some(1025));
+        assert_eq!(node_and_version_count(&list), (150, 250));
+
+        drop(snapshot);
+        assert_eq!(node_and_version_count(&list), (100, 100));
+    }
+
+    #[test]
+    fn snapshot_sees_removal_and_reinsertion() {
+        let list = new_list(0..10);
+        let before = list.snapshot();
+        list.remove(5usize).unwrap();
+        let removed = list.snapshot();
+        list.put(5usize, 50).unwrap();
+        let after = list.snapshot();
+        assert_eq!(before.get(5usize).unwrap(), Some(5));
+        assert_eq!(removed.get(5usize).unwrap(), None);
+        assert_eq!(after.get(5usize).unwrap(), Some(50));
+        assert_eq!(removed.iter::<usize>().count(), 9);
+        drop((before, removed, after));
+        assert_eq!(node_and_version_count(&list), (10, 10));
+    }
+
+    /// Runs `write` on one thread while others keep checking snapshots.
+    fn check_snapshots_during(
+        list: &List,
+        write: impl FnOnce() + Send,
+        check: impl Fn(Vec<(usize, usize)>) + Sync,
+    ) {
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut checked = 0;
+                    while !done.load(Ordering::Acquire) || checked == 0 {
+                        check(list.snapshot().iter().collect());
+                        checked += 1;
+                    }
+                });
+            }
+            write();
+            done.store(true, Ordering::Release);
+        });
+    }
+
+    #[test]
+    fn snapshot_of_sliding_window_is_contiguous() {
+        const WINDOW: usize = 64;
+        let list = new_list(0..WINDOW);
+        // Inserting the key above the window before removing the one below
+        // leaves WINDOW or WINDOW + 1 consecutive keys at every instant.
+        check_snapshots_during(
+            &list,
+            || {
+                for low in 0..20_000usize {
+                    list.put(low + WINDOW, low + WINDOW).unwrap();
+                    list.remove(low).unwrap();
+                }
+            },
+            |seen| {
+                assert!(seen.len() == WINDOW || seen.len() == WINDOW + 1);
+                let low = seen[0].0;
+                for (i, (key, value)) in seen.into_iter().enumerate() {
+                    assert_eq!((key, value), (low + i, low + i));
+                }
+            },
+        );
+        assert_eq!(list.len(), WINDOW);
+        assert_eq!(node_and_version_count(&list), (WINDOW, WINDOW));
+    }
+
+    #[test]
+    fn snapshot_of_sweeping_writer_is_one_cut() {
+        const KEYS: usize = 64;
+        let list = <List as Tree<usize, usize>>::new();
+        for key in 0..KEYS {
+            list.put(key, 0).unwrap();
+        }
+        // Round `r` sets every key to `r` in ascending order, so a snapshot
+        // sees the keys below some point at `r` and the rest at `r - 1`.
+        check_snapshots_during(
+            &list,
+            || {
+                for round in 1..500 {
+                    for key in 0..KEYS {
+                        list.put(key, round).unwrap();
+                    }
+                }
+            },
+            |seen| {
+                assert_eq!(seen.len(), KEYS);
+                let newest = seen[0].1;
+                let cut = seen.iter().take_while(|(_, v)| *v == newest).count();
+                assert!(seen[cut..].iter().all(|(_, v)| *v + 1 == newest));
+            },
+        );
+        assert_eq!(node_and_version_count(&list), (KEYS, KEYS));
+    }
+
+    /// Checks every live node sits on each level of its tower, in key order.
+    fn check_towers<P: SkipListParams<Key = usize>>(list: &LockFreeSkipList<P, usize>) {
+        let guard = &epoch::pin();
+        let mut bottom = Vec::new();
+        list.for_each_node(guard, |node| {
+            assert!(node.height <= P::MAX_LEVEL);
+            assert_eq!(node.unlinked.load(Ordering::Acquire), 0);
+            bottom.push((*node.key(), node.height));
+        });
+        for level in 0..P::MAX_LEVEL {
+            let mut linked = Vec::new();
+            let mut curr = list.head().next(level).load(Ordering::Acquire, guard);
+            while let Some(node) = unsafe { curr.as_ref() } {
+                assert_eq!(curr.tag(), 0);
+                linked.push(*node.key());
+                curr = node.next(level).load(Ordering::Acquire, guard);
+            }
+            let expected: Vec<_> = bottom
+                .iter()
+                .filter(|(_, height)| *height > level)
+                .map(|(key, _)| *key)
+                .collect();
+            assert_eq!(linked, expected, "level {level}");
+        }
+    }
+
+    fn matches_model<P: SkipListParams<Key = usize>>() {
+        let list = <LockFreeSkipList<P, usize> as Tree<usize, usize>>::new();
+        let mut model = BTreeMap::new();
+        for i in 0..3000usize {
+            let key = i * 7919 % 3000;
+            list.put(key, i).unwrap();
+            model.insert(key, i);
+            if i % 3 == 0 {
+                let key = i * 31 % 3000;
+                list.remove(key).unwrap();
+                model.remove(&key);
+            }
+        }
+        for key in 0..3001usize {
+            assert_eq!(
+                list.get(key).unwrap(),
+                model.get(&key).copied(),
+                "key {key}"
+            );
+        }
+        assert_eq!(list.len(), model.len());
+        for start in [0usize, 1, 1500, 2990, 3000] {
+            let expected: Vec<_> = model
+                .range(start..)
+                .take(100)
+                .map(|(k, v)| (*k, *v))
+                .collect();
+            assert_eq!(
+                list.scan(start, 100).unwrap(),
+                expected,
+                "scan from {start}"
+            );
+        }
+        check_towers(&list);
+        list.clear();
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.scan(0usize, 10).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn matches_model_at_any_tower_height() {
+        matches_model::<DefaultSkipListParams<usize>>();
+        matches_model::<Capped<1, 50>>();
+        matches_model::<Capped<4, 90>>();
+        matches_model::<Capped<LEVEL_LIMIT, 0>>();
+    }
+
+    #[test]
+    fn fixed_keys_sort_by_bytes() {
+        let list = <FixedKeySkipList<usize> as Tree<String, usize>>::new();
+        // Byte order, not length or numeric order.
+        let sorted = ["", "10", "9", "Z", "a", "ab", "b", "\u{e9}"];
+        for (i, key) in sorted.iter().enumerate().rev() {
+            list.put(key.to_string(), i).unwrap();
+        }
+        let expected: Vec<_> = sorted
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.to_string(), i))
+            .collect();
+        assert_eq!(list.scan(String::new(), 100).unwrap(), expected);
+        assert_eq!(
+            list.scan("aa".to_string(), 2).unwrap(),
+            expected[5..7].to_vec()
+        );
+        assert_eq!(list.get("9".to_string()).unwrap(), Some(2));
+        assert!(matches!(
+            list.put("x".repeat(17), 0),
+            Err(TreeError::KeyTooLong { .. })
+        ));
+        assert_eq!(list.len(), sorted.len());
+    }
+
+    #[test]
+    fn tower_building_races_with_removal() {
+        // Tall towers on few keys, so removals often land mid-build.
+        let list = <LockFreeSkipList<Capped<16, 75>, usize> as Tree<usize, usize>>::new();
+        let keys = 16;
+        thread::scope(|s| {
+            for id in 0..4 {
+                let list = &list;
+                s.spawn(move || {
+                    for round in 0..5000 {
+                        let key = (round * 7 + id * 3) % keys;
+                        if (round + id) % 2 == 0 {
+                            list.put(key, id).unwrap();
+                        } else {
+                            list.remove(key).unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        // Whatever a racing remove left behind must be unlinked by the next
+        // traversal, and the keys still present fully linked.
+        list.find(&keys, &epoch::pin());
+        check_towers(&list);
+        let present = (0..keys)
+            .filter(|key| list.get(*key).unwrap().is_some())
+            .count();
+        assert_eq!(list.len(), present);
+        list.clear();
+        list.find(&keys, &epoch::pin());
+        check_towers(&list);
+        assert_eq!(list.len(), 0);
     }
 }
@@ -0,0 +1,563 @@
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    hash::BuildHasher,
+    mem,
+    ptr::null_mut,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::error::Result;
+use crate::interfaces::{
+    Clear, Insert, KeyType, Len, MemoryUsage, PointRead, RangeScan, Remove, Tree, ValueType,
+};
+
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+
+struct Links<K, V> {
+    /// `None` only for the holder.
+    value: Option<V>,
+    children: [*mut Node<K, V>; 2],
+}
+
+struct Node<K, V> {
+    /// `None` for the holder, whose right child is the root.
+    key: Option<K>,
+    /// Max-heap order; the holder has `u64::MAX` so nothing rotates above it.
+    priority: u64,
+    links: RwLock<Links<K, V>>,
+}
+
+impl<K: Ord, V> Node<K, V> {
+    fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+
+    fn dir(&self, key: &K) -> usize {
+        if key < self.key() {
+            LEFT
+        } else {
+            RIGHT
+        }
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Links<K, V>> {
+        self.links.write().unwrap()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Links<K, V>> {
+        self.links.read().unwrap()
+    }
+}
+
+/// A node and its read guard, for scans.
+type ReadLocked<'a, K, V> = (&'a Node<K, V>, RwLockReadGuard<'a, Links<K, V>>);
+
+fn random_priority() -> u64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().hash_one(0u8) | 1);
+    }
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x.min(u64::MAX - 1)
+    })
+}
+
+/// Concurrent treap with a reader-writer lock per node.
+///
+/// Random priorities keep the expected depth logarithmic whatever the insert
+/// order. Every operation descends with lock coupling, taking the child's
+/// lock before releasing the parent's, so threads never overtake each other
+/// and a rotation, done holding the locks of the nodes it relinks, never
+/// moves a subtree someone is inside of. Inserts add a leaf and rotate it up,
+/// re-descending from the root for each rotation to keep locking top-down;
+/// removes rotate the node down to a leaf and cut it off. Scans keep the
+/// nodes they still have to return to read-locked, so they see a consistent
+/// range but hold back writers meanwhile.
+pub struct Treap<K, V> {
+    holder: Box<Node<K, V>>,
+}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for Treap<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Treap<K, V> {}
+
+impl<K: KeyType, V: ValueType> Treap<K, V> {
+    /// Rotates `node` above its parent if its priority is higher. Returns
+    /// whether it did, i.e. whether the node may have to go further up.
+    fn rotate_up(&self, key: &K, node: *mut Node<K, V>) -> bool {
+        let mut upper = self.holder.write();
+        let mut upper_dir = RIGHT;
+        let first = upper.children[RIGHT];
+        if first.is_null() || first == node {
+            return false;
+        }
+        let mut parent = unsafe { &*first };
+        let mut parent_links = parent.write();
+        loop {
+            if parent.key() == key {
+                // `node` was removed and the key inserted again.
+                return false;
+            }
+            let dir = parent.dir(key);
+            let child = parent_links.children[dir];
+            if child.is_null() {
+                return false;
+            }
+            let next = unsafe { &*child };
+            if child == node {
+                if next.priority <= parent.priority {
+                    return false;
+                }
+                let mut links = next.write();
+                parent_links.children[dir] = links.children[1 - dir];
+                links.children[1 - dir] = parent as *const _ as *mut _;
+                upper.children[upper_dir] = child;
+                return true;
+            }
+            let next_links = next.write();
+            upper = parent_links;
+            upper_dir = dir;
+            parent_links = next_links;
+            parent = next;
+        }
+    }
+
+    /// Calls `f` on every node below the holder. Holding the holder's read
+    /// lock keeps all writers out, since they all start by write-locking it.
+    fn for_each_node(&self, mut f: impl FnMut(&Node<K, V>, &Links<K, V>)) {
+        let holder = self.holder.read();
+        let mut pending = vec![holder.children[RIGHT]];
+        while let Some(node) = pending.pop() {
+            if node.is_null() {
+                continue;
+            }
+            let node = unsafe { &*node };
+            let links = node.read();
+            f(node, &links);
+            pending.extend(links.children);
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> Tree<K, V> for Treap<K, V> {
+    fn new() -> Self {
+        Treap {
+            holder: Box::new(Node {
+                key: None,
+                priority: u64::MAX,
+                links: RwLock::new(Links {
+                    value: None,
+                    children: [null_mut(); 2],
+                }),
+            }),
+        }
+    }
+
+    fn as_remove(&self) -> Option<&dyn Remove<K, V>> {
+        Some(self)
+    }
+
+    fn as_range_scan(&self) -> Option<&dyn RangeScan<K, V>> {
+        Some(self)
+    }
+}
+
+impl<K: KeyType, V: ValueType> PointRead<K, V> for Treap<K, V> {
+    fn get_with<R>(&self, key: K, f: impl FnOnce(&V) -> R) -> Result<Option<R>> {
+        let mut links = self.holder.read();
+        let mut child = links.children[RIGHT];
+        while !child.is_null() {
+            let node = unsafe { &*child };
+            let next_links = node.read();
+            if node.key() == &key {
+                return Ok(next_links.value.as_ref().map(f));
+            }
+            links = next_links;
+            child = links.children[node.dir(&key)];
+        }
+        Ok(None)
+    }
+}
+
+impl<K: KeyType, V: ValueType> Insert<K, V> for Treap<K, V> {
+    fn put(&self, key: K, value: V) -> Result<()> {
+        let mut parent: &Node<K, V> = &self.holder;
+        let mut links = parent.write();
+        let mut dir = RIGHT;
+        let node = loop {
+            let child = links.children[dir];
+            if child.is_null() {
+                let node = Box::into_raw(Box::new(Node {
+                    key: Some(key.clone()),
+                    priority: random_priority(),
+                    links: RwLock::new(Links {
+                        value: Some(value),
+                        children: [null_mut(); 2],
+                    }),
+                }));
+                links.children[dir] = node;
+                break node;
+            }
+            let next = unsafe { &*child };
+            let mut next_links = next.write();
+            if next.key() == &key {
+                next_links.value = Some(value);
+                return Ok(());
+            }
+            dir = next.dir(&key);
+            links = next_links;
+            parent = next;
+        };
+        let rotate = unsafe { (*node).priority } > parent.priority;
+        drop(links);
+        if rotate {
+            while self.rotate_up(&key, node) {}
+        }
+        Ok(())
+    }
+}
+
+impl<K: KeyType, V: ValueType> Remove<K, V> for Treap<K, V> {
+    fn remove(&self, key: K) -> Result<()> {
+        let mut parent_links = self.holder.write();
+        let mut dir = RIGHT;
+        let (node, mut links) = loop {
+            let child = parent_links.children[dir];
+            if child.is_null() {
+                return Ok(());
+            }
+            let next = unsafe { &*child };
+            let next_links = next.write();
+            if next.key() == &key {
+                break (child, next_links);
+            }
+            dir = next.dir(&key);
+            parent_links = next_links;
+        };
+
+        // Rotate the higher priority child above `node` until it has at most
+        // one child left, which then takes its place.
+        loop {
+            let [left, right] = links.children;
+            if left.is_null() || right.is_null() {
+                parent_links.children[dir] = if left.is_null() { right } else { left };
+                drop(links);
+                drop(parent_links);
+                drop(unsafe { Box::from_raw(node) });
+                return Ok(());
+            }
+            let up = if unsafe { (*left).priority > (*right).priority } {
+                LEFT
+            } else {
+                RIGHT
+            };
+            let child = links.children[up];
+            let mut child_links = unsafe { &*child }.write();
+            links.children[up] = child_links.children[1 - up];
+            child_links.children[1 - up] = node;
+            parent_links.children[dir] = child;
+            parent_links = child_links;
+            dir = 1 - up;
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> RangeScan<K, V> for Treap<K, V> {
+    fn scan(&self, start: K, n: usize) -> Result<Vec<(K, V)>> {
+        let mut result = Vec::new();
+        // Nodes not below `start` whose left subtree is being visited.
+        let mut stack: Vec<ReadLocked<K, V>> = Vec::new();
+        let mut holding = Some(self.holder.read());
+        let mut child = holding.as_ref().unwrap().children[RIGHT];
+        while !child.is_null() {
+            let node = unsafe { &*child };
+            let links = node.read();
+            holding = None;
+            if node.key() >= &start {
+                child = links.children[LEFT];
+                stack.push((node, links));
+            } else {
+                child = links.children[RIGHT];
+                holding = Some(links);
+            }
+        }
+        drop(holding);
+
+        while let Some((node, links)) = stack.pop() {
+            if result.len() >= n {
+                break;
+            }
+            result.push((node.key().clone(), links.value.clone().unwrap()));
+            let mut child = links.children[RIGHT];
+            let mut holding = Some(links);
+            while !child.is_null() {
+                let next = unsafe { &*child };
+                let next_links = next.read();
+                holding = None;
+                child = next_links.children[LEFT];
+                stack.push((next, next_links));
+            }
+            drop(holding);
+        }
+        Ok(result)
+    }
+}
+
+impl<K: KeyType, V: ValueType> Len for Treap<K, V> {
+    fn len(&self) -> usize {
+        let mut len = 0;
+        self.for_each_node(|_, _| len += 1);
+        len
+    }
+}
+
+impl<K: KeyType, V: ValueType> Clear for Treap<K, V> {
+    /// Removes the keys one by one: a thread may still be inside the tree
+    /// after passing the holder, so the nodes cannot just be cut off.
+    fn clear(&self) {
+        let mut keys = Vec::new();
+        self.for_each_node(|node, _| keys.push(node.key().clone()));
+        for key in keys {
+            self.remove(key).unwrap();
+        }
+    }
+}
+
+impl<K: KeyType, V: ValueType> MemoryUsage for Treap<K, V> {
+    fn memory_usage(&self) -> usize {
+        let mut bytes = mem::size_of::<Node<K, V>>();
+        self.for_each_node(|_, _| bytes += mem::size_of::<Node<K, V>>());
+        bytes
+    }
+}
+
+impl<K, V> Drop for Treap<K, V> {
+    fn drop(&mut self) {
+        let mut pending = vec![self.holder.links.get_mut().unwrap().children[RIGHT]];
+        while let Some(node) = pending.pop() {
+            if node.is_null() {
+                continue;
+            }
+            let mut node = unsafe { Box::from_raw(node) };
+            pending.extend(node.links.get_mut().unwrap().children);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::Arc, thread};
+
+    fn new_treap() -> Treap<usize, usize> {
+        <Treap<usize, usize> as Tree<usize, usize>>::new()
+    }
+
+    fn node(
+        key: usize,
+        priority: u64,
+        children: [*mut Node<usize, usize>; 2],
+    ) -> *mut Node<usize, usize> {
+        Box::into_raw(Box::new(Node {
+            key: Some(key),
+            priority,
+            links: RwLock::new(Links {
+                value: Some(key),
+                children,
+            }),
+        }))
+    }
+
+    fn set_root(treap: &Treap<usize, usize>, root: *mut Node<usize, usize>) {
+        treap.holder.write().children[RIGHT] = root;
+    }
+
+    /// The tree as nested `(left key right)`, leaves as their key alone.
+    fn shape(treap: &Treap<usize, usize>) -> String {
+        fn walk(node: *mut Node<usize, usize>) -> String {
+            let Some(node) = (unsafe { node.as_ref() }) else {
+                return ".".to_string();
+            };
+            let [left, right] = node.read().children;
+            if left.is_null() && right.is_null() {
+                return node.key().to_string();
+            }
+            format!("({} {} {})", walk(left), node.key(), walk(right))
+        }
+        walk(treap.holder.read().children[RIGHT])
+    }
+
+    /// Checks key and heap order below `node` and returns its depth.
+    fn check_treap(node: *mut Node<usize, usize>, low: usize, high: usize, priority: u64) -> usize {
+        let Some(node) = (unsafe { node.as_ref() }) else {
+            return 0;
+        };
+        let key = *node.key();
+        assert!(low <= key && key < high, "{key} outside [{low}, {high})");
+        assert!(node.priority <= priority, "heap order broken at {key}");
+        let [left, right] = node.read().children;
+        let depth = check_treap(left, low, key, node.priority).max(check_treap(
+            right,
+            key + 1,
+            high,
+            node.priority,
+        ));
+        depth + 1
+    }
+
+    fn depth(treap: &Treap<usize, usize>) -> usize {
+        check_treap(treap.holder.read().children[RIGHT], 0, usize::MAX, u64::MAX)
+    }
+
+    #[test]
+    fn rotate_up_stops_below_higher_priorities() {
+        for (priority, calls, expected) in [
+            (95, 3, "(((. 20 30) 40 .) 50 .)"),
+            (200, 4, "((. 20 30) 40 50)"),
+        ] {
+            let treap = new_treap();
+            let leaf = node(40, priority, [null_mut(); 2]);
+            let middle = node(30, 80, [null_mut(), leaf]);
+            let left = node(20, 90, [null_mut(), middle]);
+            set_root(&treap, node(50, 100, [left, null_mut()]));
+            for call in 1..=calls {
+                assert_eq!(treap.rotate_up(&40, leaf), call < calls, "call {call}");
+            }
+            assert_eq!(shape(&treap), expected);
+            depth(&treap);
+        }
+    }
+
+    #[test]
+    fn rotate_up_gives_up_on_a_reinserted_key() {
+        let treap = new_treap();
+        set_root(
+            &treap,
+            node(50, 100, [node(40, 90, [null_mut(); 2]), null_mut()]),
+        );
+        // The node that was inserted, since removed, its key now on another.
+        let stale = node(40, 95, [null_mut(); 2]);
+        assert!(!treap.rotate_up(&40, stale));
+        assert_eq!(shape(&treap), "(40 50 .)");
+        drop(unsafe { Box::from_raw(stale) });
+    }
+
+    #[test]
+    fn remove_rotates_down_past_the_higher_child() {
+        let treap = new_treap();
+        let left = node(20, 90, [null_mut(), node(30, 80, [null_mut(); 2])]);
+        let right = node(70, 85, [null_mut(); 2]);
+        set_root(&treap, node(50, 100, [left, right]));
+        treap.remove(50).unwrap();
+        assert_eq!(shape(&treap), "(. 20 (30 70 .))");
+        assert_eq!(treap.get(50).unwrap(), None);
+        assert_eq!(treap.len(), 3);
+        depth(&treap);
+    }
+
+    #[test]
+    fn ascending_inserts_stay_shallow() {
+        let treap = new_treap();
+        for key in 0..10_000usize {
+            treap.put(key, key).unwrap();
+        }
+        // Expected around 2.99 ln n, about 28; a list would be 10000 deep.
+        let depth = depth(&treap);
+        assert!(depth <= 60, "depth {depth} for 10000 ascending keys");
+    }
+
+    #[test]
+    fn matches_model() {
+        let treap = new_treap();
+        let mut model = BTreeMap::new();
+        for i in 0..4000usize {
+            let key = i * 7919 % 4000;
+            treap.put(key, i).unwrap();
+            model.insert(key, i);
+            if i % 3 == 0 {
+                let key = i * 31 % 4000;
+                treap.remove(key).unwrap();
+                model.remove(&key);
+            }
+        }
+        depth(&treap);
+        for key in 0..4001usize {
+            assert_eq!(
+                treap.get(key).unwrap(),
+                model.get(&key).copied(),
+                "key {key}"
+            );
+        }
+        assert_eq!(treap.len(), model.len());
+        for start in [0usize, 1, 1999, 3990, 4000] {
+            let expected: Vec<_> = model
+                .range(start..)
+                .take(100)
+                .map(|(k, v)| (*k, *v))
+                .collect();
+            assert_eq!(
+                treap.scan(start, 100).unwrap(),
+                expected,
+                "scan from {start}"
+            );
+        }
+        treap.clear();
+        assert_eq!(treap.len(), 0);
+    }
+
+    #[test]
+    fn scans_see_stable_keys_while_others_churn() {
+        // Even keys stay put, odd ones come and go under the scans.
+        let treap = Arc::new(new_treap());
+        let keys = 512;
+        for key in (0..keys).step_by(2) {
+            treap.put(key, key).unwrap();
+        }
+        let writers: Vec<_> = (0..2)
+            .map(|id| {
+                let treap = treap.clone();
+                thread::spawn(move || {
+                    for round in 0..20_000 {
+                        let key = ((round * 7 + id * 64) % keys) | 1;
+                        if round % 2 == 0 {
+                            treap.put(key, key).unwrap();
+                        } else {
+                            treap.remove(key).unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        let scanners: Vec<_> = (0..2)
+            .map(|_| {
+                let treap = treap.clone();
+                thread::spawn(move || {
+                    for _ in 0..200 {
+                        let seen = treap.scan(100, keys).unwrap();
+                        assert!(seen.windows(2).all(|pair| pair[0].0 < pair[1].0));
+                        assert!(seen.iter().all(|(key, value)| key == value));
+                        let even: Vec<_> = seen
+                            .iter()
+                            .map(|(key, _)| *key)
+                            .filter(|key| key % 2 == 0)
+                            .collect();
+                        assert_eq!(even, (100..keys).step_by(2).collect::<Vec<_>>());
+                    }
+                })
+            })
+            .collect();
+        for handle in writers.into_iter().chain(scanners) {
+            handle.join().unwrap();
+        }
+        depth(&treap);
+        let present = (0..keys)
+            .filter(|key| treap.get(*key).unwrap().is_some())
+            .count();
+        assert_eq!(treap.len(), present);
+    }
+}
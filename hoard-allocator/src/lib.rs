@@ -4,9 +4,14 @@ use std::cmp;
 use std::ffi::{c_int, c_void};
 use std::ptr;
 
+mod stats;
+
+pub use stats::{stats, HoardStats};
+
 #[link(name = "hoard", kind = "static")]
 extern "C" {
     fn malloc(size: usize) -> *mut c_void;
+    fn calloc(nmemb: usize, size: usize) -> *mut c_void;
     fn posix_memalign(ptr: *mut *mut c_void, align: usize, size: usize) -> c_int;
     fn free(ptr: *mut c_void);
     fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
@@ -29,7 +34,7 @@ pub struct Hoard;
 
 unsafe impl GlobalAlloc for Hoard {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        if layout.align() <= MIN_ALIGN {
+        let ptr = if layout.align() <= MIN_ALIGN {
             malloc(layout.size()) as *mut u8
         } else {
             let mut out = std::ptr::null_mut();
@@ -39,21 +44,145 @@ unsafe impl GlobalAlloc for Hoard {
             } else {
                 out as *mut u8
             }
+        };
+        if !ptr.is_null() {
+            stats::record_alloc(layout.size());
         }
+        ptr
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if layout.align() <= MIN_ALIGN {
+            let ptr = calloc(1, layout.size()) as *mut u8;
+            if !ptr.is_null() {
+                stats::record_alloc(layout.size());
+            }
+            ptr
+        } else {
+            let ptr = self.alloc(layout);
+            if !ptr.is_null() {
+                ptr::write_bytes(ptr, 0, layout.size());
+            }
+            ptr
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        stats::record_free(layout.size());
         free(ptr as *mut c_void)
     }
 
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
         if layout.align() <= MIN_ALIGN {
-            realloc(ptr as *mut c_void, new_size) as *mut u8
+            let new_ptr = realloc(ptr as *mut c_void, new_size) as *mut u8;
+            if !new_ptr.is_null() {
+                stats::record_free(layout.size());
+                stats::record_alloc(new_size);
+            }
+            new_ptr
         } else {
-            let new_ptr = self.alloc(layout);
-            ptr::copy(ptr, new_ptr, cmp::min(layout.size(), new_size));
-            self.dealloc(ptr, layout);
+            // No aligned realloc in the C API: allocate, copy and free. On
+            // failure the original block must stay valid.
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            let new_ptr = self.alloc(new_layout);
+            if !new_ptr.is_null() {
+                ptr::copy_nonoverlapping(ptr, new_ptr, cmp::min(layout.size(), new_size));
+                self.dealloc(ptr, layout);
+            }
             new_ptr
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn fill(ptr: *mut u8, size: usize) {
+        for i in 0..size {
+            *ptr.add(i) = (i % 251) as u8;
+        }
+    }
+
+    unsafe fn check(ptr: *const u8, size: usize) {
+        for i in 0..size {
+            assert_eq!(*ptr.add(i), (i % 251) as u8, "byte {} corrupted", i);
+        }
+    }
+
+    unsafe fn alloc_realloc_roundtrip(size: usize, align: usize) {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let ptr = Hoard.alloc(layout);
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % align, 0);
+        fill(ptr, size);
+
+        let grown = Hoard.realloc(ptr, layout, size * 4);
+        assert!(!grown.is_null());
+        assert_eq!(grown as usize % align, 0);
+        check(grown, size);
+        fill(grown, size * 4);
+
+        let grown_layout = Layout::from_size_align(size * 4, align).unwrap();
+        let shrunk = Hoard.realloc(grown, grown_layout, size / 2);
+        assert!(!shrunk.is_null());
+        assert_eq!(shrunk as usize % align, 0);
+        check(shrunk, size / 2);
+
+        Hoard.dealloc(shrunk, Layout::from_size_align(size / 2, align).unwrap());
+    }
+
+    #[test]
+    fn small_allocations() {
+        unsafe { alloc_realloc_roundtrip(24, 8) }
+    }
+
+    #[test]
+    fn large_allocations() {
+        unsafe { alloc_realloc_roundtrip(4 << 20, MIN_ALIGN) }
+    }
+
+    #[test]
+    fn over_aligned_allocations() {
+        unsafe {
+            alloc_realloc_roundtrip(100, 64);
+            alloc_realloc_roundtrip(5000, 4096);
+        }
+    }
+
+    #[test]
+    fn zeroed_allocations() {
+        for (size, align) in [(64, 8), (1 << 20, MIN_ALIGN), (256, 512)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            unsafe {
+                let ptr = Hoard.alloc(layout);
+                fill(ptr, size);
+                Hoard.dealloc(ptr, layout);
+
+                let ptr = Hoard.alloc_zeroed(layout);
+                assert!(!ptr.is_null());
+                assert_eq!(ptr as usize % align, 0);
+                assert!((0..size).all(|i| *ptr.add(i) == 0));
+                Hoard.dealloc(ptr, layout);
+            }
+        }
+    }
+
+    #[test]
+    fn failed_allocation_returns_null() {
+        let layout = Layout::from_size_align(isize::MAX as usize - 4096, 4096).unwrap();
+        unsafe {
+            assert!(Hoard.alloc(layout).is_null());
+
+            let small = Layout::from_size_align(64, 4096).unwrap();
+            let ptr = Hoard.alloc(small);
+            fill(ptr, 64);
+            assert!(Hoard
+                .realloc(ptr, small, isize::MAX as usize - 4096)
+                .is_null());
+            // The original block is untouched after a failed realloc.
+            check(ptr, 64);
+            Hoard.dealloc(ptr, small);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const NUM_STRIPES: usize = 64;
+
+/// Per-thread stripe of heap counters, padded to a cache line so that
+/// threads do not contend on the same line while counting.
+#[repr(align(64))]
+struct Stripe {
+    allocated_bytes: AtomicUsize,
+    freed_bytes: AtomicUsize,
+    allocations: AtomicUsize,
+    frees: AtomicUsize,
+}
+
+impl Stripe {
+    const fn new() -> Self {
+        Stripe {
+            allocated_bytes: AtomicUsize::new(0),
+            freed_bytes: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+        }
+    }
+}
+
+static STRIPES: [Stripe; NUM_STRIPES] = [const { Stripe::new() }; NUM_STRIPES];
+static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // 0 means "not assigned yet", otherwise stripe index + 1.
+    static STRIPE_ID: Cell<usize> = const { Cell::new(0) };
+}
+
+#[inline(always)]
+fn stripe() -> &'static Stripe {
+    let id = STRIPE_ID
+        .try_with(|id| {
+            if id.get() == 0 {
+                id.set(NEXT_STRIPE.fetch_add(1, Ordering::Relaxed) % NUM_STRIPES + 1);
+            }
+            id.get()
+        })
+        .unwrap_or(1);
+    &STRIPES[id - 1]
+}
+
+#[inline(always)]
+pub(crate) fn record_alloc(size: usize) {
+    let s = stripe();
+    s.allocated_bytes.fetch_add(size, Ordering::Relaxed);
+    s.allocations.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub(crate) fn record_free(size: usize) {
+    let s = stripe();
+    s.freed_bytes.fetch_add(size, Ordering::Relaxed);
+    s.frees.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Heap counters collected by the `Hoard` wrapper.
+///
+/// Hoard does not export its internal per-heap statistics, so the wrapper
+/// counts requested bytes itself. Blocks freed by a different thread than the
+/// one that allocated them are counted on the freeing thread; the totals are
+/// exact, the per-thread split is not exposed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HoardStats {
+    pub allocated_bytes: usize,
+    pub freed_bytes: usize,
+    pub allocations: usize,
+    pub frees: usize,
+}
+
+impl HoardStats {
+    /// Bytes requested by live allocations.
+    pub fn live_bytes(&self) -> usize {
+        self.allocated_bytes.saturating_sub(self.freed_bytes)
+    }
+
+    /// Number of live allocations.
+    pub fn live_allocations(&self) -> usize {
+        self.allocations.saturating_sub(self.frees)
+    }
+}
+
+/// Sums the counters of all threads.
+pub fn stats() -> HoardStats {
+    STRIPES
+        .iter()
+        .fold(HoardStats::default(), |acc, s| HoardStats {
+            allocated_bytes: acc.allocated_bytes + s.allocated_bytes.load(Ordering::Relaxed),
+            freed_bytes: acc.freed_bytes + s.freed_bytes.load(Ordering::Relaxed),
+            allocations: acc.allocations + s.allocations.load(Ordering::Relaxed),
+            frees: acc.frees + s.frees.load(Ordering::Relaxed),
+        })
+}